@@ -53,4 +53,30 @@ pub struct Args {
     /// Hostname for CORS
     #[clap(long, value_parser)]
     pub cors_host: Option<String>,
+
+    /// Import Markdown posts from a zip file or directory, then exit
+    #[clap(long, value_parser)]
+    pub import: Option<String>,
+
+    /// Import posts from a WordPress WXR export file, then exit
+    #[clap(long, value_parser)]
+    pub import_wordpress: Option<String>,
+
+    /// WordPress uploads directory, used with --import-wordpress
+    #[clap(long, value_parser)]
+    pub uploads: Option<String>,
+
+    /// Create a backup archive in ./backup, then exit
+    #[clap(long, value_parser)]
+    #[serde(default)]
+    pub backup: bool,
+
+    /// Restore from a backup archive (the server must be stopped), then exit
+    #[clap(long, value_parser)]
+    pub restore: Option<String>,
+
+    /// Only print the import report or verify the backup, don't save anything
+    #[clap(long, value_parser)]
+    #[serde(default)]
+    pub dry_run: bool,
 }
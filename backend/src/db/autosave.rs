@@ -0,0 +1,70 @@
+use blog_common::{
+    dto::post::{AutosaveDraft, PostAutosave},
+    result::Error,
+    util::time,
+};
+use sqlx::Row;
+
+use crate::util::{diff, result::Result};
+
+// 返回保存的时间，和已保存的博客内容相同时不保留草稿，返回 None
+pub async fn save(draft: &PostAutosave) -> Result<Option<u64>> {
+    let row = sqlx::query("SELECT title, markdown_content FROM posts WHERE id = ?")
+        .bind(draft.id)
+        .fetch_optional(super::get_sqlite())
+        .await?;
+    let row = match row {
+        Some(r) => r,
+        None => return Err(Error::CannotFoundPost.into()),
+    };
+    let title: String = row.get(0);
+    let content: String = row.get(1);
+    if title == draft.title && content == draft.content {
+        discard(draft.id).await?;
+        return Ok(None);
+    }
+    let now = time::unix_epoch_sec();
+    sqlx::query("REPLACE INTO post_autosaves(post_id, title, markdown_content, saved_at) VALUES(?, ?, ?, ?)")
+        .bind(draft.id)
+        .bind(&draft.title)
+        .bind(&draft.content)
+        .bind(now as i64)
+        .execute(super::get_sqlite())
+        .await?;
+    Ok(Some(now))
+}
+
+// 只返回比博客最后一次保存更新的草稿
+pub async fn get(id: i64) -> Result<Option<AutosaveDraft>> {
+    let row = sqlx::query("SELECT a.title, a.markdown_content, a.saved_at, p.title, p.markdown_content, IFNULL(p.updated_at, p.created_at) FROM post_autosaves a INNER JOIN posts p ON a.post_id = p.id WHERE a.post_id = ?")
+        .bind(id)
+        .fetch_optional(super::get_sqlite())
+        .await?;
+    let row = match row {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let saved_at: i64 = row.get(2);
+    let updated_at: i64 = row.get(5);
+    if saved_at < updated_at {
+        discard(id).await?;
+        return Ok(None);
+    }
+    let content: String = row.get(1);
+    let saved_content: String = row.get(4);
+    Ok(Some(AutosaveDraft {
+        title: row.get(0),
+        diff: diff::line_diff(&saved_content, &content),
+        content,
+        saved_at: saved_at as u64,
+        saved_title: row.get(3),
+    }))
+}
+
+pub async fn discard(id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM post_autosaves WHERE post_id = ?")
+        .bind(id)
+        .execute(super::get_sqlite())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, vec::Vec};
+
+use blog_common::{
+    dto::category::{Category as CategoryDto, CategoryData, CategoryNode},
+    result::Error,
+    util::time,
+};
+use sqlx::{Row, Sqlite};
+
+use crate::{
+    db::{model::Category, post, DATA_SOURCE},
+    util::result::Result,
+};
+
+// 查询某个分类以及它所有子分类的 id，使用时需要绑定一个分类 id
+pub(crate) const DESCENDANT_IDS_SQL: &'static str = "WITH RECURSIVE descendants(id) AS (SELECT ? UNION ALL SELECT c.id FROM categories c INNER JOIN descendants d ON c.parent_id = d.id) SELECT id FROM descendants";
+
+fn build_nodes(parent_id: Option<i64>, categories: &Vec<Category>, amounts: &HashMap<i64, u32>) -> Vec<CategoryNode> {
+    categories
+        .iter()
+        .filter(|c| c.parent_id == parent_id)
+        .map(|c| {
+            let children = build_nodes(Some(c.id), categories, amounts);
+            let amount = amounts.get(&c.id).copied().unwrap_or(0) + children.iter().map(|n| n.amount).sum::<u32>();
+            CategoryNode {
+                id: c.id,
+                name: c.name.clone(),
+                parent_id: c.parent_id,
+                amount,
+                children,
+            }
+        })
+        .collect()
+}
+
+pub async fn tree() -> Result<Vec<CategoryNode>> {
+    let categories = sqlx::query_as::<Sqlite, Category>("SELECT id,name,parent_id FROM categories ORDER BY name ASC")
+        .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    // 只统计公开的博客，不在列表里的博客也不算
+    let sql = format!(
+        "SELECT category_id, COUNT(id) FROM posts \
+         WHERE category_id IS NOT NULL{}AND visibility<>'unlisted' GROUP BY category_id",
+        post::UNPROTECTED_CONDITION
+    );
+    let rows = sqlx::query(&sql)
+        .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    let mut amounts: HashMap<i64, u32> = HashMap::with_capacity(rows.len());
+    for row in rows {
+        let amount: i64 = row.get(1);
+        amounts.insert(row.get(0), amount as u32);
+    }
+    Ok(build_nodes(None, &categories, &amounts))
+}
+
+pub async fn exists(id: i64) -> Result<bool> {
+    let r = sqlx::query("SELECT id FROM categories WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    Ok(r.is_some())
+}
+
+pub async fn new_category(data: &CategoryData) -> Result<i64> {
+    if data.name.trim().is_empty() {
+        return Err(Error::BusinessException(String::from("分类名称不能为空/Category name must not be empty.")).into());
+    }
+    if let Some(parent_id) = data.parent_id {
+        if !exists(parent_id).await? {
+            return Err(Error::CategoryNotFound.into());
+        }
+    }
+    // 顶级分类的 parent_id 是 NULL，唯一约束不起作用，这里统一检查
+    let duplicate = sqlx::query("SELECT id FROM categories WHERE parent_id IS ? AND name = ?")
+        .bind(data.parent_id)
+        .bind(data.name.trim())
+        .fetch_optional(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    if duplicate.is_some() {
+        return Err(Error::BusinessException(String::from("分类已存在/Category already exists.")).into());
+    }
+    let id = sqlx::query("INSERT INTO categories(name, parent_id, created_at)VALUES(?,?,?)")
+        .bind(data.name.trim())
+        .bind(data.parent_id)
+        .bind(time::unix_epoch_sec() as i64)
+        .execute(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+pub async fn delete(id: i64) -> Result<()> {
+    let category = sqlx::query_as::<Sqlite, Category>("SELECT id,name,parent_id FROM categories WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    if category.is_none() {
+        return Err(Error::CategoryNotFound.into());
+    }
+    let category = category.unwrap();
+
+    let mut transaction = DATA_SOURCE.get().unwrap().sqlite.begin().await?;
+    // 子分类挂到上一级分类下，博客则归到上一级分类
+    sqlx::query("UPDATE categories SET parent_id=?, updated_at=? WHERE parent_id=?")
+        .bind(category.parent_id)
+        .bind(time::unix_epoch_sec() as i64)
+        .bind(id)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query("UPDATE posts SET category_id=? WHERE category_id=?")
+        .bind(category.parent_id)
+        .bind(id)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query("DELETE FROM categories WHERE id=?")
+        .bind(id)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn breadcrumb(id: i64) -> Result<Vec<CategoryDto>> {
+    let categories = sqlx::query_as::<Sqlite, Category>(
+        "WITH RECURSIVE path(id, name, parent_id, depth) AS (SELECT id,name,parent_id,0 FROM categories WHERE id = ? UNION ALL SELECT c.id,c.name,c.parent_id,p.depth+1 FROM categories c INNER JOIN path p ON c.id = p.parent_id) SELECT id,name,parent_id FROM path ORDER BY depth DESC",
+    )
+    .bind(id)
+    .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
+    .await?;
+    Ok(categories
+        .into_iter()
+        .map(|c| CategoryDto { id: c.id, name: c.name })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::post::Visibility;
+
+    use super::*;
+    use crate::util::{common, testing};
+
+    fn find(nodes: &[CategoryNode], id: i64) -> Option<&CategoryNode> {
+        nodes.iter().find_map(|n| if n.id == id { Some(n) } else { find(&n.children, id) })
+    }
+
+    #[test]
+    fn count_public_posts_only() {
+        testing::block_on(async {
+            let parent_id = new_category(&CategoryData {
+                name: common::simple_uuid(),
+                parent_id: None,
+            })
+            .await
+            .unwrap();
+            let child_id = new_category(&CategoryData {
+                name: String::from("Child"),
+                parent_id: Some(parent_id),
+            })
+            .await
+            .unwrap();
+            testing::new_post("Public", Some(child_id), Visibility::Public).await;
+            testing::new_post("Unlisted", Some(child_id), Visibility::Unlisted).await;
+            testing::new_post("Password", Some(child_id), Visibility::Password).await;
+            testing::new_post("Private", Some(parent_id), Visibility::Private).await;
+
+            let nodes = tree().await.unwrap();
+            assert_eq!(1, find(&nodes, child_id).unwrap().amount);
+            assert_eq!(1, find(&nodes, parent_id).unwrap().amount);
+        });
+    }
+
+    #[test]
+    fn reject_duplicate_names_under_one_parent() {
+        testing::block_on(async {
+            let name = common::simple_uuid();
+            let data = |name: &str, parent_id| CategoryData {
+                name: String::from(name),
+                parent_id,
+            };
+            let root = new_category(&data(&name, None)).await.unwrap();
+            assert!(new_category(&data(&name, None)).await.is_err());
+
+            new_category(&data("Notes", Some(root))).await.unwrap();
+            assert!(new_category(&data(" Notes ", Some(root))).await.is_err());
+            // 不同的上级分类下可以同名
+            let other = new_category(&data(&common::simple_uuid(), None)).await.unwrap();
+            assert!(new_category(&data("Notes", Some(other))).await.is_ok());
+        });
+    }
+}
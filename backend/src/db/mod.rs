@@ -1,21 +1,26 @@
 use core::time::Duration;
 use std::{
+    ffi::CStr,
     marker::{Send, Unpin},
+    os::raw::c_int,
     path::Path,
 };
 
 use blog_common::result::Error;
 use futures::StreamExt;
+use libsqlite3_sys as ffi;
 use once_cell::sync::OnceCell;
 use serde::Serialize;
 use sqlx::{
     pool::PoolOptions,
-    sqlite::SqliteRow,
-    Sqlite,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteRow},
+    ConnectOptions, Connection, Row, Sqlite,
 };
 use tokio::fs::OpenOptions;
-use crate::util::result::Result;
+use crate::util::result::{ErrorWrapper, Result};
 
+pub(crate) mod autosave;
+pub(crate) mod category;
 pub(crate) mod management;
 pub mod model;
 pub(crate) mod post;
@@ -26,6 +31,17 @@ type SqliteConnPool = sqlx::Pool<Sqlite>;
 
 static DATA_SOURCE: OnceCell<DataSource> = OnceCell::new();
 
+// 每次表结构有变动，就在这里追加一个升级脚本，同时更新 ddl.sql
+// Append an upgrade script here whenever the schema changes, and keep ddl.sql in sync
+const UPGRADES: &[&str] = &[
+    include_str!("../resource/sql/upgrade/v1.sql"),
+    include_str!("../resource/sql/upgrade/v2.sql"),
+    include_str!("../resource/sql/upgrade/v3.sql"),
+    include_str!("../resource/sql/upgrade/v4.sql"),
+    include_str!("../resource/sql/upgrade/v5.sql"),
+    include_str!("../resource/sql/upgrade/v6.sql"),
+];
+
 // pub trait SqliteParam = for<'q> Encode<'q, Sqlite> + Type<Sqlite>;
 
 pub enum SqlParam {
@@ -96,6 +112,9 @@ pub async fn init_datasource() {
         // if let Err(e) = sqlx::query(dml).execute(&pool).await {
         //     panic!("{:?}", e);
         // }
+        set_schema_version(&pool, UPGRADES.len()).await;
+    } else {
+        upgrade(&pool).await;
     }
 
     let datasource = DataSource {
@@ -123,6 +142,92 @@ pub async fn init_datasource() {
     */
 }
 
+async fn set_schema_version(pool: &SqliteConnPool, version: usize) {
+    let sql = format!("PRAGMA user_version = {}", version);
+    if let Err(e) = sqlx::query(&sql).execute(pool).await {
+        panic!("{:?}", e);
+    }
+}
+
+async fn upgrade(pool: &SqliteConnPool) {
+    let row = sqlx::query("PRAGMA user_version")
+        .fetch_one(pool)
+        .await
+        .expect("Read schema version failed.");
+    let version: i64 = row.get(0);
+    for (idx, sql) in UPGRADES.iter().enumerate().skip(version as usize) {
+        println!("Upgrading database to version {}", idx + 1);
+        let mut stream = sqlx::query(sql).execute_many(pool).await;
+        while let Some(res) = stream.next().await {
+            if let Err(e) = res {
+                panic!("{:?}", e);
+            }
+        }
+        set_schema_version(pool, idx + 1).await;
+    }
+}
+
+pub fn latest_schema_version() -> usize {
+    UPGRADES.len()
+}
+
+pub async fn schema_version() -> Result<i64> {
+    let row = sqlx::query("PRAGMA user_version").fetch_one(get_sqlite()).await?;
+    Ok(row.get(0))
+}
+
+// 用 SQLite 的在线备份接口把数据库复制到 path，复制期间其它连接可以照常读写
+pub async fn snapshot(path: &Path) -> Result<()> {
+    let mut source = get_sqlite().acquire().await?;
+    let mut dest = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Delete)
+        .connect()
+        .await?;
+    {
+        let mut source_handle = source.lock_handle().await?;
+        let mut dest_handle = dest.lock_handle().await?;
+        let source_db = source_handle.as_raw_handle().as_ptr();
+        let dest_db = dest_handle.as_raw_handle().as_ptr();
+        // 复制过程是阻塞的，不占用异步运行时的线程
+        tokio::task::block_in_place(|| unsafe { backup_database(source_db, dest_db) })?;
+    }
+    dest.close().await?;
+    Ok(())
+}
+
+// 每次复制 BACKUP_PAGES_PER_STEP 页，中间让出数据库锁；数据库忙时等一会儿再继续
+const BACKUP_PAGES_PER_STEP: c_int = 256;
+
+unsafe fn backup_database(source: *mut ffi::sqlite3, dest: *mut ffi::sqlite3) -> Result<()> {
+    let main = c"main".as_ptr();
+    let backup = ffi::sqlite3_backup_init(dest, main, source, main);
+    if backup.is_null() {
+        return Err(backup_error(ffi::sqlite3_errcode(dest)));
+    }
+    let mut rc = ffi::SQLITE_OK;
+    while rc == ffi::SQLITE_OK || rc == ffi::SQLITE_BUSY || rc == ffi::SQLITE_LOCKED {
+        rc = ffi::sqlite3_backup_step(backup, BACKUP_PAGES_PER_STEP);
+        if rc == ffi::SQLITE_BUSY || rc == ffi::SQLITE_LOCKED {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    let finish = ffi::sqlite3_backup_finish(backup);
+    if rc != ffi::SQLITE_DONE {
+        return Err(backup_error(rc));
+    }
+    if finish != ffi::SQLITE_OK {
+        return Err(backup_error(finish));
+    }
+    Ok(())
+}
+
+fn backup_error(code: c_int) -> ErrorWrapper {
+    let message = unsafe { CStr::from_ptr(ffi::sqlite3_errstr(code)) };
+    Error::BusinessException(format!("数据库备份失败/Database backup failed: {}", message.to_string_lossy())).into()
+}
+
 pub async fn shutdown() {
     let ds = DATA_SOURCE.get().unwrap();
     ds.sqlite.close().await;
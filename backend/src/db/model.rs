@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use blog_common::dto::{post::PostDetail, user::UserInfo};
+use blog_common::dto::{
+    post::{PostDetail, Visibility},
+    user::UserInfo,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
 pub struct User {
@@ -40,6 +43,13 @@ pub struct Post {
     pub rendered_content: String,
     pub created_at: i64,
     pub updated_at: Option<i64>,
+    pub category_id: Option<i64>,
+    pub pinned: bool,
+    pub featured: bool,
+    pub language: String,
+    // 互为翻译的博客使用同一个分组，分组 id 是最早那篇博客的 id
+    pub translation_group: Option<i64>,
+    pub visibility: String,
 }
 
 impl Into<PostDetail> for &Post {
@@ -50,6 +60,16 @@ impl Into<PostDetail> for &Post {
             title_image: self.title_image.clone(),
             content: self.rendered_content.clone(),
             tags: None,
+            category_id: self.category_id,
+            breadcrumb: None,
+            toc: None,
+            related: None,
+            pinned: self.pinned,
+            featured: self.featured,
+            language: self.language.clone(),
+            translations: None,
+            visibility: Visibility::from_str(&self.visibility),
+            locked: false,
             created_at: self.created_at as u64,
             updated_at: self.updated_at.map(|t| t as u64),
             editable: false,
@@ -63,6 +83,13 @@ pub struct Tag {
     pub name: String,
 }
 
+#[derive(Deserialize, Serialize, sqlx::FromRow)]
+pub struct Category {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
+
 #[derive(Deserialize, Serialize, sqlx::FromRow)]
 pub struct TagUsage {
     pub id: i64,
@@ -1,22 +1,30 @@
+use std::collections::HashMap;
+
 use blog_common::{
     dto::{
-        post::{PostData, PostDetail},
+        archive::{ArchiveMonth, ArchiveYear},
+        post::{PostData, PostDetail, TocItem, Translation, Visibility},
         PaginationData,
     },
     result::Error,
     util::time,
     val,
 };
-use comrak::{markdown_to_html, ComrakOptions};
-use sqlx::{Row, Sqlite};
+use sqlx::{
+    query::{Query, QueryAs},
+    sqlite::SqliteArguments,
+    Row, Sqlite,
+};
 
 use crate::{
     db::{
+        autosave, category,
         model::{Post, Tag},
         tag,
     },
+    service::{markdown, related, status},
     util::{
-        common,
+        common, crypt,
         result::Result,
         snowflake,
     },
@@ -43,7 +51,13 @@ async fn to_detail_list(posts: Vec<Post>) -> Result<Vec<PostDetail>> {
         .iter()
         .map(|i| {
             let mut detail: PostDetail = i.into();
-            detail.content = review_rendered_content(&i.rendered_content);
+            // 需要密码的博客在列表里不显示摘要
+            if detail.visibility == Visibility::Password {
+                detail.content.clear();
+                detail.locked = true;
+            } else {
+                detail.content = review_rendered_content(&i.rendered_content);
+            }
             let tags = tags_map.get(&i.id);
             if tags.is_some() {
                 detail.tags = Some(tags.unwrap().iter().map(|t| t.name.clone()).collect());
@@ -85,8 +99,139 @@ fn append_pagination_sql(sql: &mut String, pagination_type: &str, post_id: u64)
     order_by_asc
 }
 
-pub async fn list(pagination_type: &str, post_id: u64, page_size: u8) -> Result<PaginationData<Vec<PostDetail>>> {
-    let row = sqlx::query("SELECT COUNT(id) FROM posts")
+pub(crate) fn is_valid_language(language: &str) -> bool {
+    common::LANGUAGE_CODE_REGEX.is_match(language)
+}
+
+// 列表的筛选条件
+pub struct PostFilter {
+    pub language: Option<String>,
+    // 登录后可以看到所有博客，否则只能看到公开的和需要密码的博客
+    pub authed: bool,
+}
+
+impl PostFilter {
+    fn language(&self) -> Option<&str> {
+        self.language.as_deref().filter(|l| !l.is_empty())
+    }
+
+    // 语言通过参数绑定，拼 SQL 的位置要和 bind/bind_as 的调用顺序一致
+    fn condition(&self) -> Result<String> {
+        let mut condition = String::new();
+        if let Some(l) = self.language() {
+            if !is_valid_language(l) {
+                return Err(Error::BusinessException(String::from("语言代码不正确/Invalid language code.")).into());
+            }
+            condition.push_str(" AND language=? ");
+        }
+        if !self.authed {
+            condition.push_str(PUBLIC_LISTED_CONDITION);
+        }
+        Ok(condition)
+    }
+
+    fn bind<'q>(&'q self, query: Query<'q, Sqlite, SqliteArguments<'q>>) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+        match self.language() {
+            Some(l) => query.bind(l),
+            None => query,
+        }
+    }
+
+    fn bind_as<'q, O>(
+        &'q self,
+        query: QueryAs<'q, Sqlite, O, SqliteArguments<'q>>,
+    ) -> QueryAs<'q, Sqlite, O, SqliteArguments<'q>> {
+        match self.language() {
+            Some(l) => query.bind(l),
+            None => query,
+        }
+    }
+}
+
+pub(crate) const PUBLIC_LISTED_CONDITION: &'static str = " AND visibility IN ('public','password') ";
+// 分类的博客数量和 post-link 的标题都不能泄露需要密码和私密的博客
+pub(crate) const UNPROTECTED_CONDITION: &str = " AND visibility NOT IN ('password','private') ";
+
+// 置顶的博客不参与分页，只在第一页的最上面显示
+// 从第二页往前翻时，如果已经没有更新的博客，说明回到了第一页
+async fn is_first_page(
+    pagination_type: &str,
+    post_id: u64,
+    posts: &[Post],
+    tag_id: Option<i64>,
+    filter: &PostFilter,
+) -> Result<bool> {
+    if post_id == 0 {
+        return Ok(true);
+    }
+    if pagination_type != "prev" {
+        return Ok(false);
+    }
+    let newest_id = posts.first().map_or(post_id as i64, |p| p.id);
+    let mut sql = String::from("SELECT COUNT(id) FROM posts WHERE pinned=0 AND id>?");
+    if tag_id.is_some() {
+        sql.push_str(" AND id IN (SELECT post_id FROM tags_usage WHERE tag_id = ?)");
+    }
+    sql.push_str(&filter.condition()?);
+    let mut query = sqlx::query(&sql).bind(newest_id);
+    if let Some(tag_id) = tag_id {
+        query = query.bind(tag_id);
+    }
+    let row = filter.bind(query).fetch_one(super::get_sqlite()).await?;
+    let newer: i64 = row.get(0);
+    Ok(newer == 0)
+}
+
+async fn pinned_posts(tag_id: Option<i64>, filter: &PostFilter) -> Result<Vec<Post>> {
+    let mut sql = String::from("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE pinned=1");
+    if tag_id.is_some() {
+        sql.push_str(" AND id IN (SELECT post_id FROM tags_usage WHERE tag_id = ?)");
+    }
+    sql.push_str(&filter.condition()?);
+    sql.push_str(" ORDER BY id DESC");
+    let mut query = sqlx::query_as::<Sqlite, Post>(&sql);
+    if let Some(tag_id) = tag_id {
+        query = query.bind(tag_id);
+    }
+    Ok(filter.bind_as(query).fetch_all(super::get_sqlite()).await?)
+}
+
+pub async fn featured(amount: u8) -> Result<Vec<PostDetail>> {
+    let sql = format!("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE featured=1{}ORDER BY id DESC LIMIT ?", PUBLIC_LISTED_CONDITION);
+    let posts = sqlx::query_as::<Sqlite, Post>(&sql)
+        .bind(amount)
+        .fetch_all(super::get_sqlite())
+        .await?;
+    to_detail_list(posts).await
+}
+
+pub async fn update_pinned(id: u64, pinned: bool) -> Result<()> {
+    sqlx::query("UPDATE posts SET pinned=? WHERE id=?")
+        .bind(pinned)
+        .bind(id as i64)
+        .execute(super::get_sqlite())
+        .await?;
+    Ok(())
+}
+
+pub async fn update_featured(id: u64, featured: bool) -> Result<()> {
+    sqlx::query("UPDATE posts SET featured=? WHERE id=?")
+        .bind(featured)
+        .bind(id as i64)
+        .execute(super::get_sqlite())
+        .await?;
+    Ok(())
+}
+
+pub async fn list(
+    pagination_type: &str,
+    post_id: u64,
+    page_size: u8,
+    filter: &PostFilter,
+) -> Result<PaginationData<Vec<PostDetail>>> {
+    let condition = filter.condition()?;
+    let row = filter
+        .bind(sqlx::query(&format!("SELECT COUNT(id) FROM posts WHERE 1=1{}", condition)))
         .fetch_one(super::get_sqlite())
         .await?;
     let total: i64 = row.get(0);
@@ -97,18 +242,25 @@ pub async fn list(pagination_type: &str, post_id: u64, page_size: u8) -> Result<
 
     let mut sql = String::with_capacity(256);
     sql.push_str(
-        "SELECT id,title,title_image,'' AS markdown_content,'' AS rendered_content,created_at,updated_at FROM posts ",
+        "SELECT id,title,title_image,'' AS markdown_content,'' AS rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE pinned=0 ",
     );
+    sql.push_str(&condition);
     let order_by_asc = append_pagination_sql(&mut sql, pagination_type, post_id);
     println!("sql={}", sql);
 
-    let mut d = sqlx::query_as::<Sqlite, Post>(&sql)
+    let mut d = filter
+        .bind_as(sqlx::query_as::<Sqlite, Post>(&sql))
         .bind(page_size)
         .fetch_all(super::get_sqlite())
         .await?;
     if order_by_asc {
         d.reverse();
     }
+    if is_first_page(pagination_type, post_id, &d, None, filter).await? {
+        let mut pinned = pinned_posts(None, filter).await?;
+        pinned.append(&mut d);
+        d = pinned;
+    }
     Ok(PaginationData {
         total: total as u64,
         data: to_detail_list(d).await?,
@@ -129,7 +281,9 @@ pub async fn seach_by_key(
     pagination_type: &str,
     post_id: u64,
     page_size: u8,
+    filter: &PostFilter,
 ) -> Result<PaginationData<Vec<PostDetail>>> {
+    let condition = filter.condition()?;
     // SELECT id,title,title_image,'' AS markdown_content,'' AS rendered_content,created_at,updated_at FROM posts WHERE title LIKE 'XXXX%'
     let key_word = urlencoding::decode(&key_word)?;
     let s = key_word.as_ref();
@@ -138,19 +292,23 @@ pub async fn seach_by_key(
     let mut key = String::from("%");
     key.push_str(s);
     key.push_str("%");
-    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE title LIKE ?");
+    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE title LIKE ?");
+    sql.push_str(&condition);
 
     let order_by_asc = append_pagination_sql(&mut sql, pagination_type, post_id);
     println!("key={}", key);
     println!("sql={}", sql);
-    let mut d = sqlx::query_as::<Sqlite, Post>(
-        // "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ? ORDER BY id DESC LIMIT ?, ?)",
-        &sql,
-    )
-    .bind(key)
-    .bind(page_size)
-    .fetch_all(super::get_sqlite())
-    .await?;
+    let mut d = filter
+        .bind_as(
+            sqlx::query_as::<Sqlite, Post>(
+                // "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ? ORDER BY id DESC LIMIT ?, ?)",
+                &sql,
+            )
+            .bind(key),
+        )
+        .bind(page_size)
+        .fetch_all(super::get_sqlite())
+        .await?;
     if order_by_asc {
         d.reverse();
     }
@@ -166,7 +324,9 @@ pub async fn list_by_tag(
     pagination_type: &str,
     post_id: u64,
     page_size: u8,
+    filter: &PostFilter,
 ) -> Result<PaginationData<Vec<PostDetail>>> {
+    let condition = filter.condition()?;
     let tag_name = urlencoding::decode(&tag_name)?;
     let s = tag_name.as_ref();
     let tag = sqlx::query_as::<Sqlite, Tag>("SELECT id,name FROM tags WHERE name = ?")
@@ -178,8 +338,14 @@ pub async fn list_by_tag(
     }
     let tag = tag.unwrap();
 
-    let r = sqlx::query("SELECT COUNT(*) FROM tags_usage WHERE tag_id = ?")
-        .bind(tag.id)
+    let r = filter
+        .bind(
+            sqlx::query(&format!(
+                "SELECT COUNT(id) FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ?){}",
+                condition
+            ))
+            .bind(tag.id),
+        )
         .fetch_one(super::get_sqlite())
         .await?;
     let r = r.try_get::<i64, usize>(0);
@@ -194,18 +360,151 @@ pub async fn list_by_tag(
     }
 
     let mut sql = String::with_capacity(256);
-    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ? ");
+    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE pinned=0 AND id IN (SELECT post_id FROM tags_usage WHERE tag_id = ?) ");
+    sql.push_str(&condition);
     let order_by_asc = append_pagination_sql(&mut sql, pagination_type, post_id);
+    let mut d = filter
+        .bind_as(
+            sqlx::query_as::<Sqlite, Post>(
+                // "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ? ORDER BY id DESC LIMIT ?, ?)",
+                &sql,
+            )
+            .bind(tag.id),
+        )
+        .bind(page_size)
+        .fetch_all(super::get_sqlite())
+        .await?;
+    if order_by_asc {
+        d.reverse();
+    }
+    if is_first_page(pagination_type, post_id, &d, Some(tag.id), filter).await? {
+        let mut pinned = pinned_posts(Some(tag.id), filter).await?;
+        pinned.append(&mut d);
+        d = pinned;
+    }
+    Ok(PaginationData {
+        total: total as u64,
+        data: to_detail_list(d).await?,
+    })
+}
+
+pub async fn list_by_category(
+    category_id: i64,
+    pagination_type: &str,
+    post_id: u64,
+    page_size: u8,
+    filter: &PostFilter,
+) -> Result<PaginationData<Vec<PostDetail>>> {
+    let condition = filter.condition()?;
+    if !category::exists(category_id).await? {
+        return Err(Error::CategoryNotFound.into());
+    }
+
+    let mut sql = String::with_capacity(512);
+    sql.push_str("SELECT COUNT(id) FROM posts WHERE category_id IN (");
+    sql.push_str(category::DESCENDANT_IDS_SQL);
     sql.push_str(")");
-    println!("sql={}", sql);
-    let mut d = sqlx::query_as::<Sqlite, Post>(
-        // "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id IN (SELECT post_id FROM tags_usage WHERE tag_id = ? ORDER BY id DESC LIMIT ?, ?)",
-        &sql,
-    )
-    .bind(tag.id)
-    .bind(page_size)
-    .fetch_all(super::get_sqlite())
-    .await?;
+    sql.push_str(&condition);
+    let row = filter
+        .bind(sqlx::query(&sql).bind(category_id))
+        .fetch_one(super::get_sqlite())
+        .await?;
+    let total: i64 = row.get(0);
+    if total < 1 {
+        return Ok(PaginationData { total: 0, data: vec![] });
+    }
+
+    sql.clear();
+    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE category_id IN (");
+    sql.push_str(category::DESCENDANT_IDS_SQL);
+    sql.push_str(") ");
+    sql.push_str(&condition);
+    let order_by_asc = append_pagination_sql(&mut sql, pagination_type, post_id);
+    let mut d = filter
+        .bind_as(sqlx::query_as::<Sqlite, Post>(&sql).bind(category_id))
+        .bind(page_size)
+        .fetch_all(super::get_sqlite())
+        .await?;
+    if order_by_asc {
+        d.reverse();
+    }
+    Ok(PaginationData {
+        total: total as u64,
+        data: to_detail_list(d).await?,
+    })
+}
+
+pub async fn archive(filter: &PostFilter) -> Result<Vec<ArchiveYear>> {
+    let sql = format!("SELECT CAST(strftime('%Y', created_at, 'unixepoch') AS INTEGER) AS y, CAST(strftime('%m', created_at, 'unixepoch') AS INTEGER) AS m, COUNT(id) FROM posts WHERE 1=1{} GROUP BY y, m ORDER BY y DESC, m DESC", filter.condition()?);
+    let rows = filter.bind(sqlx::query(&sql)).fetch_all(super::get_sqlite()).await?;
+    let mut years: Vec<ArchiveYear> = Vec::new();
+    for row in rows {
+        let year: i64 = row.get(0);
+        let month: i64 = row.get(1);
+        let amount: i64 = row.get(2);
+        let month = ArchiveMonth {
+            month: month as u8,
+            amount: amount as u32,
+        };
+        match years.last_mut() {
+            Some(y) if y.year == year as i32 => {
+                y.amount += month.amount;
+                y.months.push(month);
+            },
+            _ => years.push(ArchiveYear {
+                year: year as i32,
+                amount: month.amount,
+                months: vec![month],
+            }),
+        }
+    }
+    Ok(years)
+}
+
+// month 为 0 时列出整年的博客
+pub async fn list_by_month(
+    year: i32,
+    month: u8,
+    pagination_type: &str,
+    post_id: u64,
+    page_size: u8,
+    filter: &PostFilter,
+) -> Result<PaginationData<Vec<PostDetail>>> {
+    let condition = filter.condition()?;
+    if month > 12 {
+        return Err(Error::BusinessException(String::from("月份不正确/Invalid month.")).into());
+    }
+    let (format, period) = if month == 0 {
+        ("%Y", format!("{:04}", year))
+    } else {
+        ("%Y-%m", format!("{:04}-{:02}", year, month))
+    };
+
+    let row = filter
+        .bind(
+            sqlx::query(&format!(
+                "SELECT COUNT(id) FROM posts WHERE strftime(?, created_at, 'unixepoch') = ?{}",
+                condition
+            ))
+            .bind(format)
+            .bind(&period),
+        )
+        .fetch_one(super::get_sqlite())
+        .await?;
+    let total: i64 = row.get(0);
+    if total < 1 {
+        return Ok(PaginationData { total: 0, data: vec![] });
+    }
+
+    let mut sql = String::with_capacity(256);
+    sql.push_str("SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE strftime(?, created_at, 'unixepoch') = ? ");
+    sql.push_str(&condition);
+    let order_by_asc = append_pagination_sql(&mut sql, pagination_type, post_id);
+    let mut d = filter
+        .bind_as(sqlx::query_as::<Sqlite, Post>(&sql).bind(format).bind(&period))
+        .bind(page_size)
+        .fetch_all(super::get_sqlite())
+        .await?;
     if order_by_asc {
         d.reverse();
     }
@@ -243,11 +542,30 @@ pub async fn update_title_image(id: i64, title_image: &str) -> Result<()> {
     Ok(())
 }
 
+// 导入时保留原来的发布时间
+pub async fn update_created_at(id: i64, created_at: i64) -> Result<()> {
+    sqlx::query("UPDATE posts SET created_at=? WHERE id=?")
+        .bind(created_at)
+        .bind(id)
+        .execute(super::get_sqlite())
+        .await?;
+
+    Ok(())
+}
+
+pub async fn exists_by_title(title: &str) -> Result<bool> {
+    let r = sqlx::query("SELECT id FROM posts WHERE title = ? LIMIT 1")
+        .bind(title)
+        .fetch_optional(super::get_sqlite())
+        .await?;
+    Ok(r.is_some())
+}
+
 async fn get_post(id: i64, edit: bool) -> Result<Option<Post>> {
     let sql = if edit {
-        "SELECT id,title,title_image,'' AS markdown_content,markdown_content AS rendered_content,created_at,updated_at FROM posts WHERE id = ?"
+        "SELECT id,title,title_image,'' AS markdown_content,markdown_content AS rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE id = ?"
     } else {
-        "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at FROM posts WHERE id = ?"
+        "SELECT id,title,title_image,'' AS markdown_content,rendered_content,created_at,updated_at,category_id,pinned,featured,language,translation_group,visibility FROM posts WHERE id = ?"
     };
     sqlx::query_as::<Sqlite, Post>(sql)
         .bind(id)
@@ -264,6 +582,48 @@ pub async fn save(post_data: PostData) -> Result<PostDetail> {
     if post.is_none() {
         return Err(Error::CannotFoundPost.into());
     }
+    if let Some(category_id) = post_data.category_id {
+        if !category::exists(category_id).await? {
+            return Err(Error::CategoryNotFound.into());
+        }
+    }
+    let language = post_data.language.trim();
+    if !language.is_empty() && !is_valid_language(language) {
+        return Err(Error::BusinessException(String::from("语言代码不正确/Invalid language code.")).into());
+    }
+    let translation_group = match post_data.translation_of {
+        Some(origin_id) if origin_id != post_data.id => {
+            let origin = get_post(origin_id, false).await?;
+            if origin.is_none() {
+                return Err(Error::CannotFoundPost.into());
+            }
+            let origin = origin.unwrap();
+            if origin.translation_group.is_none() {
+                sqlx::query("UPDATE posts SET translation_group=? WHERE id=?")
+                    .bind(origin.id)
+                    .bind(origin.id)
+                    .execute(super::get_sqlite())
+                    .await?;
+            }
+            Some(origin.translation_group.unwrap_or(origin.id))
+        },
+        // 没有关联其它博客时，保留其它博客对这篇博客的关联
+        _ => post.as_ref().and_then(|p| p.translation_group).filter(|g| *g == post_data.id),
+    };
+    // 只有需要密码的博客保存密码，密码为空时沿用原来的密码
+    let password = match post_data.visibility {
+        Visibility::Password => match post_data.password.as_ref().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            Some(p) => crypt::encrypt_password(p)?,
+            None => {
+                let stored = get_password(post_data.id).await?;
+                if stored.is_empty() {
+                    return Err(Error::BusinessException(String::from("需要设置密码/Password required.")).into());
+                }
+                stored
+            },
+        },
+        _ => String::new(),
+    };
 
     // needs to be in a transaction
     let transaction = super::get_sqlite().begin().await?;
@@ -277,13 +637,24 @@ pub async fn save(post_data: PostData) -> Result<PostDetail> {
     // pulldown_cmark::html::push_html(&mut html_text, parser);
 
     let post = post.unwrap();
+    let rendered = markdown::render(&post_data.content).await?;
 
     let post_detail = PostDetail {
         id: post_data.id,
         title: post_data.title,
         title_image: post_data.title_image,
-        content: markdown_to_html(&post_data.content, &ComrakOptions::default()),
+        content: rendered.html,
         tags: post_data.tags,
+        category_id: post_data.category_id,
+        breadcrumb: None,
+        toc: Some(rendered.toc),
+        related: None,
+        pinned: post.pinned,
+        featured: post.featured,
+        language: language.to_string(),
+        translations: None,
+        visibility: post_data.visibility,
+        locked: false,
         created_at: post.created_at as u64,
         updated_at: post.updated_at.map(|time| time as u64),
         editable: true,
@@ -297,13 +668,19 @@ pub async fn save(post_data: PostData) -> Result<PostDetail> {
 
     // save to sqlite
     sqlx::query(
-        "UPDATE posts SET title=?, title_image=?, markdown_content=?, rendered_content=?, updated_at=? WHERE id=?",
+        "UPDATE posts SET title=?, title_image=?, markdown_content=?, rendered_content=?, toc=?, updated_at=?, category_id=?, language=?, translation_group=?, visibility=?, password=? WHERE id=?",
     )
     .bind(post_title)
     .bind(&post_detail.title_image)
     .bind(&post_data.content)
     .bind(&post_detail.content)
+    .bind(serde_json::to_string(&post_detail.toc)?)
     .bind(time::unix_epoch_sec() as i64)
+    .bind(&post_detail.category_id)
+    .bind(&post_detail.language)
+    .bind(translation_group)
+    .bind(post_detail.visibility.as_str())
+    .bind(&password)
     .bind(&post_detail.id)
     .execute(super::get_sqlite())
     .await?;
@@ -311,32 +688,157 @@ pub async fn save(post_data: PostData) -> Result<PostDetail> {
     // 这里只关心 commit，因为 https://docs.rs/sqlx/0.5.1/sqlx/struct.Transaction.html 说到
     // If neither are called before the transaction goes out-of-scope, rollback is called. In other words, rollback is called on drop if the transaction is still in-progress.
     transaction.commit().await?;
+    related::invalidate();
+    autosave::discard(post_detail.id).await?;
 
     Ok(post_detail)
 }
 
-pub async fn show(id: u64, editable: bool) -> Result<PostDetail> {
+// authed 为登录用户，grant 为输入密码后拿到的凭证
+pub async fn show(id: u64, editable: bool, authed: bool, grant: Option<String>) -> Result<PostDetail> {
     // let r: Option<PostDetail> = db::sled_get(&DATA_SOURCE.get().unwrap().post, id.to_le_bytes()).await?;
     let id = id as i64;
     let r = get_post(id, editable).await?;
-    if r.is_none() {
+    let visibility = r.as_ref().map(|p| Visibility::from_str(&p.visibility));
+    if r.is_none() || (visibility == Some(Visibility::Private) && !authed) {
         Err(Error::CannotFoundPost.into())
     } else {
         let tags = sqlx::query_as::<Sqlite, Tag>("SELECT t.id AS id, t.name AS name FROM tags t INNER JOIN tags_usage u ON t.id = u.tag_id WHERE u.post_id = ? ORDER BY t.created_at DESC")
             .bind(id)
             .fetch_all(super::get_sqlite())
             .await?.iter().map(|t| t.name.clone()).collect();
-        let mut post_detail: PostDetail = (&r.unwrap()).into();
+        let post = r.unwrap();
+        let mut post_detail: PostDetail = (&post).into();
         post_detail.tags = Some(tags);
+        if let Some(group) = post.translation_group {
+            post_detail.translations = Some(translations(group, id, authed).await?);
+        }
+        if let Some(category_id) = post_detail.category_id {
+            post_detail.breadcrumb = Some(category::breadcrumb(category_id).await?);
+        }
+        if !editable {
+            post_detail.toc = get_toc(id).await?;
+            post_detail.related = Some(related::related_posts(id));
+        }
+        if visibility == Some(Visibility::Password)
+            && !authed
+            && !grant.map_or(false, |g| status::check_post_grant(&g, id))
+        {
+            post_detail.content.clear();
+            post_detail.toc = None;
+            post_detail.locked = true;
+        }
         Ok(post_detail)
     }
 }
 
+async fn get_password(id: i64) -> Result<String> {
+    let row = sqlx::query("SELECT password FROM posts WHERE id = ?")
+        .bind(id)
+        .fetch_optional(super::get_sqlite())
+        .await?;
+    Ok(row.map_or(String::new(), |r| r.get(0)))
+}
+
+pub async fn unlock(id: u64, password: &str) -> Result<bool> {
+    let stored = get_password(id as i64).await?;
+    if stored.is_empty() {
+        return Err(Error::CannotFoundPost.into());
+    }
+    crypt::verify_password(password, &stored)
+}
+
+// 未登录时不显示私密的翻译
+pub async fn translations(group: i64, exclude_id: i64, authed: bool) -> Result<Vec<Translation>> {
+    let sql = format!(
+        "SELECT id,language,title FROM posts WHERE translation_group=? AND id<>?{}ORDER BY language",
+        if authed { " " } else { PUBLIC_LISTED_CONDITION }
+    );
+    let rows = sqlx::query(&sql)
+        .bind(group)
+        .bind(exclude_id)
+        .fetch_all(super::get_sqlite())
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|r| Translation {
+            id: r.get(0),
+            language: r.get(1),
+            title: r.get(2),
+        })
+        .collect())
+}
+
+// 分组 id -> 这个分组下公开的博客
+pub async fn all_translations() -> Result<HashMap<i64, Vec<Translation>>> {
+    let sql = format!(
+        "SELECT id,language,title,translation_group FROM posts WHERE translation_group IS NOT NULL{}",
+        PUBLIC_LISTED_CONDITION
+    );
+    let rows = sqlx::query(&sql)
+        .fetch_all(super::get_sqlite())
+        .await?;
+    let mut groups: HashMap<i64, Vec<Translation>> = HashMap::new();
+    for r in rows.iter() {
+        groups.entry(r.get(3)).or_default().push(Translation {
+            id: r.get(0),
+            language: r.get(1),
+            title: r.get(2),
+        });
+    }
+    Ok(groups)
+}
+
+// 短代码 post-link 用到的博客标题，不包括需要密码和私密的博客
+pub async fn titles(ids: Vec<i64>) -> Result<HashMap<i64, String>> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut sql = String::from("SELECT id,title FROM posts WHERE id IN (");
+    for _i in 0..ids.len() {
+        sql.push_str("?,");
+    }
+    sql.replace_range(sql.len() - 1.., ")");
+    sql.push_str(UNPROTECTED_CONDITION);
+    let mut query = sqlx::query(&sql);
+    for id in ids.iter() {
+        query = query.bind(id);
+    }
+    let rows = query.fetch_all(super::get_sqlite()).await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+async fn get_toc(id: i64) -> Result<Option<Vec<TocItem>>> {
+    let row = sqlx::query("SELECT toc FROM posts WHERE id = ?")
+        .bind(id)
+        .fetch_one(super::get_sqlite())
+        .await?;
+    let toc: String = row.get(0);
+    // 旧数据重新渲染之前 toc 是空的
+    if toc.is_empty() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_str(&toc)?)
+}
+
+pub async fn update_rendered_content(id: i64, rendered_content: &str, toc: &[TocItem]) -> Result<()> {
+    sqlx::query("UPDATE posts SET rendered_content=?, toc=? WHERE id=?")
+        .bind(rendered_content)
+        .bind(serde_json::to_string(toc)?)
+        .bind(id)
+        .execute(super::get_sqlite())
+        .await?;
+
+    Ok(())
+}
+
 pub async fn delete(id: u64) -> Result<()> {
     let r = sqlx::query("DELETE FROM posts WHERE id=?")
         .bind(id as i64)
         .execute(super::get_sqlite())
         .await?;
+    related::invalidate();
+    autosave::discard(id as i64).await?;
     Ok(())
 }
 
@@ -347,10 +849,69 @@ pub async fn all() -> Result<Vec<Post>> {
     Ok(posts)
 }
 
-pub async fn all_by_since(timestamp: i64) -> Result<Vec<Post>> {
-    let posts = sqlx::query_as::<Sqlite, Post>("SELECT * FROM posts WHERE updated_at>=? ORDER BY id DESC")
-        .bind(timestamp)
+// 导出时用来生成不重复的 slug
+pub async fn all_titles() -> Result<Vec<(i64, String)>> {
+    let rows = sqlx::query("SELECT id,title FROM posts ORDER BY id")
         .fetch_all(super::get_sqlite())
         .await?;
-    Ok(posts)
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::category::CategoryData;
+
+    use super::*;
+    use crate::util::testing;
+
+    async fn new_category(name: &str, parent_id: Option<i64>) -> i64 {
+        let data = CategoryData {
+            name: String::from(name),
+            parent_id,
+        };
+        category::new_category(&data).await.unwrap()
+    }
+
+    #[test]
+    fn list_posts_in_sub_categories() {
+        testing::block_on(async {
+            let root = new_category(&common::simple_uuid(), None).await;
+            let child = new_category("Child", Some(root)).await;
+            let grandchild = new_category("Grandchild", Some(child)).await;
+            let other = new_category(&common::simple_uuid(), None).await;
+            let in_root = testing::new_post("In root", Some(root), Visibility::Public).await;
+            let in_child = testing::new_post("In child", Some(child), Visibility::Public).await;
+            let in_grandchild = testing::new_post("In grandchild", Some(grandchild), Visibility::Public).await;
+            testing::new_post("In other", Some(other), Visibility::Public).await;
+            let filter = PostFilter {
+                language: None,
+                authed: false,
+            };
+
+            let page = list_by_category(root, "", 0, 10, &filter).await.unwrap();
+            assert_eq!(3, page.total);
+            let ids = page.data.iter().map(|p| p.id).collect::<Vec<i64>>();
+            assert_eq!(vec![in_grandchild, in_child, in_root], ids);
+
+            let page = list_by_category(child, "", 0, 10, &filter).await.unwrap();
+            let ids = page.data.iter().map(|p| p.id).collect::<Vec<i64>>();
+            assert_eq!(vec![in_grandchild, in_child], ids);
+        });
+    }
+
+    #[test]
+    fn titles_exclude_protected_posts() {
+        testing::block_on(async {
+            let public = testing::new_post("Public", None, Visibility::Public).await;
+            let unlisted = testing::new_post("Unlisted", None, Visibility::Unlisted).await;
+            let password = testing::new_post("Password", None, Visibility::Password).await;
+            let private = testing::new_post("Private", None, Visibility::Private).await;
+
+            let titles = titles(vec![public, unlisted, password, private]).await.unwrap();
+            assert_eq!(2, titles.len());
+            assert_eq!("Public", titles[&public]);
+            assert_eq!("Unlisted", titles[&unlisted]);
+        });
+    }
 }
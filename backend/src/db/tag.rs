@@ -7,12 +7,14 @@ use blog_common::{dto::tag::TagUsageAmount, util::time};
 use sqlx::{Row, Sqlite};
 
 use crate::{
-    db::{model::Tag, DATA_SOURCE},
+    db::{model::Tag, post, DATA_SOURCE},
     util::result::Result,
 };
 
+// 只统计公开的博客
 pub async fn top() -> Result<Vec<TagUsageAmount>> {
-    let tags = sqlx::query("SELECT t.id,t.name,u.amount FROM tags t INNER JOIN (SELECT tag_id, COUNT(tag_id) AS amount FROM tags_usage GROUP BY tag_id) u ON t.id=u.tag_id ORDER BY u.amount DESC")
+    let sql = format!("SELECT t.id,t.name,u.amount FROM tags t INNER JOIN (SELECT tag_id, COUNT(tag_id) AS amount FROM tags_usage WHERE post_id IN (SELECT id FROM posts WHERE 1=1{}) GROUP BY tag_id) u ON t.id=u.tag_id ORDER BY u.amount DESC", post::PUBLIC_LISTED_CONDITION);
+    let tags = sqlx::query(&sql)
         .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
         .await?;
     let name_list = tags
@@ -26,6 +28,14 @@ pub async fn top() -> Result<Vec<TagUsageAmount>> {
     Ok(name_list)
 }
 
+// (post_id, tag_id)
+pub async fn all_usages() -> Result<Vec<(i64, i64)>> {
+    let rows = sqlx::query("SELECT post_id,tag_id FROM tags_usage")
+        .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
+        .await?;
+    Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+}
+
 pub async fn list() -> Result<Vec<String>> {
     let tag_list = sqlx::query_as::<Sqlite, Tag>("SELECT id,name FROM tags ORDER BY created_at DESC")
         .fetch_all(&DATA_SOURCE.get().unwrap().sqlite)
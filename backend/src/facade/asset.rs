@@ -3,10 +3,34 @@ use core::result::Result;
 use hyper::{body::Body, header};
 use warp::{filters::path::Tail, http::Response, Rejection};
 
-use crate::service::asset;
+use crate::service::{asset, highlight, markdown};
 
 pub async fn get_asset(tail: Tail) -> Result<Response<Body>, Rejection> {
-    Ok(response_asset(tail.as_str()))
+    let asset = tail.as_str();
+    if asset.starts_with("highlight/") && asset.ends_with(".css") {
+        return Ok(response_highlight_css(&asset[10..asset.len() - 4]).await);
+    }
+    Ok(response_asset(asset))
+}
+
+// 代码高亮主题的样式，current 表示设置里当前选中的主题
+async fn response_highlight_css(theme: &str) -> Response<Body> {
+    let theme = if theme == "current" {
+        match markdown::get_options().await {
+            Ok(o) => o.highlight_theme,
+            Err(_) => String::from(highlight::DEFAULT_THEME),
+        }
+    } else {
+        String::from(theme)
+    };
+    match highlight::theme_css(&theme) {
+        Ok(Some(css)) => Response::builder()
+            .header(header::CONTENT_TYPE, "text/css")
+            .header(header::CONTENT_LENGTH, css.len())
+            .body(css.into())
+            .unwrap(),
+        _ => Response::builder().status(404).body("".into()).unwrap(),
+    }
 }
 
 fn response_asset(asset: &str) -> Response<Body> {
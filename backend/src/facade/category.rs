@@ -0,0 +1,39 @@
+use core::result::Result;
+
+use blog_common::{
+    dto::{category::CategoryData, user::UserInfo},
+    result::Error,
+};
+use warp::{Rejection, Reply};
+
+use crate::{
+    db::category,
+    facade::{wrap_json_data, wrap_json_err},
+};
+
+pub async fn tree() -> Result<impl Reply, Rejection> {
+    match category::tree().await {
+        Ok(list) => Ok(wrap_json_data(&list)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn new(user: Option<UserInfo>, data: CategoryData) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match category::new_category(&data).await {
+        Ok(id) => Ok(wrap_json_data(&id)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn delete(id: i64, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match category::delete(id).await {
+        Ok(_) => Ok(wrap_json_data("")),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
@@ -0,0 +1,55 @@
+use blog_common::dto::deploy::DeployTarget;
+use hyper::body::Body;
+use warp::{http::Response, Rejection, Reply};
+
+use crate::{
+    facade,
+    service::{deploy, export, status},
+};
+
+pub async fn show(token: Option<String>) -> Result<Response<Body>, Rejection> {
+    if status::check_auth(token).is_err() {
+        return Ok(super::management_sign_in("/management/deploy-targets").into_response());
+    }
+    let mut context = tera::Context::new();
+    context.insert("exporters", &export::exporter_names());
+    let html = match export::TEMPLATES.render("deploy-targets.html", &context) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{:?}", e);
+            format!("Failed render page: {}", e)
+        },
+    };
+    let response = Response::builder().header("Content-Type", "text/html; charset=utf-8");
+    Ok(response.body(html.into()).unwrap())
+}
+
+pub async fn targets(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(deploy::targets().await)
+}
+
+// 按名称新增或修改，返回所有目标
+pub async fn save_target(token: Option<String>, target: DeployTarget) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(deploy::save_target(target).await)
+}
+
+pub async fn remove_target(name: String, token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(deploy::remove_target(&name).await)
+}
+
+// 在后台部署，返回任务，进度和 Git Pages 一样通过 /git/publish/jobs/{id}/events 获取
+pub async fn deploy_now(name: String, token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(deploy::deploy_now(&name).await)
+}
@@ -1,9 +1,9 @@
 use blog_common::{
-    dto::user::UserInfo,
+    dto::{export::ExportResult, user::UserInfo},
 };
 use hyper::body::Body;
 use hyper::header::{self};
-use warp::{filters::path::Tail, http::Response, Rejection};
+use warp::{filters::path::Tail, http::Response, Rejection, Reply};
 
 use crate::{
     service::export,
@@ -14,10 +14,10 @@ pub async fn export_handler(tail: Tail, user: Option<UserInfo>) -> Result<Respon
         return Ok(Response::builder().status(403).body("".into()).unwrap());
     }
     let path = tail.as_str();
-    if path.eq("hugo") {
-        return hugo().await;
+    if export::exporter_names().contains(&path) {
+        return zip(path).await;
     }
-    if path.rfind(".zip").is_some() {
+    if path.ends_with(".zip") || path.ends_with(".csv") {
         return Ok(get_file(path));
     }
     Ok(Response::builder().status(404).body("".into()).unwrap())
@@ -42,16 +42,13 @@ fn get_file(file: &str) -> Response<Body> {
     Response::builder().status(404).body("".into()).unwrap()
 }
 
-async fn hugo() -> Result<Response<Body>, Rejection> {
-    let filename = export::hugo().await?;
-    let mut uri = String::with_capacity(64);
-    uri.push_str("/export/");
-    uri.push_str(&filename);
+async fn zip(exporter: &str) -> Result<Response<Body>, Rejection> {
+    let result = export::zip(exporter).await.map(|(filename, missing)| {
+        let mut uri = String::with_capacity(64);
+        uri.push_str("/export/");
+        uri.push_str(&filename);
+        ExportResult { url: uri, missing }
+    });
     // Ok(warp::redirect::temporary(warp::http::Uri::from_static(&uri)))
-    let r = Response::builder()
-        .header(header::CONTENT_TYPE, "text/plain")
-        .header(header::CONTENT_LENGTH, uri.len())
-        .body(uri.into())
-        .unwrap();
-    Ok(r)
+    Ok(super::response(result)?.into_response())
 }
@@ -1,20 +1,29 @@
 use std::collections::HashMap;
 
 use blog_common::{
-    dto::{
-        git::{GitPushInfo, GitRepositoryInfo},
+    dto::git::{
+        ConflictStrategy, DeployKeyUpload, GitCredential, GitPullOptions, GitPushInfo, GitRepositoryInfo, KnownHosts,
     },
-    result::{Error},
+    result::Error,
 };
+use futures::stream::{self, StreamExt};
 use hyper::body::Body;
-use warp::{filters::path::Tail, http::Response, Rejection, Reply};
+use tokio::sync::broadcast::error::RecvError;
+use warp::{filters::path::Tail, http::Response, sse::Event, Rejection, Reply};
 
 use crate::{
-    facade::{wrap_json_data, wrap_json_err},
-    service::{export, git::git, status},
+    facade::{self, wrap_json_data, wrap_json_err},
+    service::{
+        export,
+        git::{credential, git, publish, ssh},
+        status,
+    },
     util::common,
 };
 
+// 页面上显示的最近提交数
+const RECENT_COMMITS: usize = 20;
+
 static GIT_PAGES_INIT_HTML: &'static str = include_str!("../resource/page/git-pages-init.html");
 
 pub async fn show(token: Option<String>) -> Result<Response<Body>, Rejection> {
@@ -39,6 +48,9 @@ pub async fn show(token: Option<String>) -> Result<Response<Body>, Rejection> {
         context.insert("remote_url", &info.remote_url);
         context.insert("name", &info.name);
         context.insert("email", &info.email);
+        context.insert("exporters", &export::exporter_names());
+        context.insert("ssh", &ssh::is_ssh_url(&info.remote_url));
+        context.insert("conflict_strategy", &info.conflict_strategy);
         if info.branch_name.is_some() {
             context.insert("branch", &info.branch_name.unwrap());
             let d: Vec<String> = Vec::new();
@@ -67,13 +79,30 @@ pub async fn show(token: Option<String>) -> Result<Response<Body>, Rejection> {
     Ok(r)
 }
 
-pub async fn new_repository(mut params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn new_repository(
+    token: Option<String>,
+    mut params: HashMap<String, String>,
+) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return Ok(wrap_json_err(500, e.0));
+    }
     let empty_str = String::new();
     let url = params.get("url").unwrap_or(&empty_str);
-    if !url.starts_with("http") {
+    let is_ssh = ssh::is_ssh_url(url);
+    if !url.starts_with("http") && !is_ssh {
         return Ok(super::wrap_json_err(
             500,
-            Error::BusinessException(String::from("Url must starts with 'http'.")),
+            Error::BusinessException(String::from(
+                "Url must starts with 'http' or 'ssh://', or be like 'git@host:path'.",
+            )),
+        ));
+    }
+    if is_ssh && !matches!(ssh::deploy_key(), Ok(Some(_))) {
+        return Ok(wrap_json_err(
+            500,
+            Error::BusinessException(
+                "请先生成或上传部署密钥/Please generate or upload a deploy key first.".to_string(),
+            ),
         ));
     }
     let user = params.get("user").unwrap_or(&empty_str);
@@ -94,14 +123,15 @@ pub async fn new_repository(mut params: HashMap<String, String>) -> Result<impl
     if url.ends_with("/") {
         url.pop();
     }
-    let r = url.rfind("/");
+    // git@host:repo.git 没有 '/'
+    let r = url.rfind(|c| c == '/' || (is_ssh && c == ':'));
     if r.is_none() {
         return Ok(wrap_json_err(
             500,
             Error::BusinessException("输入的仓库地址不合法/Illegal repository address.".to_string()),
         ));
     }
-    let repository_name = &url[(r.unwrap() + 1)..];
+    let repository_name = url[(r.unwrap() + 1)..].trim_end_matches(".git");
     if repository_name.is_empty() {
         return Ok(wrap_json_err(
             500,
@@ -116,7 +146,7 @@ pub async fn new_repository(mut params: HashMap<String, String>) -> Result<impl
         repository_name: String::from(repository_name),
         remote_url: url,
         branch_name: None,
-        last_export_second: 0,
+        conflict_strategy: ConflictStrategy::default(),
     };
     match git::new_repository(info).await {
         Ok(_) => Ok(wrap_json_data("")),
@@ -124,7 +154,10 @@ pub async fn new_repository(mut params: HashMap<String, String>) -> Result<impl
     }
 }
 
-pub async fn remove_repository() -> Result<impl Reply, Rejection> {
+pub async fn remove_repository(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return Ok(wrap_json_err(500, e.0));
+    }
     let result = git::must_get_repository_info().await;
     let message = match result {
         Ok(info) => {
@@ -143,7 +176,10 @@ pub async fn remove_repository() -> Result<impl Reply, Rejection> {
     }
 }
 
-pub async fn set_branch(tail: Tail) -> Result<impl Reply, Rejection> {
+pub async fn set_branch(tail: Tail, token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return Ok(wrap_json_err(500, e.0));
+    }
     let result = git::must_get_repository_info().await;
     let message = match result {
         Ok(mut info) => {
@@ -168,24 +204,162 @@ pub async fn set_branch(tail: Tail) -> Result<impl Reply, Rejection> {
     }
 }
 
-pub async fn push(push_info: GitPushInfo) -> Result<impl Reply, Rejection> {
-    let result = git::must_get_repository_info().await;
-    let message = match result {
-        Ok(info) => match crate::service::git::pull::pull(&info) {
-            Ok(_) => match export::git(&info, &push_info).await {
-                Ok(_) => match git::sync_to_remote(&info, push_info.repo_credential.as_str()) {
-                    Ok(_) => String::new(),
-                    Err(e) => format!("Failed to push posts to git: {}", e),
-                },
-                Err(e) => format!("Failed to export posts: {:?}", e.0),
-            },
-            Err(e) => format!("Failed pull: {:?}", e),
+pub async fn update_pull_options(token: Option<String>, options: GitPullOptions) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    let result = match git::must_get_repository_info().await {
+        Ok(mut info) => {
+            info.conflict_strategy = options.conflict_strategy;
+            git::update_git_repository_info(&info).await
         },
-        Err(e) => e,
+        Err(e) => Err(e),
     };
-    if message.is_empty() {
-        Ok(wrap_json_data(message))
-    } else {
-        Ok(wrap_json_err(500, Error::BusinessException(message)))
+    facade::response(result.map_err(|e| Error::BusinessException(e).into()))
+}
+
+pub async fn repository_status(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    let result = match git::must_get_repository_info().await {
+        Ok(info) => git::status(&info, RECENT_COMMITS)
+            .map_err(|e| Error::BusinessException(format!("Failed to get repository status: {}", e)).into()),
+        Err(e) => Err(Error::BusinessException(e).into()),
+    };
+    facade::response(result)
+}
+
+pub async fn preview(token: Option<String>, push_info: GitPushInfo) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(publish::preview(push_info).await)
+}
+
+// 在后台发布，返回任务，进度通过 /git/publish/jobs/{id}/events 获取
+pub async fn push(token: Option<String>, push_info: GitPushInfo) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(publish::start(push_info).await)
+}
+
+pub async fn publish_jobs(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(Ok(publish::history()))
+}
+
+pub async fn publish_job(id: String, token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(publish::job(&id))
+}
+
+pub async fn cancel_publish_job(id: String, token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(publish::cancel(&id))
+}
+
+// 先发送整个任务（包括已有的日志），然后是新的日志和状态变化，任务结束后关闭
+pub async fn publish_events(id: String, token: Option<String>) -> Result<warp::reply::Response, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return Ok(wrap_json_err(401, e.0).into_response());
+    }
+    // 先订阅，避免错过获取任务之后的事件
+    let receiver = publish::subscribe();
+    let job = match publish::job(&id) {
+        Ok(j) => j,
+        Err(e) => return Ok(wrap_json_err(404, e.0).into_response()),
+    };
+    let finished = job.state.is_finished();
+    let first = stream::once(async move { Event::default().event("job").json_data(&job) });
+    let events = stream::unfold((receiver, finished), move |(mut receiver, finished)| {
+        let id = id.clone();
+        async move {
+            if finished {
+                return None;
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(e) if e.job_id == id => {
+                        let finished = e.state.is_finished();
+                        return Some((Event::default().event("progress").json_data(&e), (receiver, finished)));
+                    },
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    });
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(first.chain(events))).into_response())
+}
+
+pub async fn deploy_key(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::deploy_key())
+}
+
+pub async fn generate_deploy_key(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::generate())
+}
+
+pub async fn upload_deploy_key(token: Option<String>, key: DeployKeyUpload) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::upload(&key.private_key))
+}
+
+pub async fn remove_deploy_key(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::remove())
+}
+
+pub async fn known_hosts(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::known_hosts().map(|content| KnownHosts { content }))
+}
+
+pub async fn update_known_hosts(token: Option<String>, hosts: KnownHosts) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(ssh::update_known_hosts(&hosts.content))
+}
+
+pub async fn credential_status(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(credential::status())
+}
+
+// 保存新的密码/令牌，替换旧的
+pub async fn save_credential(token: Option<String>, c: GitCredential) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(credential::save(&c.credential))
+}
+
+pub async fn forget_credential(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
     }
+    facade::response(credential::forget())
 }
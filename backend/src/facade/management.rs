@@ -1,11 +1,15 @@
 use core::{convert::Infallible, result::Result};
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use blog_common::{
     dto::{
+        import::ImportDirectory,
         management::{AdminUser, Setting},
+        markdown::{MarkdownOptions, ShortcodeTemplate},
+        site::SiteOptions,
         user::UserInfo,
     },
+    result::Error,
 };
 use hyper::{body::Body, header};
 use warp::{http::Uri, reply::Response, Rejection, Reply};
@@ -14,8 +18,8 @@ use crate::{
     db::management,
     facade,
     facade::{wrap_json_data, wrap_json_err},
-    service::status,
-    util::common,
+    service::{backup, export, highlight, import, markdown, shortcode, site, status, wordpress},
+    util::{common, result::Result as CommonResult},
 };
 
 pub const SETTINGS_HTML: &'static str = include_str!("../resource/page/settings.html");
@@ -87,6 +91,17 @@ pub async fn show_render_templates_page(token: Option<String>) -> Result<warp::h
         context.insert("post_detail_template", &setting.content);
     }
     context.insert("post_detail_template_default", POST_DETAIL_DEFAULT_TEMPLATE);
+    // 每种导出格式的模板：(配置项, 名称, 自定义模板, 默认模板)
+    let mut exporters = Vec::new();
+    for name in export::exporter_names() {
+        let item = export::template_setting(name);
+        let custom = match management::get_setting(&item).await {
+            Ok(s) => s.map_or(String::new(), |s| s.content),
+            Err(e) => return Ok(response.body(format!("{:?}", e.0).into()).unwrap()),
+        };
+        exporters.push((item, name, custom, export::default_template(name)));
+    }
+    context.insert("exporters", &exporters);
     let html = match crate::service::export::TEMPLATES.render("export-template.html", &context) {
         Ok(s) => s,
         Err(e) => {
@@ -104,14 +119,155 @@ pub async fn update_render_templates(
     if let Err(e) = status::check_auth(token) {
         return facade::response(Err(e));
     }
-    let setting = crate::db::model::Setting {
-        item: crate::util::val::POST_DETAIL_RENDER_TEMPLATE.to_string(),
-        content: data
-            .get(crate::util::val::POST_DETAIL_RENDER_TEMPLATE)
-            .map_or(String::new(), |s| String::from(s)),
+    let mut items = vec![crate::util::val::POST_DETAIL_RENDER_TEMPLATE.to_string()];
+    items.extend(export::exporter_names().into_iter().map(export::template_setting));
+    // 只更新提交了的模板
+    for item in items {
+        if let Some(content) = data.get(&item) {
+            let setting = crate::db::model::Setting {
+                item,
+                content: content.clone(),
+            };
+            if let Err(e) = management::update_setting(setting).await {
+                return facade::response(Err(e));
+            }
+        }
+    }
+    facade::response(Ok(""))
+}
+
+pub async fn markdown_options(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(markdown::get_options().await)
+}
+
+pub async fn update_markdown_options(token: Option<String>, options: MarkdownOptions) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(markdown::update_options(options).await)
+}
+
+pub async fn highlight_themes(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(Ok(highlight::themes()))
+}
+
+pub async fn shortcode_templates(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(shortcode::get_templates().await)
+}
+
+pub async fn update_shortcode_templates(
+    token: Option<String>,
+    templates: Vec<ShortcodeTemplate>,
+) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(shortcode::update_templates(templates).await)
+}
+
+// 上传 zip 压缩包导入 Markdown 博客，dry_run=true 时只返回报告
+pub async fn import_archive(
+    token: Option<String>,
+    query: HashMap<String, String>,
+    body: bytes::Bytes,
+) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    let dry_run = query.get("dry_run").map_or(false, |v| v.eq("true") || v.eq("1"));
+    facade::response(import::import_zip(&body, dry_run).await)
+}
+
+pub async fn import_directory(token: Option<String>, params: ImportDirectory) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(import::import_dir(&params.path, params.dry_run).await)
+}
+
+// 上传 WordPress 导出的 WXR 文件，uploads 是服务器上 WordPress 上传目录的路径
+pub async fn import_wordpress(
+    token: Option<String>,
+    query: HashMap<String, String>,
+    body: bytes::Bytes,
+) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    let xml = match String::from_utf8(body.to_vec()) {
+        Ok(s) => s,
+        Err(_) => return facade::response(Err(Error::BadRequest.into())),
+    };
+    let dry_run = query.get("dry_run").map_or(false, |v| v.eq("true") || v.eq("1"));
+    let uploads = query.get("uploads").map(|s| s.as_str());
+    facade::response(wordpress::import_wxr(&xml, uploads, dry_run).await)
+}
+
+// 生成备份并直接下载
+pub async fn backup(token: Option<String>) -> Result<Response, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response::<()>(Err(e)).map(|r| r.into_response());
+    }
+    let result: CommonResult<(PathBuf, Vec<u8>)> = match backup::backup().await {
+        Ok(path) => tokio::fs::read(path.as_path()).await.map(|d| (path, d)).map_err(|e| e.into()),
+        Err(e) => Err(e),
     };
-    match management::update_setting(setting).await {
-        Ok(_) => facade::response(Ok("")),
-        Err(e) => facade::response(Err(e)),
+    match result {
+        Ok((path, data)) => {
+            let filename = path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+            let mut response = Response::new(data.into());
+            let headers = response.headers_mut();
+            headers.append(header::CONTENT_TYPE.as_str(), "application/zip".parse().unwrap());
+            headers.append(
+                header::CONTENT_DISPOSITION.as_str(),
+                format!("attachment; filename=\"{}\"", filename).parse().unwrap(),
+            );
+            Ok(response)
+        },
+        Err(e) => facade::response::<()>(Err(e)).map(|r| r.into_response()),
+    }
+}
+
+pub async fn rerender_posts(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(markdown::rerender_all().await)
+}
+
+pub async fn site_options(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(site::get_options().await)
+}
+
+pub async fn update_site_options(token: Option<String>, options: SiteOptions) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(site::update_options(options).await)
+}
+
+pub async fn site_themes(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
+    }
+    facade::response(site::themes())
+}
+
+pub async fn build_site(token: Option<String>) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_auth(token) {
+        return facade::response(Err(e));
     }
+    facade::response(site::build_local().await)
 }
@@ -1,4 +1,6 @@
 pub(crate) mod asset;
+pub(crate) mod category;
+pub(crate) mod deploy;
 pub(crate) mod export;
 pub(crate) mod git;
 pub(crate) mod image;
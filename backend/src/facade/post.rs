@@ -2,7 +2,7 @@ use core::{result::Result};
 use std::collections::HashMap;
 
 use blog_common::{
-    dto::{post::PostData, user::UserInfo},
+    dto::{markdown::MarkdownPreview, post::{PostAutosave, PostData, PostUnlock}, user::UserInfo},
     result::{Error},
     val,
 };
@@ -13,9 +13,9 @@ use warp::{
 };
 
 use crate::{
-    db::post,
+    db::{autosave, post, post::PostFilter},
     facade::{wrap_json_data, wrap_json_err},
-    service::{image, status},
+    service::{image, markdown, status},
 };
 
 pub async fn new(token: Option<String>) -> Result<impl Reply, Rejection> {
@@ -28,27 +28,119 @@ pub async fn new(token: Option<String>) -> Result<impl Reply, Rejection> {
         .or_else(|e| Ok(wrap_json_err(500, e.0)))
 }
 
-pub async fn list(pagination_type: String, post_id: u64) -> Result<impl Reply, Rejection> {
-    match post::list(pagination_type.as_str(), post_id, val::POSTS_PAGE_SIZE).await {
+// 列表接口都可以通过 ?lang=xx 按语言筛选，未登录时看不到私密的博客
+fn post_filter(query_string: &HashMap<String, String>, user: &Option<UserInfo>) -> PostFilter {
+    PostFilter {
+        language: query_string.get("lang").cloned(),
+        authed: user.is_some(),
+    }
+}
+
+pub async fn list(
+    pagination_type: String,
+    post_id: u64,
+    query_string: HashMap<String, String>,
+    user: Option<UserInfo>,
+) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&query_string, &user);
+    match post::list(pagination_type.as_str(), post_id, val::POSTS_PAGE_SIZE, &filter).await {
         Ok(list) => Ok(wrap_json_data(&list)),
         Err(e) => Ok(wrap_json_err(500, e.0)),
     }
 }
 
-pub async fn seach_by_key(key_word: String, pagination_type: String, post_id: u64) -> Result<impl Reply, Rejection> {
-    match post::seach_by_key(key_word, &pagination_type, post_id, val::POSTS_PAGE_SIZE).await {
+pub async fn seach_by_key(
+    key_word: String,
+    pagination_type: String,
+    post_id: u64,
+    query_string: HashMap<String, String>,
+    user: Option<UserInfo>,
+) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&query_string, &user);
+    match post::seach_by_key(key_word, &pagination_type, post_id, val::POSTS_PAGE_SIZE, &filter).await {
+        Ok(list) => Ok(wrap_json_data(&list)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn list_by_tag(
+    tag: String,
+    pagination_type: String,
+    post_id: u64,
+    query_string: HashMap<String, String>,
+    user: Option<UserInfo>,
+) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&query_string, &user);
+    match post::list_by_tag(tag, &pagination_type, post_id, val::POSTS_PAGE_SIZE, &filter).await {
+        Ok(list) => Ok(wrap_json_data(&list)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn list_by_category(
+    category_id: i64,
+    pagination_type: String,
+    post_id: u64,
+    query_string: HashMap<String, String>,
+    user: Option<UserInfo>,
+) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&query_string, &user);
+    match post::list_by_category(category_id, &pagination_type, post_id, val::POSTS_PAGE_SIZE, &filter).await {
         Ok(list) => Ok(wrap_json_data(&list)),
         Err(e) => Ok(wrap_json_err(500, e.0)),
     }
 }
 
-pub async fn list_by_tag(tag: String, pagination_type: String, post_id: u64) -> Result<impl Reply, Rejection> {
-    match post::list_by_tag(tag, &pagination_type, post_id, val::POSTS_PAGE_SIZE).await {
+pub async fn archive(user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&HashMap::new(), &user);
+    match post::archive(&filter).await {
         Ok(list) => Ok(wrap_json_data(&list)),
         Err(e) => Ok(wrap_json_err(500, e.0)),
     }
 }
 
+pub async fn list_by_month(
+    year: i32,
+    month: u8,
+    pagination_type: String,
+    post_id: u64,
+    query_string: HashMap<String, String>,
+    user: Option<UserInfo>,
+) -> Result<impl Reply, Rejection> {
+    let filter = post_filter(&query_string, &user);
+    match post::list_by_month(year, month, &pagination_type, post_id, val::POSTS_PAGE_SIZE, &filter).await {
+        Ok(list) => Ok(wrap_json_data(&list)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn featured() -> Result<impl Reply, Rejection> {
+    match post::featured(val::FEATURED_POSTS_AMOUNT).await {
+        Ok(list) => Ok(wrap_json_data(&list)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn pin(id: u64, pinned: bool, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match post::update_pinned(id, pinned).await {
+        Ok(_) => Ok(wrap_json_data(&pinned)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn feature(id: u64, featured: bool, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match post::update_featured(id, featured).await {
+        Ok(_) => Ok(wrap_json_data(&featured)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
 pub async fn save(user: Option<UserInfo>, post: PostData) -> Result<impl Reply, Rejection> {
     if user.is_none() {
         return Ok(wrap_json_err(403, Error::NotAuthed));
@@ -59,6 +151,46 @@ pub async fn save(user: Option<UserInfo>, post: PostData) -> Result<impl Reply,
     }
 }
 
+pub async fn autosave(user: Option<UserInfo>, draft: PostAutosave) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match autosave::save(&draft).await {
+        Ok(saved_at) => Ok(wrap_json_data(&saved_at)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn autosave_detail(id: u64, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match autosave::get(id as i64).await {
+        Ok(draft) => Ok(wrap_json_data(&draft)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn discard_autosave(id: u64, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match autosave::discard(id as i64).await {
+        Ok(_) => Ok(wrap_json_data(&id)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
+pub async fn preview(user: Option<UserInfo>, data: MarkdownPreview) -> Result<impl Reply, Rejection> {
+    if user.is_none() {
+        return Ok(wrap_json_err(403, Error::NotAuthed));
+    }
+    match markdown::render(&data.content).await {
+        Ok(rendered) => Ok(wrap_json_data(&rendered.html)),
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
 pub async fn show(
     token: Option<String>,
     id: u64,
@@ -70,7 +202,8 @@ pub async fn show(
         return Ok(wrap_json_err(500, auth_result.unwrap_err().0));
     }
     let editable = auth_result.is_ok() && edit;
-    match post::show(id, editable).await {
+    let grant = query_string.get("grant").cloned();
+    match post::show(id, editable, auth_result.is_ok(), grant).await {
         Ok(mut blog) => {
             blog.editable = editable;
             Ok(wrap_json_data(&blog))
@@ -79,6 +212,26 @@ pub async fn show(
     }
 }
 
+pub async fn unlock(id: u64, data: PostUnlock) -> Result<impl Reply, Rejection> {
+    if let Err(e) = status::check_unlock_attempt(id as i64) {
+        return Ok(wrap_json_err(500, e.0));
+    }
+    match post::unlock(id, &data.password).await {
+        Ok(true) => {
+            status::clear_unlock_failures(id as i64);
+            Ok(wrap_json_data(&status::grant_post_access(id as i64)))
+        },
+        Ok(false) => {
+            status::record_unlock_failure(id as i64);
+            Ok(wrap_json_err(
+                500,
+                Error::BusinessException(String::from("密码不正确/Wrong password.")),
+            ))
+        },
+        Err(e) => Ok(wrap_json_err(500, e.0)),
+    }
+}
+
 pub async fn delete(id: u64, user: Option<UserInfo>) -> Result<impl Reply, Rejection> {
     if user.is_some() {
         if let Err(e) = image::delete_post_images(id).await {
@@ -1,3 +1,4 @@
+#![recursion_limit = "256"]
 // #![feature(const_fn)]
 // #![feature(const_mut_refs)]
 // #![feature(trait_alias)]
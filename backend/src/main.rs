@@ -1,4 +1,4 @@
-#![recursion_limit = "256"]
+#![recursion_limit = "512"]
 
 use std::{net::SocketAddr, env};
 
@@ -38,6 +38,65 @@ fn main() -> result::Result<()> {
         .thread_stack_size(1024 * 1024)
         .build()?;
 
+    if let Some(archive) = args.restore.as_ref() {
+        let archive = std::path::Path::new(archive);
+        let manifest = if args.dry_run {
+            service::backup::verify(archive)?
+        } else {
+            service::backup::restore(archive)?
+        };
+        println!(
+            "Backup created at {} by version {}, schema version {}, {} files verified",
+            manifest.created_at,
+            manifest.app_version,
+            manifest.schema_version,
+            manifest.files.len()
+        );
+        if !args.dry_run {
+            // 打开数据库时会自动升级旧版本的表结构
+            println!("Initializing database connection...");
+            runtime.block_on(db::init_datasource());
+            runtime.block_on(db::shutdown());
+            println!("Restored");
+        }
+        return Ok(());
+    }
+
+    if args.backup {
+        println!("Initializing database connection...");
+        runtime.block_on(db::init_datasource());
+        let path = runtime.block_on(service::backup::backup());
+        runtime.block_on(db::shutdown());
+        println!("Backup written to {}", path?.display());
+        return Ok(());
+    }
+
+    if args.import.is_some() || args.import_wordpress.is_some() {
+        println!("Initializing database connection...");
+        runtime.block_on(db::init_datasource());
+        let report = match args.import_wordpress.as_ref() {
+            Some(xml_path) => {
+                let xml = std::fs::read_to_string(xml_path)?;
+                runtime.block_on(service::wordpress::import_wxr(&xml, args.uploads.as_deref(), args.dry_run))
+            },
+            None => runtime.block_on(service::import::import_path(args.import.as_ref().unwrap(), args.dry_run)),
+        };
+        runtime.block_on(db::shutdown());
+        let report = report?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        let imported = report.posts.iter().filter(|p| p.id.is_some()).count();
+        println!(
+            "{} posts found, {} imported, {} skipped",
+            report.posts.len(),
+            imported,
+            report.skipped.len()
+        );
+        if let Some(mapping_file) = report.mapping_file.as_ref() {
+            println!("URL mapping written to .{}", mapping_file);
+        }
+        return Ok(());
+    }
+
     let (tx, rx1) = broadcast::channel(2);
     let rx2 = tx.subscribe();
     runtime.spawn(async move {
@@ -69,6 +128,7 @@ fn main() -> result::Result<()> {
     } else {
         println!("Initializing database connection...");
         runtime.block_on(db::init_datasource());
+        runtime.spawn(service::deploy::schedule());
 
         println!("Creating server instance...");
         let mut servers: Vec<BoxFuture<()>> = Vec::new();
@@ -18,6 +18,16 @@ CREATE INDEX post_id_IDX ON tags_usage (post_id);
 CREATE INDEX tag_id_IDX ON tags_usage (tag_id);
 -- CREATE UNIQUE INDEX blog_id_tag_id_IDX ON using_tag (blog_id,tag_id);
 
+CREATE TABLE categories (
+id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+name TEXT(32) NOT NULL,
+parent_id INTEGER,
+created_at INTEGER NOT NULL,
+updated_at INTEGER,
+CONSTRAINT "category_UN" UNIQUE ("parent_id" ASC, "name" ASC)
+);
+CREATE INDEX parent_id_IDX ON categories (parent_id);
+
 CREATE TABLE posts (
 id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
 title TEXT(64) NOT NULL,
@@ -27,7 +37,24 @@ rendered_content TEXT(65535) NOT NULL,
 created_at INTEGER NOT NULL,
 updated_at INTEGER,
 is_deleted INTEGER DEFAULT 0 NOT NULL,
-deleted_at INTEGER
+deleted_at INTEGER,
+category_id INTEGER,
+toc TEXT(4096) DEFAULT '' NOT NULL,
+pinned INTEGER DEFAULT 0 NOT NULL,
+featured INTEGER DEFAULT 0 NOT NULL,
+language TEXT(16) DEFAULT '' NOT NULL,
+translation_group INTEGER,
+visibility TEXT(16) DEFAULT 'public' NOT NULL,
+password TEXT(256) DEFAULT '' NOT NULL
+);
+CREATE INDEX category_id_IDX ON posts (category_id);
+CREATE INDEX translation_group_IDX ON posts (translation_group);
+
+CREATE TABLE post_autosaves (
+post_id INTEGER NOT NULL PRIMARY KEY,
+title TEXT(64) NOT NULL,
+markdown_content TEXT(65535) NOT NULL,
+saved_at INTEGER NOT NULL
 );
 
 CREATE TABLE settings (
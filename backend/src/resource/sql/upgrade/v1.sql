@@ -0,0 +1,12 @@
+CREATE TABLE categories (
+id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+name TEXT(32) NOT NULL,
+parent_id INTEGER,
+created_at INTEGER NOT NULL,
+updated_at INTEGER,
+CONSTRAINT "category_UN" UNIQUE ("parent_id" ASC, "name" ASC)
+);
+CREATE INDEX parent_id_IDX ON categories (parent_id);
+
+ALTER TABLE posts ADD COLUMN category_id INTEGER;
+CREATE INDEX category_id_IDX ON posts (category_id);
@@ -0,0 +1 @@
+ALTER TABLE posts ADD COLUMN toc TEXT(4096) DEFAULT '' NOT NULL;
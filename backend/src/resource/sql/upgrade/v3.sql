@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN pinned INTEGER DEFAULT 0 NOT NULL;
+ALTER TABLE posts ADD COLUMN featured INTEGER DEFAULT 0 NOT NULL;
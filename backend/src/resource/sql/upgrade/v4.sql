@@ -0,0 +1,3 @@
+ALTER TABLE posts ADD COLUMN language TEXT(16) DEFAULT '' NOT NULL;
+ALTER TABLE posts ADD COLUMN translation_group INTEGER;
+CREATE INDEX translation_group_IDX ON posts (translation_group);
@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN visibility TEXT(16) DEFAULT 'public' NOT NULL;
+ALTER TABLE posts ADD COLUMN password TEXT(256) DEFAULT '' NOT NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE post_autosaves (
+post_id INTEGER NOT NULL PRIMARY KEY,
+title TEXT(64) NOT NULL,
+markdown_content TEXT(65535) NOT NULL,
+saved_at INTEGER NOT NULL
+);
@@ -0,0 +1,355 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use blog_common::{result::Error, util::time};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    db,
+    util::{crypt, result::Result},
+};
+
+// 备份文件格式有变动时加一
+const FORMAT_VERSION: u32 = 2;
+const MANIFEST_NAME: &str = "manifest.json";
+const DB_FILE: &str = "blog.dat";
+// sqlx 默认使用 WAL，恢复时要一起挪走，否则旧的 WAL 会被应用到恢复的数据库上
+const DB_JOURNAL_FILES: [&str; 2] = ["blog.dat-wal", "blog.dat-shm"];
+const UPLOAD_DIR: &str = "upload";
+// 导出的文件、Git Pages 的仓库和 SSH 密钥，密钥和保存的凭据用 server.secret 加密，必须一起备份
+const DATA_DIRS: [&str; 3] = [UPLOAD_DIR, "export", "git-pages"];
+const BACKUP_DIR: &str = "backup";
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BackupFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub schema_version: i64,
+    pub created_at: u64,
+    pub files: Vec<BackupFile>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::BusinessException(format!("备份文件不正确/Invalid backup: {}", message))
+}
+
+// 返回 (压缩包里的路径, 文件路径)
+pub(crate) fn list_files(root: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+    if !root.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &name, files)?;
+        } else if file_type.is_file() {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn add_file(
+    zip: &mut ZipWriter<File>,
+    options: FileOptions,
+    name: &str,
+    data: &[u8],
+    manifest_files: &mut Vec<BackupFile>,
+) -> Result<()> {
+    zip.start_file(name, options)?;
+    zip.write_all(data)?;
+    manifest_files.push(BackupFile {
+        path: name.to_string(),
+        size: data.len() as u64,
+        sha256: sha256_hex(data),
+    });
+    Ok(())
+}
+
+fn write_archive(root: &Path, path: &Path, snapshot: &Path, schema_version: i64, created_at: u64) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // 图片本身已经压缩过了
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut files = Vec::new();
+    add_file(&mut zip, deflated, DB_FILE, &std::fs::read(snapshot)?, &mut files)?;
+
+    let secret = root.join(crypt::SECRET_FILE);
+    if secret.is_file() {
+        add_file(&mut zip, deflated, crypt::SECRET_FILE, &std::fs::read(secret)?, &mut files)?;
+    }
+    for dir in DATA_DIRS {
+        let mut list = Vec::new();
+        list_files(&root.join(dir), dir, &mut list)?;
+        list.sort();
+        let options = if dir == UPLOAD_DIR { stored } else { deflated };
+        for (name, file) in list.iter() {
+            add_file(&mut zip, options, name, &std::fs::read(file)?, &mut files)?;
+        }
+    }
+
+    let manifest = BackupManifest {
+        format_version: FORMAT_VERSION,
+        app_version: String::from(env!("CARGO_PKG_VERSION")),
+        schema_version,
+        created_at,
+        files,
+    };
+    zip.start_file(MANIFEST_NAME, deflated)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+// 生成 backup/blog-backup-<时间>.zip，包含数据库快照、server.secret、DATA_DIRS 里的文件和 manifest.json
+pub async fn backup() -> Result<PathBuf> {
+    backup_from(std::env::current_dir()?).await
+}
+
+async fn backup_from(root: PathBuf) -> Result<PathBuf> {
+    let dir = root.join(BACKUP_DIR);
+    if !dir.exists() {
+        tokio::fs::create_dir_all(dir.as_path()).await?;
+    }
+    let created_at = time::unix_epoch_sec();
+    let snapshot = dir.join(format!("blog-{}.dat.tmp", created_at));
+    // 快照要写到一个新的数据库文件里
+    if snapshot.exists() {
+        std::fs::remove_file(snapshot.as_path())?;
+    }
+    let schema_version = db::schema_version().await?;
+    db::snapshot(snapshot.as_path()).await?;
+
+    let path = dir.join(format!("blog-backup-{}.zip", created_at));
+    // 读写文件和压缩都是阻塞的，不要占用异步运行时的线程
+    tokio::task::spawn_blocking(move || {
+        let result = write_archive(&root, path.as_path(), snapshot.as_path(), schema_version, created_at);
+        if let Err(e) = std::fs::remove_file(snapshot.as_path()) {
+            eprintln!("{:?}", e);
+        }
+        if result.is_err() && path.exists() {
+            std::fs::remove_file(path.as_path())?;
+        }
+        result.map(|_| path)
+    })
+    .await
+    .map_err(|e| Error::BusinessException(format!("备份失败/Backup failed: {}", e)))?
+}
+
+// 只允许恢复数据库、server.secret 和 DATA_DIRS 下的文件，防止解压到其它地方
+fn is_allowed(path: &str) -> bool {
+    if path == DB_FILE || path == crypt::SECRET_FILE {
+        return true;
+    }
+    let mut parts = path.split('/');
+    let top = parts.next().unwrap_or("");
+    DATA_DIRS.contains(&top) && path.len() > top.len() && !parts.any(|p| p == ".." || p.is_empty())
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>> {
+    let mut file = archive
+        .by_name(name)
+        .map_err(|_| invalid(&format!("缺少文件/missing {}", name)))?;
+    let mut data = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// 校验 manifest 里的每个文件，target 不为空时同时解压到 target 目录
+fn unpack(archive_path: &Path, target: Option<&Path>) -> Result<BackupManifest> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let manifest = read_entry(&mut archive, MANIFEST_NAME)?;
+    let manifest: BackupManifest =
+        serde_json::from_slice(&manifest).map_err(|_| invalid("manifest.json"))?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(invalid("备份格式太新/backup format is newer than this program").into());
+    }
+    if manifest.schema_version > db::latest_schema_version() as i64 {
+        return Err(invalid("数据库版本太新/database schema is newer than this program").into());
+    }
+    if !manifest.files.iter().any(|f| f.path == DB_FILE) {
+        return Err(invalid("缺少数据库/missing blog.dat").into());
+    }
+    for f in manifest.files.iter() {
+        if !is_allowed(&f.path) {
+            return Err(invalid(&f.path).into());
+        }
+        let data = read_entry(&mut archive, &f.path)?;
+        if data.len() as u64 != f.size || sha256_hex(&data) != f.sha256 {
+            return Err(invalid(&format!("校验失败/checksum mismatch {}", f.path)).into());
+        }
+        if let Some(target) = target {
+            let path = target.join(&f.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, &data)?;
+        }
+    }
+    Ok(manifest)
+}
+
+pub fn verify(archive_path: &Path) -> Result<BackupManifest> {
+    unpack(archive_path, None)
+}
+
+// 需要在服务停止时执行；原来的数据库、server.secret 和 DATA_DIRS 改名为 *.before-restore-<时间> 保留下来
+// 恢复之后调用 db::init_datasource 会自动升级旧版本的数据库
+pub fn restore(archive_path: &Path) -> Result<BackupManifest> {
+    restore_into(archive_path, &std::env::current_dir()?)
+}
+
+fn restore_into(archive_path: &Path, root: &Path) -> Result<BackupManifest> {
+    let suffix = format!("before-restore-{}", time::unix_epoch_sec());
+    let staging = root.join(format!("restore-{}", time::unix_epoch_sec()));
+    let manifest = match unpack(archive_path, Some(staging.as_path())) {
+        Ok(m) => m,
+        Err(e) => {
+            if staging.exists() {
+                std::fs::remove_dir_all(staging.as_path())?;
+            }
+            return Err(e);
+        },
+    };
+    // 旧版本的备份没有 server.secret 和其它目录，这时保留现有的
+    let mut replaced = vec![DB_FILE];
+    replaced.extend(DB_JOURNAL_FILES);
+    replaced.extend(
+        [crypt::SECRET_FILE]
+            .into_iter()
+            .chain(DATA_DIRS)
+            .filter(|name| *name == UPLOAD_DIR || staging.join(name).exists()),
+    );
+    let mut renames = Vec::new();
+    for name in replaced {
+        let current = root.join(name);
+        if current.exists() {
+            renames.push((current, root.join(format!("{}.{}", name, suffix))));
+        }
+    }
+    for name in [DB_FILE, crypt::SECRET_FILE].into_iter().chain(DATA_DIRS) {
+        let staged = staging.join(name);
+        if staged.exists() {
+            renames.push((staged, root.join(name)));
+        }
+    }
+    let result = rename_all(&renames);
+    std::fs::remove_dir_all(staging.as_path())?;
+    result?;
+    std::fs::create_dir_all(root.join(UPLOAD_DIR))?;
+    Ok(manifest)
+}
+
+// 按顺序改名，中途失败时把已经改名的文件倒序改回去，不留下一半新一半旧的数据
+fn rename_all(renames: &[(PathBuf, PathBuf)]) -> Result<()> {
+    for (i, (from, to)) in renames.iter().enumerate() {
+        if let Err(e) = std::fs::rename(from, to) {
+            for (from, to) in renames[..i].iter().rev() {
+                if let Err(e) = std::fs::rename(to, from) {
+                    eprintln!("{:?}", e);
+                }
+            }
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Row};
+
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn allowed_paths() {
+        assert!(is_allowed("blog.dat"));
+        assert!(is_allowed("server.secret"));
+        assert!(is_allowed("upload/2021/08/a.png"));
+        assert!(is_allowed("git-pages/blog/.git/HEAD"));
+        assert!(is_allowed("export/site.zip"));
+
+        assert!(!is_allowed("upload"));
+        assert!(!is_allowed("upload/../blog.dat"));
+        assert!(!is_allowed("upload//a.png"));
+        assert!(!is_allowed("backup/blog-backup-1.zip"));
+        assert!(!is_allowed("/etc/passwd"));
+    }
+
+    #[test]
+    fn roll_back_renames() {
+        let dir = testing::temp_dir("backup-rollback");
+        std::fs::write(dir.join("a"), "a").unwrap();
+        std::fs::write(dir.join("b"), "b").unwrap();
+        let renames = vec![
+            (dir.join("a"), dir.join("a.old")),
+            (dir.join("missing"), dir.join("missing.old")),
+            (dir.join("b"), dir.join("b.old")),
+        ];
+
+        assert!(rename_all(&renames).is_err());
+        assert_eq!("a", std::fs::read_to_string(dir.join("a")).unwrap());
+        assert_eq!("b", std::fs::read_to_string(dir.join("b")).unwrap());
+        assert!(!dir.join("a.old").exists());
+    }
+
+    #[test]
+    fn backup_verify_restore() {
+        let source = testing::temp_dir("backup-source");
+        std::fs::write(source.join(crypt::SECRET_FILE), "secret").unwrap();
+        std::fs::create_dir_all(source.join("upload/2021")).unwrap();
+        std::fs::write(source.join("upload/2021/a.png"), "png").unwrap();
+        let archive = testing::block_on(backup_from(source.clone())).unwrap();
+
+        let manifest = verify(&archive).unwrap();
+        let schema_version = testing::block_on(db::schema_version()).unwrap();
+        assert_eq!(schema_version, manifest.schema_version);
+        assert_eq!(3, manifest.files.len());
+
+        let target = testing::temp_dir("backup-target");
+        std::fs::write(target.join(DB_FILE), "old database").unwrap();
+        std::fs::create_dir_all(target.join("upload")).unwrap();
+        std::fs::write(target.join("upload/old.png"), "old").unwrap();
+        restore_into(&archive, &target).unwrap();
+
+        assert_eq!("secret", std::fs::read_to_string(target.join(crypt::SECRET_FILE)).unwrap());
+        assert_eq!("png", std::fs::read_to_string(target.join("upload/2021/a.png")).unwrap());
+        assert!(!target.join("upload/old.png").exists());
+        let kept = std::fs::read_dir(&target)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("blog.dat.before-restore-") || name.starts_with("upload.before-restore-"))
+            .count();
+        assert_eq!(2, kept);
+        // 恢复的数据库是完整的 SQLite 数据库
+        let restored = testing::block_on(async {
+            let mut conn = SqliteConnectOptions::new()
+                .filename(target.join(DB_FILE))
+                .connect()
+                .await
+                .unwrap();
+            let row = sqlx::query("PRAGMA user_version").fetch_one(&mut conn).await.unwrap();
+            row.get::<i64, _>(0)
+        });
+        assert_eq!(schema_version, restored);
+    }
+}
@@ -0,0 +1,646 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use blog_common::{
+    dto::{
+        deploy::{DeployDestination, DeployTarget},
+        git::{GitPushInfo, GitPushResult, GitRepositoryInfo, PublishJob, PublishState},
+    },
+    result::Error,
+    util::time,
+};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use regex::Regex;
+
+use crate::{
+    db::{management, model::Setting},
+    service::{
+        backup, export,
+        git::{credential, git, publish, publish::JobContext, ssh},
+    },
+    util::{common, result::Result, val},
+};
+
+// rsync 和 sftp 目标先导出到这个目录
+const STAGING_DIR: &str = "deploy";
+// 检查定时部署的间隔（秒）
+const SCHEDULE_INTERVAL: u64 = 60;
+
+lazy_static! {
+    static ref TARGETS: RwLock<Option<Vec<DeployTarget>>> = RwLock::new(None);
+    static ref NAME_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_\-]{1,64}$").unwrap();
+}
+
+pub async fn targets() -> Result<Vec<DeployTarget>> {
+    let cached = TARGETS.read().clone();
+    if let Some(t) = cached {
+        return Ok(t);
+    }
+    let targets = match management::get_setting(val::DEPLOY_TARGETS_SETTING).await? {
+        Some(setting) if !setting.content.is_empty() => serde_json::from_str::<Vec<DeployTarget>>(&setting.content)?,
+        _ => Vec::new(),
+    };
+    *TARGETS.write() = Some(targets.clone());
+    Ok(targets)
+}
+
+async fn save_targets(targets: Vec<DeployTarget>) -> Result<()> {
+    let setting = Setting {
+        item: String::from(val::DEPLOY_TARGETS_SETTING),
+        content: serde_json::to_string(&targets)?,
+    };
+    management::update_setting(setting).await?;
+    *TARGETS.write() = Some(targets);
+    Ok(())
+}
+
+fn invalid(message: &str) -> Error {
+    Error::BusinessException(String::from(message))
+}
+
+fn not_found() -> Error {
+    invalid("找不到部署目标/Deploy target not found.")
+}
+
+fn require_deploy_key() -> Result<()> {
+    match ssh::deploy_key()? {
+        Some(_) => Ok(()),
+        None => Err(invalid("请先生成或上传部署密钥/Please generate or upload a deploy key first.").into()),
+    }
+}
+
+fn validate(target: &DeployTarget) -> Result<()> {
+    if !NAME_REGEX.is_match(&target.name) {
+        return Err(
+            invalid("名称只能包含字母、数字、'-' 和 '_'/Name may only contain letters, digits, '-' and '_'.").into(),
+        );
+    }
+    if !target.exporter.is_empty() && export::exporter(&target.exporter).is_none() {
+        return Err(invalid("不支持的导出格式/Unknown export format.").into());
+    }
+    export::export_root(Path::new(""), &target.subdirectory)?;
+    match &target.destination {
+        DeployDestination::Git {
+            remote_url,
+            branch,
+            user_name,
+            email,
+            ..
+        } => {
+            let is_ssh = ssh::is_ssh_url(remote_url);
+            let supported = remote_url.starts_with("http://")
+                || remote_url.starts_with("https://")
+                || remote_url.starts_with("file://")
+                || is_ssh
+                || Path::new(remote_url).is_absolute();
+            if !supported {
+                return Err(invalid("输入的仓库地址不合法/Illegal repository address.").into());
+            }
+            if !git2::Reference::is_valid_name(&format!("refs/heads/{}", branch)) {
+                return Err(invalid("分支名称不正确/Invalid branch name.").into());
+            }
+            if user_name.trim().is_empty() {
+                return Err(invalid("UserName must not be empty.").into());
+            }
+            if !common::EMAIL_REGEX.is_match(email) {
+                return Err(invalid("输入的邮箱地址不合法/Invalid email address.").into());
+            }
+            if is_ssh {
+                require_deploy_key()?;
+            }
+        },
+        DeployDestination::Local { path } => {
+            if !Path::new(path).is_absolute() {
+                return Err(invalid("目录必须是绝对路径/The directory must be an absolute path.").into());
+            }
+        },
+        DeployDestination::Rsync { destination, .. } | DeployDestination::Sftp { destination, .. } => {
+            // 不能是本地路径，也不能被当成 rsync/sftp 的参数
+            if destination.starts_with('-') || !destination.contains(':') {
+                return Err(
+                    invalid("目标地址应该像 user@host:/path/Destination should be like user@host:/path.").into(),
+                );
+            }
+            require_deploy_key()?;
+        },
+    }
+    Ok(())
+}
+
+// Git 目标克隆在 git-pages/deploy-{name}，和 Git Pages 的仓库分开
+fn repository_info(target: &DeployTarget) -> Option<GitRepositoryInfo> {
+    match &target.destination {
+        DeployDestination::Git {
+            remote_url,
+            branch,
+            user_name,
+            email,
+            conflict_strategy,
+            ..
+        } => Some(GitRepositoryInfo {
+            name: user_name.clone(),
+            email: email.clone(),
+            remote_url: remote_url.clone(),
+            repository_name: format!("deploy-{}", target.name),
+            branch_name: Some(branch.clone()),
+            conflict_strategy: *conflict_strategy,
+        }),
+        _ => None,
+    }
+}
+
+fn staging_dir(name: &str) -> Result<PathBuf> {
+    Ok(std::env::current_dir()?.join(STAGING_DIR).join(name))
+}
+
+// 删除本地的克隆或者暂存目录，目录里只有导出的文件，下次部署时重新生成
+fn remove_workdir(target: &DeployTarget) -> Result<()> {
+    let dir = match repository_info(target) {
+        Some(info) => git::get_repository_path(&info),
+        None => staging_dir(&target.name)?,
+    };
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+fn remote_url(target: &DeployTarget) -> Option<&str> {
+    match &target.destination {
+        DeployDestination::Git { remote_url, .. } => Some(remote_url),
+        _ => None,
+    }
+}
+
+// 新增或者修改（按名称），返回所有目标
+pub async fn save_target(mut target: DeployTarget) -> Result<Vec<DeployTarget>> {
+    target.name = target.name.trim().to_string();
+    validate(&target)?;
+    let entered = match &mut target.destination {
+        DeployDestination::Git { credential, .. } => std::mem::take(credential),
+        _ => String::new(),
+    };
+    let mut targets = targets().await?;
+    // 仓库地址变了，旧的密码/令牌不能发给新的地址
+    let mut forget = remote_url(&target).is_none();
+    match targets.iter_mut().find(|t| t.name == target.name) {
+        Some(t) => {
+            if publish::is_running(&t.name) {
+                return Err(invalid("该目标正在部署/The target is being deployed.").into());
+            }
+            // 地址或者分支变了，旧的克隆不能再用
+            if serde_json::to_string(&t.destination)? != serde_json::to_string(&target.destination)? {
+                remove_workdir(t)?;
+            }
+            forget = forget || remote_url(t) != remote_url(&target);
+            target.last_deploy_second = t.last_deploy_second;
+            *t = target.clone();
+        },
+        None => {
+            target.last_deploy_second = 0;
+            targets.push(target.clone());
+        },
+    }
+    if forget {
+        credential::forget_for_target(&target.name)?;
+    }
+    if !entered.trim().is_empty() {
+        credential::save_for_target(&target.name, &entered)?;
+    }
+    save_targets(targets.clone()).await?;
+    Ok(targets)
+}
+
+pub async fn remove_target(name: &str) -> Result<Vec<DeployTarget>> {
+    let mut targets = targets().await?;
+    let index = targets.iter().position(|t| t.name == name).ok_or_else(not_found)?;
+    if publish::is_running(name) {
+        return Err(invalid("该目标正在部署/The target is being deployed.").into());
+    }
+    let target = targets.remove(index);
+    remove_workdir(&target)?;
+    credential::forget_for_target(&target.name)?;
+    save_targets(targets.clone()).await?;
+    Ok(targets)
+}
+
+pub async fn deploy_now(name: &str) -> Result<PublishJob> {
+    let mut targets = targets().await?;
+    let target = targets.iter_mut().find(|t| t.name == name).ok_or_else(not_found)?;
+    if publish::is_running(name) {
+        return Err(invalid("该目标正在部署/The target is being deployed.").into());
+    }
+    target.last_deploy_second = time::unix_epoch_sec() as i64;
+    let target = target.clone();
+    save_targets(targets).await?;
+    let (name, exporter, subdirectory) = (
+        target.name.clone(),
+        target.exporter.clone(),
+        target.subdirectory.clone(),
+    );
+    Ok(publish::spawn(
+        &name,
+        &exporter,
+        target.render_html,
+        &subdirectory,
+        move |ctx| async move { deploy(&ctx, &target).await },
+    ))
+}
+
+async fn deploy(ctx: &JobContext, target: &DeployTarget) -> Result<GitPushResult> {
+    match &target.destination {
+        DeployDestination::Git { .. } => deploy_git(ctx, target).await,
+        DeployDestination::Local { path } => {
+            ctx.set_state(PublishState::Exporting);
+            let root = export::export_root(Path::new(path), &target.subdirectory)?;
+            std::fs::create_dir_all(&root)?;
+            let result = export::to_directory(&root, &target.exporter, target.render_html).await?;
+            ctx.log_export(&result);
+            Ok(result)
+        },
+        DeployDestination::Rsync { destination, port } | DeployDestination::Sftp { destination, port } => {
+            ctx.set_state(PublishState::Exporting);
+            let staging = staging_dir(&target.name)?;
+            std::fs::create_dir_all(&staging)?;
+            let result = export::to_directory(&staging, &target.exporter, target.render_html).await?;
+            ctx.log_export(&result);
+
+            ctx.check_cancelled()?;
+            ctx.set_state(PublishState::Pushing);
+            let subdirectory = target.subdirectory.trim().trim_matches('/');
+            let destination = if subdirectory.is_empty() {
+                destination.clone()
+            } else {
+                format!("{}/{}", destination.trim_end_matches('/'), subdirectory)
+            };
+            let (c, name, port) = (ctx.clone(), target.name.clone(), *port);
+            let use_sftp = matches!(target.destination, DeployDestination::Sftp { .. });
+            let removed = result.changes.removed.clone();
+            tokio::task::spawn_blocking(move || {
+                if use_sftp {
+                    sftp(&c, &name, &staging, &destination, port, &removed)
+                } else {
+                    rsync(&c, &name, &staging, &destination, port)
+                }
+            })
+            .await
+            .map_err(|e| Error::BusinessException(format!("Failed to upload: {}", e)))??;
+            Ok(result)
+        },
+    }
+}
+
+// 只使用这个目标自己保存的密码/令牌
+async fn deploy_git(ctx: &JobContext, target: &DeployTarget) -> Result<GitPushResult> {
+    let info = repository_info(target).ok_or_else(not_found)?;
+    let password = credential::load_for_target(&target.name)?.unwrap_or_default();
+    if !git::get_repository_path(&info).exists() {
+        ctx.log(format!("Cloning {}", info.remote_url));
+        let (i, p) = (info.clone(), password.clone());
+        tokio::task::spawn_blocking(move || {
+            let path = git::get_repository_path(&i);
+            let branch = i.branch_name.as_deref().unwrap_or("main");
+            let result = git::clone_repository(&i, &path, &p).and_then(|repo| git::checkout_branch(&repo, branch));
+            if result.is_err() {
+                let _ = std::fs::remove_dir_all(&path);
+            }
+            result
+        })
+        .await
+        .map_err(|e| Error::BusinessException(format!("Failed to clone repository: {}", e)))?
+        .map_err(|e| Error::BusinessException(format!("Failed to clone repository: {}", e)))?;
+    }
+    let push_info = GitPushInfo {
+        subdirectory: target.subdirectory.clone(),
+        render_html: target.render_html,
+        exporter: target.exporter.clone(),
+        repo_credential: password,
+        save_credential: false,
+    };
+    publish::publish(ctx, &info, &push_info).await
+}
+
+// 私钥只在 rsync/sftp 运行期间写到磁盘上
+fn with_private_key<F>(name: &str, run: F) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let key_file = ssh::key_dir()?.join(format!("deploy-{}.key", name));
+    if !ssh::write_private_key(&key_file)? {
+        return Err(invalid("请先生成或上传部署密钥/Please generate or upload a deploy key first.").into());
+    }
+    let result = run(&key_file);
+    let _ = std::fs::remove_file(&key_file);
+    result
+}
+
+fn rsync(ctx: &JobContext, name: &str, staging: &Path, destination: &str, port: u16) -> Result<()> {
+    with_private_key(name, |key_file| {
+        // 只信任 Git Pages 里配置的 known_hosts
+        let shell = format!(
+            "ssh -p {} -i '{}' -o IdentitiesOnly=yes -o StrictHostKeyChecking=yes -o UserKnownHostsFile='{}' -o BatchMode=yes",
+            port,
+            key_file.display(),
+            ssh::known_hosts_path()?.display()
+        );
+        // 末尾的 '/' 表示同步目录里的内容
+        let mut source = staging.as_os_str().to_owned();
+        source.push("/");
+        ctx.log(format!("rsync to {}", destination));
+        let mut command = Command::new("rsync");
+        command
+            .args([
+                "-rlptz",
+                "--delete",
+                "--itemize-changes",
+                "--exclude=.blog-export.json",
+                "-e",
+            ])
+            .arg(shell)
+            .arg(source)
+            .arg(destination);
+        run_command(ctx, "rsync", command, None)
+    })
+}
+
+// sftp 批处理里的路径用双引号括起来
+fn sftp_quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// 先创建目录再上传所有文件，最后删除这次没有导出的文件；'-' 开头的命令出错时继续执行
+fn sftp_batch(remote_root: &str, files: &[(String, PathBuf)], removed: &[String]) -> String {
+    let remote = |path: &str| sftp_quote(&format!("{}/{}", remote_root.trim_end_matches('/'), path));
+    let mut dirs = Vec::new();
+    for (path, _) in files.iter() {
+        let mut dir = Path::new(path).parent();
+        while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+            dirs.push(d.to_string_lossy().to_string());
+            dir = d.parent();
+        }
+    }
+    dirs.sort();
+    dirs.dedup();
+    let mut batch = format!("-mkdir {}\n", sftp_quote(remote_root.trim_end_matches('/')));
+    for dir in dirs.iter() {
+        batch.push_str(&format!("-mkdir {}\n", remote(dir)));
+    }
+    for (path, file) in files.iter() {
+        batch.push_str(&format!(
+            "put {} {}\n",
+            sftp_quote(&file.to_string_lossy()),
+            remote(path)
+        ));
+    }
+    for path in removed.iter() {
+        batch.push_str(&format!("-rm {}\n", remote(path)));
+    }
+    batch
+}
+
+fn sftp(ctx: &JobContext, name: &str, staging: &Path, destination: &str, port: u16, removed: &[String]) -> Result<()> {
+    let (host, remote_root) = destination.split_once(':').unwrap_or((destination, ""));
+    let remote_root = if remote_root.is_empty() { "." } else { remote_root };
+    let mut files = Vec::new();
+    backup::list_files(staging, "", &mut files)?;
+    let files = files
+        .into_iter()
+        .map(|(path, file)| (path.trim_start_matches('/').to_string(), file))
+        .filter(|(path, _)| path != ".blog-export.json")
+        .collect::<Vec<_>>();
+    let batch = sftp_batch(remote_root, &files, removed);
+    with_private_key(name, |key_file| {
+        ctx.log(format!("sftp {} files to {}", files.len(), destination));
+        let mut command = Command::new("sftp");
+        command
+            .args(["-b", "-", "-P", &port.to_string(), "-i"])
+            .arg(key_file)
+            .args(["-o", "IdentitiesOnly=yes", "-o", "StrictHostKeyChecking=yes", "-o"])
+            .arg(format!("UserKnownHostsFile={}", ssh::known_hosts_path()?.display()))
+            .arg(host);
+        run_command(ctx, "sftp", command, Some(batch))
+    })
+}
+
+// 输出写到任务日志里，取消任务时结束进程
+fn run_command(ctx: &JobContext, program: &str, mut command: Command, input: Option<String>) -> Result<()> {
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::BusinessException(format!("Failed to run {}: {}", program, e)))?;
+    let outputs: Vec<Box<dyn Read + Send>> = vec![
+        Box::new(child.stdout.take().unwrap()),
+        Box::new(child.stderr.take().unwrap()),
+    ];
+    let mut threads = outputs
+        .into_iter()
+        .map(|output| {
+            let ctx = ctx.clone();
+            std::thread::spawn(move || {
+                for line in BufReader::new(output).lines().map_while(|line| line.ok()) {
+                    ctx.log(line);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        // 写完之后关闭 stdin，sftp 才会退出
+        threads.push(std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        }));
+    }
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if ctx.is_cancelled() {
+            let _ = child.kill();
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    };
+    for thread in threads {
+        let _ = thread.join();
+    }
+    ctx.check_cancelled()?;
+    if status.success() {
+        ctx.log("Synced");
+        Ok(())
+    } else {
+        Err(Error::BusinessException(format!("{} 失败/{} failed: {}", program, program, status)).into())
+    }
+}
+
+// 定时部署，在后台一直运行
+pub async fn schedule() {
+    let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULE_INTERVAL));
+    loop {
+        interval.tick().await;
+        let targets = match targets().await {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("Failed to load deploy targets: {:?}", e.0);
+                continue;
+            },
+        };
+        let now = time::unix_epoch_sec() as i64;
+        for target in targets.iter().filter(|t| {
+            t.schedule_minutes > 0
+                && now - t.last_deploy_second >= t.schedule_minutes as i64 * 60
+                && !publish::is_running(&t.name)
+        }) {
+            if let Err(e) = deploy_now(&target.name).await {
+                eprintln!("Failed to deploy {}: {:?}", target.name, e.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::git::ConflictStrategy;
+
+    use super::*;
+    use crate::{db::post, util::testing};
+
+    fn target(name: &str, destination: DeployDestination) -> DeployTarget {
+        DeployTarget {
+            name: String::from(name),
+            destination,
+            exporter: String::new(),
+            render_html: false,
+            subdirectory: String::new(),
+            schedule_minutes: 0,
+            last_deploy_second: 0,
+        }
+    }
+
+    fn git_destination(remote_url: &str, branch: &str, email: &str) -> DeployDestination {
+        DeployDestination::Git {
+            remote_url: String::from(remote_url),
+            branch: String::from(branch),
+            user_name: String::from("Blog"),
+            email: String::from(email),
+            conflict_strategy: ConflictStrategy::Abort,
+            credential: String::new(),
+        }
+    }
+
+    fn local_destination(path: &Path) -> DeployDestination {
+        DeployDestination::Local {
+            path: path.display().to_string(),
+        }
+    }
+
+    async fn finished(job: PublishJob) -> Result<PublishJob> {
+        loop {
+            let job = publish::job(&job.id)?;
+            if job.state.is_finished() {
+                return Ok(job);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[test]
+    fn validate_targets() {
+        let dir = testing::work_dir();
+        let email = "blog@example.com";
+        assert!(validate(&target(
+            "site",
+            git_destination("https://example.com/site.git", "main", email)
+        ))
+        .is_ok());
+        assert!(validate(&target("site", git_destination("/srv/site.git", "gh-pages", email))).is_ok());
+        assert!(validate(&target("site", local_destination(&dir))).is_ok());
+
+        assert!(validate(&target("", local_destination(&dir))).is_err());
+        assert!(validate(&target("a b", local_destination(&dir))).is_err());
+        assert!(validate(&target(
+            "site",
+            git_destination("ftp://example.com/site.git", "main", email)
+        ))
+        .is_err());
+        assert!(validate(&target("site", git_destination("site.git", "main", email))).is_err());
+        assert!(validate(&target("site", git_destination("/srv/site.git", "a..b", email))).is_err());
+        assert!(validate(&target("site", git_destination("/srv/site.git", "main", "blog"))).is_err());
+        assert!(validate(&target("site", local_destination(Path::new("relative/dir")))).is_err());
+        for destination in ["-e sh:x", "host/var/www"] {
+            let rsync = DeployDestination::Rsync {
+                destination: String::from(destination),
+                port: 22,
+            };
+            assert!(validate(&target("site", rsync)).is_err());
+        }
+        let mut t = target("site", local_destination(&dir));
+        t.subdirectory = String::from("../outside");
+        assert!(validate(&t).is_err());
+        t.subdirectory = String::new();
+        t.exporter = String::from("unknown");
+        assert!(validate(&t).is_err());
+    }
+
+    #[test]
+    fn sftp_commands() {
+        let files = vec![
+            (String::from("index.html"), PathBuf::from("/stage/index.html")),
+            (
+                String::from("posts/a b/index.html"),
+                PathBuf::from("/stage/posts/a b/index.html"),
+            ),
+        ];
+        let removed = vec![String::from("posts/\"old\"/index.html")];
+        assert_eq!(
+            "-mkdir \"/var/www\"\n\
+             -mkdir \"/var/www/posts\"\n\
+             -mkdir \"/var/www/posts/a b\"\n\
+             put \"/stage/index.html\" \"/var/www/index.html\"\n\
+             put \"/stage/posts/a b/index.html\" \"/var/www/posts/a b/index.html\"\n\
+             -rm \"/var/www/posts/\\\"old\\\"/index.html\"\n",
+            sftp_batch("/var/www/", &files, &removed)
+        );
+    }
+
+    #[test]
+    fn deploy_to_local_and_git() {
+        testing::block_on(async {
+            post::new_post().await.unwrap();
+
+            let dir = testing::temp_dir("deploy-local");
+            save_target(target("local", local_destination(&dir))).await.unwrap();
+            let job = finished(deploy_now("local").await.unwrap()).await.unwrap();
+            assert_eq!(PublishState::Done, job.state, "{:?}", job.error);
+            assert_eq!("local", job.target);
+            assert!(!job.result.unwrap().changes.added.is_empty());
+            assert!(dir.join(".blog-export.json").is_file());
+            assert!(targets().await.unwrap()[0].last_deploy_second > 0);
+
+            let bare = testing::temp_dir("deploy-bare");
+            git2::Repository::init_bare(&bare).unwrap();
+            let remote_url = bare.display().to_string();
+            save_target(target("git", git_destination(&remote_url, "main", "blog@example.com")))
+                .await
+                .unwrap();
+            let job = finished(deploy_now("git").await.unwrap()).await.unwrap();
+            assert_eq!(PublishState::Done, job.state, "{:?}", job.error);
+            let repo = git2::Repository::open_bare(&bare).unwrap();
+            let commit = repo
+                .find_reference("refs/heads/main")
+                .unwrap()
+                .peel_to_commit()
+                .unwrap();
+            assert_eq!(Some("Blog"), commit.author().name());
+            assert!(commit.tree().unwrap().len() > 0);
+
+            remove_target("git").await.unwrap();
+            remove_target("local").await.unwrap();
+            assert!(targets().await.unwrap().is_empty());
+        });
+    }
+}
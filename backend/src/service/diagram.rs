@@ -0,0 +1,61 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use layout::{
+    backends::svg::SVGWriter,
+    gv::{DotParser, GraphBuilder},
+};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+const MAX_CACHED: usize = 256;
+
+lazy_static! {
+    // key 是图表源码的 hash，重新渲染所有博客时不用重复布局
+    static ref CACHE: RwLock<HashMap<u64, String>> = RwLock::new(HashMap::with_capacity(32));
+}
+
+pub(crate) fn is_diagram(lang: &str) -> bool {
+    matches!(lang, "dot" | "graphviz")
+}
+
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn layout_svg(source: &str) -> Option<String> {
+    let mut parser = DotParser::new(source);
+    let graph = match parser.process() {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        },
+    };
+    let mut builder = GraphBuilder::new();
+    builder.visit_graph(&graph);
+    let mut visual_graph = builder.get();
+    let mut writer = SVGWriter::new();
+    visual_graph.do_it(false, false, false, &mut writer);
+    Some(writer.finalize())
+}
+
+// 解析失败时返回 None，调用方按普通代码块处理
+pub(crate) fn render_svg(source: &str) -> Option<String> {
+    let hash = content_hash(source);
+    if let Some(svg) = CACHE.read().get(&hash) {
+        return Some(svg.clone());
+    }
+    let svg = layout_svg(source)?;
+    let html = format!("<figure class=\"diagram\">{}</figure>\n", svg);
+    let mut cache = CACHE.write();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(hash, html.clone());
+    Some(html)
+}
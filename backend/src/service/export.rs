@@ -1,27 +1,206 @@
-use std::fs::OpenOptions;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use blog_common::dto::git::{GitPushInfo, GitRepositoryInfo};
+use blog_common::dto::{
+    git::{GitPushInfo, GitPushResult, GitRepositoryInfo},
+    post::{Translation, Visibility},
+};
+use blog_common::result::Error;
 use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
 use tera::Tera;
-use zip::write::FileOptions;
+use zip::{write::FileOptions, CompressionMethod};
 
-use crate::db::{management, model::Post, post};
-use crate::util::{self, result::Result};
+use crate::db::{management, model::Post, post, tag};
+use crate::service::{manifest::BuildOutput, shortcode};
+use crate::util::{self, date, result::Result};
 
-static HUGO_TEMPLATE: &'static str = include_str!("../resource/static-site/template/hugo.txt");
 static GIT_PAGES_DETAIL_HTML: &'static str = include_str!("../resource/page/git-pages-detail.html");
+static DEPLOY_TARGETS_HTML: &'static str = include_str!("../resource/page/deploy-targets.html");
 static RENDER_TEMPLATE_HTML: &'static str = include_str!("../resource/page/export-template.html");
 
+// 不同静态网站生成器的导出方式：front matter 模板和文件路径
+pub(crate) trait Exporter: Sync {
+    // 也用作模板名和配置项名
+    fn name(&self) -> &'static str;
+    fn default_template(&self) -> &'static str;
+    // 相对导出目录的文件路径
+    fn file_path(&self, post: &ExportPost) -> String;
+    // 博客引用的上传文件：返回 (相对导出目录的文件路径, 博客里的新地址)
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String);
+    // 其它博客的 post-link 链接到这篇博客的地址，尽量交给生成器解析，这样不受网站地址和 permalink 设置的影响
+    fn post_url(&self, post: &ExportPost) -> String;
+}
+
+struct Hugo;
+
+impl Exporter for Hugo {
+    fn name(&self) -> &'static str {
+        "hugo"
+    }
+
+    fn default_template(&self) -> &'static str {
+        include_str!("../resource/static-site/template/hugo.txt")
+    }
+
+    // 使用 page bundle，图片和 index.md 放在同一个目录；多语言站点按 Hugo 的约定把语言放在文件名里
+    fn file_path(&self, post: &ExportPost) -> String {
+        if post.language.is_empty() {
+            format!("content/posts/{}/index.md", post.slug)
+        } else {
+            format!("content/posts/{}/index.{}.md", post.slug, post.language)
+        }
+    }
+
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String) {
+        (
+            format!("content/posts/{}/{}", post.slug, file_name),
+            file_name.to_string(),
+        )
+    }
+
+    fn post_url(&self, post: &ExportPost) -> String {
+        if post.language.is_empty() {
+            format!("{{{{< relref \"posts/{}\" >}}}}", post.slug)
+        } else {
+            format!("{{{{< relref path=\"posts/{}\" lang=\"{}\" >}}}}", post.slug, post.language)
+        }
+    }
+}
+
+struct Jekyll;
+
+impl Exporter for Jekyll {
+    fn name(&self) -> &'static str {
+        "jekyll"
+    }
+
+    fn default_template(&self) -> &'static str {
+        include_str!("../resource/static-site/template/jekyll.txt")
+    }
+
+    fn file_path(&self, post: &ExportPost) -> String {
+        if post.draft {
+            format!("_drafts/{}.md", post.slug)
+        } else {
+            format!("_posts/{}-{}.md", &post.datetime[..10], post.slug)
+        }
+    }
+
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String) {
+        let path = format!("assets/images/{}/{}", post.id, file_name);
+        let link = format!("/{}", path);
+        (path, link)
+    }
+
+    fn post_url(&self, post: &ExportPost) -> String {
+        format!("{{{{ site.baseurl }}}}{{% post_url {}-{} %}}", &post.datetime[..10], post.slug)
+    }
+}
+
+struct Zola;
+
+impl Exporter for Zola {
+    fn name(&self) -> &'static str {
+        "zola"
+    }
+
+    fn default_template(&self) -> &'static str {
+        include_str!("../resource/static-site/template/zola.txt")
+    }
+
+    // Zola 的 colocated assets：图片和 index.md 放在同一个目录
+    fn file_path(&self, post: &ExportPost) -> String {
+        if post.language.is_empty() {
+            format!("content/{}/index.md", post.slug)
+        } else {
+            format!("content/{}/index.{}.md", post.slug, post.language)
+        }
+    }
+
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String) {
+        (format!("content/{}/{}", post.slug, file_name), file_name.to_string())
+    }
+
+    // Zola 的内部链接
+    fn post_url(&self, post: &ExportPost) -> String {
+        format!("@/{}", self.file_path(post).trim_start_matches("content/"))
+    }
+}
+
+struct Hexo;
+
+impl Exporter for Hexo {
+    fn name(&self) -> &'static str {
+        "hexo"
+    }
+
+    fn default_template(&self) -> &'static str {
+        include_str!("../resource/static-site/template/hexo.txt")
+    }
+
+    fn file_path(&self, post: &ExportPost) -> String {
+        if post.draft {
+            format!("source/_drafts/{}.md", post.slug)
+        } else {
+            format!("source/_posts/{}.md", post.slug)
+        }
+    }
+
+    // source 目录下的文件会原样复制到站点根目录
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String) {
+        (
+            format!("source/images/{}/{}", post.id, file_name),
+            format!("/images/{}/{}", post.id, file_name),
+        )
+    }
+
+    fn post_url(&self, post: &ExportPost) -> String {
+        format!("{{% post_path {} %}}", post.slug)
+    }
+}
+
+static EXPORTERS: [&dyn Exporter; 4] = [&Hugo, &Jekyll, &Zola, &Hexo];
+
+pub(crate) fn exporter(name: &str) -> Option<&'static dyn Exporter> {
+    EXPORTERS.iter().find(|e| e.name() == name).copied()
+}
+
+pub fn exporter_names() -> Vec<&'static str> {
+    EXPORTERS.iter().map(|e| e.name()).collect()
+}
+
+// 用户自定义的模板保存在 settings 表里，为空时使用默认模板
+pub fn template_setting(name: &str) -> String {
+    format!("export_template_{}", name)
+}
+
+pub fn default_template(name: &str) -> &'static str {
+    exporter(name).map_or("", |e| e.default_template())
+}
+
+fn template_name(exporter: &dyn Exporter) -> String {
+    format!("{}.md", exporter.name())
+}
+
 lazy_static! {
+    // 编辑器插入的上传文件地址：/upload/3/123-1630000000.png
+    static ref UPLOAD_REF_REGEX: Regex = Regex::new(r"/?\bupload/[A-Za-z0-9_\-./]*\.[A-Za-z0-9]+").unwrap();
     pub static ref TEMPLATES: Tera = {
         let mut tera = Tera::default();
-        if let Err(e) = tera.add_raw_template("hugo.md", HUGO_TEMPLATE) {
-            eprintln!("{:?}", e);
+        for exporter in EXPORTERS.iter() {
+            if let Err(e) = tera.add_raw_template(&template_name(*exporter), exporter.default_template()) {
+                eprintln!("{:?}", e);
+            }
         }
         if let Err(e) = tera.add_raw_template("git-pages-detail.html", GIT_PAGES_DETAIL_HTML) {
             eprintln!("{:?}", e);
         }
+        if let Err(e) = tera.add_raw_template("deploy-targets.html", DEPLOY_TARGETS_HTML) {
+            eprintln!("{:?}", e);
+        }
         if let Err(e) = tera.add_raw_template("export-template.html", RENDER_TEMPLATE_HTML) {
             eprintln!("{:?}", e);
         }
@@ -29,58 +208,222 @@ lazy_static! {
     };
 }
 
-fn render(post: &Post, template_name: &str, template: Option<&String>) -> Result<String> {
-    let mut context = tera::Context::new();
-    context.insert("title", &post.title);
-    context.insert("content", &post.markdown_content);
-    let r = if template.is_some() {
-        tera::Tera::one_off(template.unwrap(), &context, true)
-    } else {
-        TEMPLATES.render(template_name, &context)
-    };
-    r.map_err(|e| e.into())
+// 导出的一篇博客，字段都可以在模板里使用
+#[derive(Serialize)]
+pub(crate) struct ExportPost {
+    pub(crate) id: i64,
+    pub(crate) title: String,
+    pub(crate) slug: String,
+    pub(crate) content: String,
+    pub(crate) title_image: String,
+    pub(crate) tags: Vec<String>,
+    pub(crate) language: String,
+    pub(crate) translations: Vec<Translation>,
+    // 同一组翻译使用同一个值，对应 Hugo 的 translationKey
+    pub(crate) translation_key: String,
+    pub(crate) draft: bool,
+    pub(crate) unlisted: bool,
+    pub(crate) created_at: i64,
+    pub(crate) updated_at: i64,
+    // 2021-08-30T13:44:28Z
+    pub(crate) date: String,
+    pub(crate) updated: String,
+    // 2021-08-30 13:44:28
+    pub(crate) datetime: String,
+    pub(crate) updated_datetime: String,
 }
 
-// fn render(post: &Post, template_name: &str) -> String {
-//     let mut context = tera::Context::new();
-//     context.insert("title", &post.title);
-//     context.insert("content", &post.markdown_content);
-//     match TEMPLATES.render(template_name, &context) {
-//         Ok(s) => s,
-//         Err(e) => {
-//             eprintln!("{:?}", e);
-//             String::new()
-//         },
-//     }
-// }
+// 同一分组里其它导出了的博客，用来生成 hreflang
+fn translations_of<'a>(
+    post: &Post,
+    groups: &'a HashMap<i64, Vec<Translation>>,
+    exported: &HashSet<i64>,
+) -> Vec<&'a Translation> {
+    post.translation_group
+        .and_then(|g| groups.get(&g))
+        .map_or(vec![], |list| {
+            list.iter()
+                .filter(|t| t.id != post.id && exported.contains(&t.id))
+                .collect()
+        })
+}
 
-// fn write_posts(posts: &Vec<Post>, mut writer: impl std::io::Write) -> Result<()> {
-// fn write_posts(posts: &Vec<Post>, callback: impl Fn(dyn std::io::Write, &str, &String) -> Result<()>) -> Result<()> {
-fn write_posts<C>(posts: &Vec<Post>, mut callback: C, file_ext: &str) -> Result<()>
-where
-    C: FnMut(&String, &Post) -> Result<()>,
-{
-    let mut file_name = String::with_capacity(32);
-    for post in posts {
-        file_name.push_str(post.id.to_string().as_str());
-        file_name.push_str(".");
-        file_name.push_str(file_ext);
+// 需要密码的博客不导出；私密的博客只在 include_drafts 时作为草稿导出
+fn exportable(posts: Vec<Post>, include_drafts: bool) -> Vec<Post> {
+    posts
+        .into_iter()
+        .filter(|p| match Visibility::from_str(&p.visibility) {
+            Visibility::Public | Visibility::Unlisted => true,
+            Visibility::Private => include_drafts,
+            Visibility::Password => false,
+        })
+        .collect()
+}
 
-        callback(&file_name, &post)?;
+// 和标题锚点一样保留中文等 Unicode 字母和数字
+fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// 按所有博客计算，这样增量导出时同一篇博客的 slug 不会变；重名时较新的博客加上 id
+async fn slugs() -> Result<HashMap<i64, String>> {
+    let mut used = HashSet::new();
+    let mut slugs = HashMap::new();
+    for (id, title) in post::all_titles().await? {
+        let slug = slugify(&title);
+        let slug = if slug.is_empty() {
+            id.to_string()
+        } else if used.contains(&slug) {
+            format!("{}-{}", slug, id)
+        } else {
+            slug
+        };
+        used.insert(slug.clone());
+        slugs.insert(id, slug);
+    }
+    Ok(slugs)
+}
 
-        file_name.clear();
+pub(crate) async fn export_posts(posts: Vec<Post>, include_drafts: bool) -> Result<Vec<ExportPost>> {
+    let posts = exportable(posts, include_drafts);
+    if posts.is_empty() {
+        return Ok(vec![]);
     }
-    Ok(())
+    let groups = post::all_translations().await?;
+    let exported = posts.iter().map(|p| p.id).collect::<HashSet<i64>>();
+    let mut tags = tag::get_tags_by_post_ids(posts.iter().map(|p| p.id).collect()).await?;
+    let slugs = slugs().await?;
+    let list = posts
+        .into_iter()
+        .map(|p| {
+            let updated_at = p.updated_at.unwrap_or(p.created_at);
+            let visibility = Visibility::from_str(&p.visibility);
+            ExportPost {
+                id: p.id,
+                slug: slugs.get(&p.id).cloned().unwrap_or_else(|| p.id.to_string()),
+                translations: translations_of(&p, &groups, &exported).into_iter().cloned().collect(),
+                translation_key: p.translation_group.map_or(String::new(), |g| g.to_string()),
+                tags: tags
+                    .remove(&p.id)
+                    .map_or(vec![], |list| list.into_iter().map(|t| t.name).collect()),
+                draft: visibility == Visibility::Private,
+                unlisted: visibility == Visibility::Unlisted,
+                created_at: p.created_at,
+                updated_at,
+                date: date::format_rfc3339(p.created_at),
+                updated: date::format_rfc3339(updated_at),
+                datetime: date::format_datetime(p.created_at),
+                updated_datetime: date::format_datetime(updated_at),
+                title: p.title,
+                content: p.markdown_content,
+                title_image: p.title_image,
+                language: p.language,
+            }
+        })
+        .collect();
+    Ok(list)
+}
+
+// post-link 可以链接到的博客，草稿没有固定的地址
+pub(crate) fn post_urls(exporter: &dyn Exporter, posts: &[ExportPost]) -> HashMap<i64, String> {
+    posts.iter().filter(|p| !p.draft).map(|p| (p.id, exporter.post_url(p))).collect()
+}
+
+// 需要和博客一起导出的上传文件
+pub(crate) struct Asset {
+    // 相对导出目录的路径
+    pub(crate) path: String,
+    pub(crate) source: PathBuf,
+}
+
+fn upload_file(reference: &str) -> Result<Option<PathBuf>> {
+    let relative = reference.trim_start_matches('/');
+    if relative.split('/').any(|p| p == ".." || p.is_empty()) {
+        return Ok(None);
+    }
+    let path = std::env::current_dir()?.join(relative);
+    Ok(if path.is_file() { Some(path) } else { None })
+}
+
+// 找出正文和题图引用的上传文件，改成导出后的地址；找不到的文件保留原地址，加到 missing 里
+pub(crate) fn bundle_assets(
+    exporter: &dyn Exporter,
+    post: &mut ExportPost,
+    missing: &mut Vec<String>,
+) -> Result<Vec<Asset>> {
+    let mut references = UPLOAD_REF_REGEX
+        .find_iter(&post.content)
+        .map(|m| m.as_str().to_string())
+        .collect::<Vec<String>>();
+    if UPLOAD_REF_REGEX.is_match(&post.title_image) {
+        references.push(post.title_image.clone());
+    }
+    let mut seen = HashSet::new();
+    let mut assets = Vec::new();
+    let mut links = HashMap::new();
+    for reference in references {
+        if !seen.insert(reference.clone()) {
+            continue;
+        }
+        match upload_file(&reference)? {
+            Some(source) => {
+                let file_name = source
+                    .file_name()
+                    .map_or(String::new(), |n| n.to_string_lossy().to_string());
+                let (path, link) = exporter.asset_path(post, &file_name);
+                links.insert(reference, link);
+                assets.push(Asset { path, source });
+            },
+            None => {
+                eprintln!("export: missing file {} in post {}", reference, post.id);
+                missing.push(format!("{}: {}", post.title, reference));
+            },
+        }
+    }
+    if !links.is_empty() {
+        post.content = UPLOAD_REF_REGEX
+            .replace_all(&post.content, |caps: &regex::Captures| {
+                links.get(&caps[0]).cloned().unwrap_or_else(|| caps[0].to_string())
+            })
+            .to_string();
+        if let Some(link) = links.get(&post.title_image) {
+            post.title_image = link.clone();
+        }
+    }
+    Ok(assets)
+}
+
+async fn custom_template(exporter: &dyn Exporter) -> Result<Option<String>> {
+    let setting = management::get_setting(&template_setting(exporter.name())).await?;
+    Ok(setting.map(|s| s.content).filter(|c| !c.trim().is_empty()))
+}
+
+fn render(exporter: &dyn Exporter, post: &ExportPost, template: Option<&String>) -> Result<String> {
+    let context = tera::Context::from_serialize(post)?;
+    let r = match template {
+        Some(t) => Tera::one_off(t, &context, false),
+        None => TEMPLATES.render(&template_name(exporter), &context),
+    };
+    r.map_err(|e| e.into())
 }
 
-// fn write_to_zip(mut writer: zip::ZipWriter<File>, filename: &str, content: &String) -> Result<()> {
-//     writer.start_file(filename, FileOptions::default())?;
-//     writer.write_all(content.as_bytes())?;
-//     Ok(())
-// }
+fn unknown_exporter() -> Error {
+    Error::BusinessException(String::from("不支持的导出格式/Unknown export format."))
+}
 
-pub async fn hugo() -> Result<String> {
-    let posts = post::all().await?;
+// 生成 zip 文件，返回文件名和找不到的上传文件；下载到本地的压缩包包含私密博客（作为草稿）
+pub async fn zip(name: &str) -> Result<(String, Vec<String>)> {
+    let exporter = exporter(name).ok_or_else(unknown_exporter)?;
+    let mut posts = export_posts(post::all().await?, true).await?;
+    let template = custom_template(exporter).await?;
 
     let export_dir = std::env::current_dir()?.join("export");
     if !export_dir.exists() {
@@ -91,67 +434,137 @@ pub async fn hugo() -> Result<String> {
     let output_file = export_dir.join(filename.as_str());
     let file = std::fs::File::create(output_file)?;
     let mut zip = zip::ZipWriter::new(file);
-
-    let zip_file = |file_name: &String, post: &Post| -> Result<()> {
-        zip.start_file(file_name, FileOptions::default())?;
-        let content = render(post, "hugo.md", None)?;
+    // 图片本身已经压缩过了
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut written = HashSet::new();
+    let mut missing = Vec::new();
+    let urls = post_urls(exporter, &posts);
+    for post in posts.iter_mut() {
+        post.content = shortcode::link_posts(&post.content, &urls).await?;
+        for asset in bundle_assets(exporter, post, &mut missing)? {
+            if written.insert(asset.path.clone()) {
+                zip.start_file(asset.path.as_str(), stored)?;
+                zip.write_all(&std::fs::read(asset.source.as_path())?)?;
+            }
+        }
+        zip.start_file(exporter.file_path(post), FileOptions::default())?;
+        let content = render(exporter, post, template.as_ref())?;
         zip.write_all(content.as_bytes())?;
-        Ok(())
-    };
-
-    write_posts(&posts, zip_file, "md")?;
+    }
+    if !missing.is_empty() {
+        zip.start_file("MISSING_FILES.txt", FileOptions::default())?;
+        zip.write_all(missing.join("\n").as_bytes())?;
+    }
+    zip.finish()?;
 
-    /*
-    let mut file_name = String::with_capacity(32);
-    for post in posts.iter() {
-        file_name.push_str(post.id.to_string().as_str());
-        file_name.push_str(".md");
+    Ok((filename, missing))
+}
 
-        zip_file(&file_name, post)?;
+// 子目录不能跳出仓库目录
+pub(crate) fn export_root(repository_path: &Path, subdirectory: &str) -> Result<PathBuf> {
+    let subdirectory = subdirectory.trim().trim_matches('/');
+    if subdirectory.split('/').any(|p| p == ".." || p == ".git") || subdirectory.contains('\\') {
+        return Err(Error::BusinessException(String::from("子目录不正确/Invalid subdirectory.")).into());
+    }
+    if subdirectory.is_empty() {
+        Ok(repository_path.to_path_buf())
+    } else {
+        Ok(repository_path.join(subdirectory))
+    }
+}
 
-        // zip.start_file(file_name.as_str(), FileOptions::default())?;
+pub async fn git(git: &GitRepositoryInfo, push_info: &GitPushInfo) -> Result<GitPushResult> {
+    let root = export_root(&super::git::git::get_repository_path(git), &push_info.subdirectory)?;
+    to_directory(&root, &push_info.exporter, push_info.render_html).await
+}
 
-        // let content = render(post, "hugo.md");
-        // zip.write_all(content.as_bytes())?;
+// 每次都生成所有的博客，只写入有变化的文件，删除已经删除的博客对应的文件
+pub(crate) async fn to_directory(root: &Path, exporter_name: &str, render_html: bool) -> Result<GitPushResult> {
+    println!("export path {}", root.display());
 
-        file_name.clear();
+    // 渲染成 HTML 时生成完整的静态网站，需要所有的博客来生成列表页
+    if render_html {
+        let report = super::site::build(root).await?;
+        return Ok(GitPushResult {
+            changes: report.changes,
+            missing: report.missing,
+        });
+    }
+    let mut posts = export_posts(post::all().await?, false).await?;
+    let mut output = BuildOutput::open(root)?;
+    let mut missing = Vec::new();
+    let name = if exporter_name.is_empty() {
+        "hugo"
+    } else {
+        exporter_name
+    };
+    let exporter = exporter(name).ok_or_else(unknown_exporter)?;
+    let template = custom_template(exporter).await?;
+    let urls = post_urls(exporter, &posts);
+    for post in posts.iter_mut() {
+        post.content = shortcode::link_posts(&post.content, &urls).await?;
+        for asset in bundle_assets(exporter, post, &mut missing)? {
+            output.copy(&asset.path, &asset.source)?;
+        }
+        let content = render(exporter, post, template.as_ref())?;
+        output.write(&exporter.file_path(post), content.as_bytes())?;
     }
-    */
-    zip.finish()?;
 
-    Ok(filename)
+    Ok(GitPushResult {
+        changes: output.finish()?,
+        missing,
+    })
 }
 
-pub async fn git(git: &GitRepositoryInfo, push_info: &GitPushInfo) -> Result<()> {
-    let path = super::git::git::get_repository_path(git);
-    let mut path = path.join("file.txt");
-    println!("export path {}", path.as_path().display());
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::post::PostData;
 
-    let posts = post::all_by_since(git.last_export_second).await?;
-    let one_off_template = if push_info.render_html {
-        let setting = management::get_setting(crate::util::val::POST_DETAIL_RENDER_TEMPLATE).await?;
-        setting.map(|s| s.content)
-    } else {
-        None
-    };
-    let (template_name, file_ext) = if one_off_template.is_some() {
-        ("one_off_template", "html")
-    } else {
-        ("hugo.md", "md")
-    };
-    let write_file = |filename: &String, post: &Post| -> Result<()> {
-        path.set_file_name(filename);
-        println!("export to file {}", path.as_path().display());
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path.as_path())?;
-        let content = render(post, template_name, one_off_template.as_ref())?;
-        file.write_all(content.as_bytes())?;
-        Ok(())
-    };
-    write_posts(&posts, write_file, file_ext)?;
+    use super::*;
+    use crate::util::testing;
+
+    async fn new_post(title: &str, content: &str) -> i64 {
+        let id = post::new_post().await.unwrap();
+        let data = PostData {
+            id,
+            title: String::from(title),
+            title_image: String::new(),
+            content: String::from(content),
+            tags: None,
+            category_id: None,
+            language: String::new(),
+            translation_of: None,
+            visibility: Visibility::Public,
+            password: None,
+        };
+        post::save(data).await.unwrap();
+        id
+    }
+
+    #[test]
+    fn post_links_in_markdown_exports() {
+        testing::block_on(async {
+            let target = new_post("Export Target", "Target").await;
+            new_post("Export Source", &format!("Read {{{{< post-link {} >}}}}", target)).await;
 
-    Ok(())
+            let dir = testing::temp_dir("export-hugo");
+            to_directory(&dir, "hugo", false).await.unwrap();
+            let source = std::fs::read_to_string(dir.join("content/posts/export-source/index.md")).unwrap();
+            assert!(source.contains("Read [Export Target]({{< relref \"posts/export-target\" >}})"), "{}", source);
+
+            let dir = testing::temp_dir("export-zola");
+            to_directory(&dir, "zola", false).await.unwrap();
+            let source = std::fs::read_to_string(dir.join("content/export-source/index.md")).unwrap();
+            assert!(source.contains("Read [Export Target](@/export-target/index.md)"), "{}", source);
+        });
+    }
+
+    #[test]
+    fn slugify_titles() {
+        assert_eq!("hello-world", slugify("Hello, World!"));
+        assert_eq!("rust-1-56", slugify("  Rust 1.56  "));
+        assert_eq!("使用-rust-写博客", slugify("使用 Rust 写博客"));
+        assert_eq!("café", slugify("Café"));
+        assert_eq!("", slugify("!!!"));
+    }
 }
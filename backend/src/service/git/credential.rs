@@ -0,0 +1,114 @@
+use std::{path::PathBuf, time::UNIX_EPOCH};
+
+use blog_common::{dto::git::GitCredentialStatus, result::Error};
+
+use crate::util::{crypt, result::Result};
+
+// 和部署密钥放在一起，用服务器密钥加密
+const CREDENTIAL_FILE: &str = "credential.enc";
+const CREDENTIAL_PURPOSE: &str = "git-credential";
+
+fn credential_file() -> Result<PathBuf> {
+    Ok(super::ssh::key_dir()?.join(CREDENTIAL_FILE))
+}
+
+// 每个部署目标单独保存，用不同的密钥加密，不会用到 Git Pages 保存的密码/令牌
+fn target_file(name: &str) -> Result<PathBuf> {
+    Ok(super::ssh::key_dir()?.join(format!("deploy-{}.credential.enc", name)))
+}
+
+fn target_purpose(name: &str) -> String {
+    format!("deploy-credential-{}", name)
+}
+
+fn write(path: PathBuf, purpose: &str, credential: &str) -> Result<()> {
+    let credential = credential.trim();
+    if credential.is_empty() {
+        return Err(Error::BusinessException(String::from("密码/令牌不能为空/Credential must not be empty.")).into());
+    }
+    let encrypted = crypt::encrypt(purpose, credential.as_bytes())?;
+    std::fs::write(path, encrypted)?;
+    Ok(())
+}
+
+fn read(path: PathBuf, purpose: &str) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let credential = crypt::decrypt(purpose, &std::fs::read(path)?)?;
+    Ok(Some(String::from_utf8(credential)?))
+}
+
+fn remove(path: PathBuf) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+pub fn status() -> Result<GitCredentialStatus> {
+    let path = credential_file()?;
+    if !path.is_file() {
+        return Ok(GitCredentialStatus::default());
+    }
+    let updated_at = std::fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    Ok(GitCredentialStatus {
+        saved: true,
+        updated_at,
+    })
+}
+
+// 保存或者替换已保存的密码/令牌
+pub fn save(credential: &str) -> Result<GitCredentialStatus> {
+    write(credential_file()?, CREDENTIAL_PURPOSE, credential)?;
+    status()
+}
+
+pub fn forget() -> Result<()> {
+    remove(credential_file()?)
+}
+
+pub(crate) fn load() -> Result<Option<String>> {
+    read(credential_file()?, CREDENTIAL_PURPOSE)
+}
+
+pub(crate) fn save_for_target(name: &str, credential: &str) -> Result<()> {
+    write(target_file(name)?, &target_purpose(name), credential)
+}
+
+pub(crate) fn forget_for_target(name: &str) -> Result<()> {
+    remove(target_file(name)?)
+}
+
+pub(crate) fn load_for_target(name: &str) -> Result<Option<String>> {
+    read(target_file(name)?, &target_purpose(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn save_load_and_forget() -> Result<()> {
+        testing::work_dir();
+        assert!(save("  ").is_err());
+
+        assert!(save(" token-1 ")?.saved);
+        assert_eq!(Some(String::from("token-1")), load()?);
+        // 文件里是密文
+        let data = std::fs::read(super::super::ssh::key_dir()?.join(CREDENTIAL_FILE))?;
+        assert!(!String::from_utf8_lossy(&data).contains("token-1"));
+
+        save("token-2")?;
+        assert_eq!(Some(String::from("token-2")), load()?);
+
+        forget()?;
+        assert_eq!(None, load()?);
+        assert!(!status()?.saved);
+        Ok(())
+    }
+}
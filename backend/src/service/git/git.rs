@@ -1,16 +1,24 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::vec::Vec;
 
-use blog_common::dto::git::GitRepositoryInfo;
+use blog_common::dto::git::{GitChangeKind, GitCommit, GitFileChange, GitRepositoryInfo, GitStatus, PublishState};
 use git2::{
-    BranchType, Commit, Cred, Direction, Error as GitError, ObjectType, Oid, PushOptions, Remote, RemoteCallbacks,
-    Repository, RepositoryState, Signature, StatusOptions, StatusShow,
+    build::{CheckoutBuilder, RepoBuilder},
+    BranchType, Commit, Cred, CredentialType, Direction, Error as GitError, FetchOptions, ObjectType, Oid,
+    PushOptions, Remote, RemoteCallbacks, Repository, RepositoryState, Signature, StatusOptions, StatusShow,
+    TreeWalkMode, TreeWalkResult,
 };
 
+use super::publish::JobContext;
 use crate::db::management;
 use crate::db::model::Setting;
+use crate::service::backup;
+use crate::util::diff;
 
 const SETTING_ITEM_NAME: &'static str = "git-pages";
+// 超过这个大小的文件不比较内容
+const MAX_DIFF_FILE_SIZE: usize = 256 * 1024;
 
 pub fn get_repository_path(info: &GitRepositoryInfo) -> PathBuf {
     let path = std::env::current_dir().unwrap();
@@ -49,13 +57,43 @@ pub async fn new_repository(info: GitRepositoryInfo) -> Result<(), String> {
             e
         ));
     }
-    if let Err(e) = Repository::clone(&info.remote_url, path.as_path()) {
+    if let Err(e) = clone_repository(&info, path.as_path(), "") {
+        let _ = std::fs::remove_dir_all(path.as_path());
         return Err(format!("Failed clone git repository: {}", e));
     };
     // save to db
     update_git_repository_info(&info).await
 }
 
+// http 仓库没有密码/令牌时匿名克隆
+pub(crate) fn clone_repository(info: &GitRepositoryInfo, path: &Path, password: &str) -> Result<Repository, GitError> {
+    let mut fetch_options = FetchOptions::new();
+    if super::ssh::is_ssh_url(&info.remote_url) || !password.is_empty() {
+        fetch_options.remote_callbacks(create_callbacks(info, password)?);
+    }
+    RepoBuilder::new()
+        .fetch_options(fetch_options)
+        .clone(&info.remote_url, path)
+        .map_err(super::ssh::explain)
+}
+
+// 克隆之后切换到要推送的分支：远程有这个分支时从它开始，空仓库时第一次提交会创建它
+pub(crate) fn checkout_branch(repo: &Repository, branch: &str) -> Result<(), GitError> {
+    let refname = format!("refs/heads/{}", branch);
+    let start = match repo.refname_to_id(&format!("refs/remotes/origin/{}", branch)) {
+        Ok(oid) => Some(oid),
+        Err(_) => find_last_commit(repo).ok().map(|c| c.id()),
+    };
+    match start {
+        Some(oid) => {
+            repo.reference(&refname, oid, true, &format!("Checkout {}", branch))?;
+            repo.set_head(&refname)?;
+            repo.checkout_head(Some(CheckoutBuilder::new().force()))
+        },
+        None => repo.set_head(&refname),
+    }
+}
+
 pub async fn remove_repository(info: GitRepositoryInfo) -> Result<(), String> {
     let path = get_repository_path(&info);
     if path.exists() {
@@ -63,6 +101,10 @@ pub async fn remove_repository(info: GitRepositoryInfo) -> Result<(), String> {
             return Err(format!("Failed to remove git folder: {:?}", e));
         }
     }
+    // 保存的密码/令牌属于这个仓库，一起删除
+    if let Err(e) = super::credential::forget() {
+        return Err(format!("Failed to forget git credential: {:?}", e.0));
+    }
     update_setting(String::new()).await
 }
 
@@ -136,21 +178,165 @@ async fn update_setting(content: String) -> Result<(), String> {
         .map_err(|e| format!("Failed updating settings: {:?}", e.0))
 }
 
-pub fn sync_to_remote(info: &GitRepositoryInfo, password: &str) -> Result<(), GitError> {
+pub(crate) fn sync_to_remote(info: &GitRepositoryInfo, password: &str, job: &JobContext) -> Result<(), GitError> {
     // open git repository
     let repo = get_repo(info)?;
     // find changed files
     let changed_files = get_changed_files(&repo)?;
-    if changed_files.len() > 0 {
+    if changed_files.is_empty() {
+        job.log("Nothing to commit");
+    } else {
         // perform committing
-        add_and_commit(info, &repo, changed_files, "Update posts")?;
-        // try pushing
-        push(&repo, info, password)?;
+        job.log(format!("Committing {} files", changed_files.len()));
+        let oid = add_and_commit(info, &repo, changed_files, "Update posts")?;
+        job.log(format!("Created commit {}", oid));
+        if job.is_cancelled() {
+            return Err(GitError::from_str("已取消/Cancelled."));
+        }
+    }
+    // 上次取消或者推送失败时，提交还留在本地，所以只要比远程分支新就推送
+    if !is_ahead_of_remote(&repo, info)? {
+        job.log("Nothing to push");
+        return Ok(());
+    }
+    // try pushing
+    job.set_state(PublishState::Pushing);
+    push(&repo, info, password, job)?;
+    job.log("Pushed");
+    Ok(())
+}
+
+fn is_ahead_of_remote(repo: &Repository, info: &GitRepositoryInfo) -> Result<bool, GitError> {
+    // 空仓库没有可以推送的提交
+    let head = match find_last_commit(repo) {
+        Ok(c) => c.id(),
+        Err(_) => return Ok(false),
+    };
+    let branch = info.branch_name.as_deref().unwrap_or("main");
+    match repo.refname_to_id(&format!("refs/remotes/origin/{}", branch)) {
+        Ok(upstream) => Ok(repo.graph_ahead_behind(head, upstream)?.0 > 0),
+        // 远程还没有这个分支
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+fn to_commit(commit: &Commit) -> GitCommit {
+    GitCommit {
+        id: commit.id().to_string(),
+        summary: String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default()).to_string(),
+        author: String::from_utf8_lossy(commit.author().name_bytes()).to_string(),
+        time: commit.time().seconds(),
+    }
+}
+
+pub fn status(info: &GitRepositoryInfo, limit: usize) -> Result<GitStatus, GitError> {
+    let repo = get_repo(info)?;
+    let mut status = GitStatus {
+        branch: info.branch_name.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+    // 空仓库没有 HEAD
+    let head = match find_last_commit(&repo) {
+        Ok(c) => c,
+        Err(_) => return Ok(status),
+    };
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    for oid in walk.take(limit) {
+        status.commits.push(to_commit(&repo.find_commit(oid?)?));
+    }
+    if let Ok(upstream) = repo.refname_to_id(&format!("refs/remotes/origin/{}", status.branch)) {
+        let (ahead, behind) = repo.graph_ahead_behind(head.id(), upstream)?;
+        status.ahead = ahead;
+        status.behind = behind;
+    }
+    status.head = Some(to_commit(&head));
+    Ok(status)
+}
+
+// 文本文件才比较内容
+fn text_content(data: &[u8]) -> Option<&str> {
+    if data.len() > MAX_DIFF_FILE_SIZE || data.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(data).ok()
+}
+
+// 文件路径 -> blob，不包括符号链接和子模块
+fn head_blobs(repo: &Repository) -> Result<BTreeMap<String, Oid>, GitError> {
+    let mut blobs = BTreeMap::new();
+    let tree = match find_last_commit(repo) {
+        Ok(c) => c.tree()?,
+        Err(_) => return Ok(blobs),
+    };
+    tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        if entry.kind() == Some(ObjectType::Blob) && entry.filemode() != 0o120000 {
+            if let Some(name) = entry.name() {
+                blobs.insert(format!("{}{}", parent, name), entry.id());
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(blobs)
+}
+
+// 把 HEAD 的文件写到 dir，预览时在这里导出，不影响仓库的工作区
+pub(crate) fn checkout_head_to(info: &GitRepositoryInfo, dir: &Path) -> Result<(), GitError> {
+    let repo = get_repo(info)?;
+    for (path, id) in head_blobs(&repo)? {
+        let target = dir.join(&path);
+        let blob = repo.find_blob(id)?;
+        target
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(target.as_path(), blob.content()))
+            .map_err(|e| GitError::from_str(&format!("Failed to write {}: {}", path, e)))?;
     }
     Ok(())
 }
 
-fn get_signature<'a>(repo: &'a Repository, info: &'a GitRepositoryInfo) -> Result<Signature<'a>, GitError> {
+fn file_change(path: String, kind: GitChangeKind, old: &[u8], new: &[u8]) -> GitFileChange {
+    let (binary, diff) = match (text_content(old), text_content(new)) {
+        (Some(o), Some(n)) => (false, diff::line_diff(o, n)),
+        _ => (true, Vec::new()),
+    };
+    GitFileChange {
+        path,
+        kind,
+        binary,
+        diff,
+    }
+}
+
+// dir 里的文件和 HEAD 比较，也就是把 dir 的内容提交之后会有的修改
+pub(crate) fn changes_against_head(info: &GitRepositoryInfo, dir: &Path) -> Result<Vec<GitFileChange>, GitError> {
+    let repo = get_repo(info)?;
+    let mut head = head_blobs(&repo)?;
+    let mut current = Vec::new();
+    backup::list_files(dir, "", &mut current).map_err(|e| GitError::from_str(&format!("{:?}", e.0)))?;
+    let mut files = Vec::new();
+    for (name, file) in current {
+        let path = name.trim_start_matches('/').to_string();
+        let new = std::fs::read(file).map_err(|e| GitError::from_str(&e.to_string()))?;
+        match head.remove(&path) {
+            None => files.push(file_change(path, GitChangeKind::Added, &[], &new)),
+            Some(id) if id != Oid::hash_object(ObjectType::Blob, &new)? => {
+                let old = repo.find_blob(id)?;
+                files.push(file_change(path, GitChangeKind::Modified, old.content(), &new));
+            },
+            Some(_) => {},
+        }
+    }
+    for (path, id) in head {
+        let old = repo.find_blob(id)?;
+        files.push(file_change(path, GitChangeKind::Deleted, old.content(), &[]));
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+pub(crate) fn get_signature<'a>(repo: &'a Repository, info: &'a GitRepositoryInfo) -> Result<Signature<'a>, GitError> {
     let sig = match repo.signature() {
         Ok(sig) => sig,
         Err(e) => {
@@ -165,19 +351,18 @@ fn get_signature<'a>(repo: &'a Repository, info: &'a GitRepositoryInfo) -> Resul
 }
 
 fn get_changed_files(repo: &Repository) -> Result<Vec<String>, GitError> {
-    let state = dbg!(repo.state());
+    let state = repo.state();
     if !state.eq(&RepositoryState::Clean) {
-        println!("Git repository is not clean");
-        return Ok(vec![]);
+        return Err(GitError::from_str(&format!("Git repository is not clean: {:?}", state)));
     }
     let mut opts = StatusOptions::new();
-    opts.include_untracked(true);
+    // 新导出的目录要列出里面的文件，index 不能添加目录
+    opts.include_untracked(true).recurse_untracked_dirs(true);
     // let status = repo.statuses(Some(status_options.show(StatusShow::Index)))?;
     let status = repo.statuses(Some(&mut opts))?;
     let mut files: Vec<String> = Vec::with_capacity(25);
     for f in status.iter().filter(|e| e.status() != git2::Status::IGNORED) {
-        let path = dbg!(f.path().unwrap());
-        files.push(String::from(path));
+        files.push(String::from(f.path().unwrap()));
     }
     Ok(files)
 }
@@ -197,12 +382,20 @@ fn add_and_commit(
     let signature = get_signature(repo, info)?;
     // let signature = repo.signature()?;
     let mut index = repo.index()?;
+    let workdir = repo.workdir().map(|p| p.to_path_buf()).unwrap_or_default();
     for file in files.iter() {
-        index.add_path(Path::new(&file))?;
+        // 删除的博客对应的文件
+        if workdir.join(file).exists() {
+            index.add_path(Path::new(&file))?;
+        } else {
+            index.remove_path(Path::new(&file))?;
+        }
     }
     index.write()?;
     let oid = index.write_tree()?;
-    let parent_commit = find_last_commit(&repo)?;
+    // 空仓库的第一次提交没有父提交
+    let parent_commit = find_last_commit(&repo).ok();
+    let parents = parent_commit.iter().collect::<Vec<&Commit>>();
     let tree = repo.find_tree(oid)?;
     repo.commit(
         Some("HEAD"), //  point HEAD to our new commit
@@ -210,30 +403,121 @@ fn add_and_commit(
         &signature,   // committer
         message,      // commit message
         &tree,        // tree
-        &[&parent_commit],
+        &parents,
     )
 }
 
-fn create_callbacks<'a, 'b: 'a>(
+pub(crate) fn create_callbacks<'a, 'b: 'a>(
     info: &'a GitRepositoryInfo,
     password: &'b str,
 ) -> Result<RemoteCallbacks<'a>, GitError> {
     let mut callbacks = RemoteCallbacks::new();
-    let _ = callbacks.credentials(|str, str_opt, cred_type| Cred::userpass_plaintext(&info.name, password));
+    if super::ssh::is_ssh_url(&info.remote_url) {
+        let (public_key, private_key) = super::ssh::key_pair()
+            .map_err(|e| GitError::from_str(&format!("Failed to load deploy key: {:?}", e.0)))?
+            .ok_or_else(|| {
+                GitError::from_str("请先生成或上传部署密钥/Please generate or upload a deploy key first.")
+            })?;
+        // 密钥被拒绝时 libgit2 会一直重试，只尝试一次
+        let mut tried = false;
+        let _ = callbacks.credentials(move |_url, username, cred_type| {
+            let username = username.unwrap_or("git");
+            if cred_type.contains(CredentialType::USERNAME) {
+                return Cred::username(username);
+            }
+            if tried {
+                return Err(GitError::from_str("部署密钥被拒绝/The deploy key was rejected."));
+            }
+            tried = true;
+            Cred::ssh_key_from_memory(username, Some(&public_key), &private_key, None)
+        });
+        // 只信任 known_hosts 里的主机密钥
+        let port = super::ssh::url_port(&info.remote_url);
+        let _ = callbacks.certificate_check(move |cert, host| match cert.as_hostkey().and_then(|k| k.hash_sha256()) {
+            Some(hash) => super::ssh::verify_host(host, port, hash),
+            None => false,
+        });
+    } else {
+        // 调用方决定使用哪个密码/令牌，这里不会去读保存的
+        let password = String::from(password);
+        let mut tried = false;
+        let _ = callbacks.credentials(move |_url, _username, _cred_type| {
+            if tried || password.is_empty() {
+                return Err(GitError::from_str(
+                    "密码/令牌不正确或者没有保存/The credential is wrong or not saved.",
+                ));
+            }
+            tried = true;
+            Cred::userpass_plaintext(&info.name, &password)
+        });
+    }
     Ok(callbacks)
 }
 
-fn push(repo: &Repository, info: &GitRepositoryInfo, password: &str) -> Result<(), GitError> {
+fn push(repo: &Repository, info: &GitRepositoryInfo, password: &str, job: &JobContext) -> Result<(), GitError> {
     let mut remote = match repo.find_remote("origin") {
         Ok(r) => r,
         Err(_) => repo.remote("origin", &info.remote_url)?,
     };
-    remote.connect_auth(Direction::Push, Some(create_callbacks(info, password)?), None)?;
+    remote
+        .connect_auth(Direction::Push, Some(create_callbacks(info, password)?), None)
+        .map_err(super::ssh::explain)?;
     let branch_name = info.branch_name.as_ref().unwrap();
     let refs = format!("refs/heads/{}:refs/heads/{}", branch_name, branch_name);
     repo.remote_add_push("origin", &refs)?;
+    let mut callbacks = create_callbacks(info, password)?;
+    callbacks.push_transfer_progress(|current, total, bytes| {
+        if current == total {
+            job.log(format!("Sent {}/{} objects in {} bytes", current, total, bytes));
+        }
+    });
+    // 远程拒绝更新时 push 本身不会返回错误
+    callbacks.push_update_reference(|name, status| match status {
+        Some(message) => Err(GitError::from_str(&format!("Remote rejected {}: {}", name, message))),
+        None => Ok(()),
+    });
     let mut push_options = PushOptions::default();
-    push_options.remote_callbacks(create_callbacks(info, password)?);
+    push_options.remote_callbacks(callbacks);
+
+    remote
+        .push(&[&refs], Some(&mut push_options))
+        .map_err(super::ssh::explain)
+}
+
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::git::ConflictStrategy;
 
-    remote.push(&[&refs], Some(&mut push_options))
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn push_commit_left_by_cancelled_job() {
+        testing::work_dir();
+        let bare = testing::temp_dir("sync-bare");
+        let remote = Repository::init_bare(&bare).unwrap();
+        let info = GitRepositoryInfo {
+            name: String::from("Blog"),
+            email: String::from("blog@example.com"),
+            remote_url: bare.display().to_string(),
+            repository_name: String::from("sync-test"),
+            branch_name: Some(String::from("main")),
+            conflict_strategy: ConflictStrategy::Abort,
+        };
+        let path = get_repository_path(&info);
+        let repo = clone_repository(&info, &path, "").unwrap();
+        checkout_branch(&repo, "main").unwrap();
+        std::fs::write(path.join("index.html"), "home").unwrap();
+
+        // 提交之后、推送之前取消
+        assert!(sync_to_remote(&info, "", &JobContext::detached(true)).is_err());
+        assert!(remote.find_reference("refs/heads/main").is_err());
+        assert!(is_ahead_of_remote(&repo, &info).unwrap());
+
+        // 没有新的修改也要推送上次的提交
+        sync_to_remote(&info, "", &JobContext::detached(false)).unwrap();
+        let head = find_last_commit(&repo).unwrap().id();
+        assert_eq!(head, remote.refname_to_id("refs/heads/main").unwrap());
+        assert!(!is_ahead_of_remote(&repo, &info).unwrap());
+    }
 }
@@ -1,2 +1,5 @@
+pub(crate) mod credential;
 pub(crate) mod git;
+pub(crate) mod publish;
 pub(crate) mod pull;
+pub(crate) mod ssh;
@@ -0,0 +1,367 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use blog_common::{
+    dto::git::{GitPreview, GitPushInfo, GitPushResult, GitRepositoryInfo, PublishEvent, PublishJob, PublishState},
+    result::Error,
+    util::time,
+};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use tokio::sync::broadcast;
+
+use crate::{
+    service::export,
+    util::{
+        common,
+        result::{ErrorWrapper, Result},
+    },
+};
+
+// 和仓库放在同一个目录
+const HISTORY_FILE: &str = "publish-history.json";
+const MAX_HISTORY: usize = 30;
+const MAX_LOG_LINES: usize = 1000;
+
+struct Jobs {
+    // 最新的在前面
+    history: VecDeque<PublishJob>,
+    cancel_flags: HashMap<String, Arc<AtomicBool>>,
+}
+
+lazy_static! {
+    static ref JOBS: Mutex<Option<Jobs>> = Mutex::new(None);
+    static ref EVENTS: broadcast::Sender<PublishEvent> = broadcast::channel(256).0;
+    // 同一时间只有一个任务操作仓库，其它任务排队
+    static ref RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+fn load_history() -> VecDeque<PublishJob> {
+    let path = match super::ssh::key_dir() {
+        Ok(d) => d.join(HISTORY_FILE),
+        Err(_) => return VecDeque::new(),
+    };
+    let mut history = std::fs::read(path)
+        .ok()
+        .and_then(|d| serde_json::from_slice::<VecDeque<PublishJob>>(&d).ok())
+        .unwrap_or_default();
+    // 服务器重启时还没结束的任务
+    for job in history.iter_mut().filter(|j| !j.state.is_finished()) {
+        job.state = PublishState::Failed;
+        job.error = Some(String::from("服务器已重启/Interrupted by a server restart."));
+    }
+    history
+}
+
+fn with_jobs<R>(f: impl FnOnce(&mut Jobs) -> R) -> R {
+    let mut jobs = JOBS.lock();
+    let jobs = jobs.get_or_insert_with(|| Jobs {
+        history: load_history(),
+        cancel_flags: HashMap::new(),
+    });
+    f(jobs)
+}
+
+fn save_history() {
+    let data = with_jobs(|jobs| serde_json::to_vec(&jobs.history));
+    let result = data
+        .map_err(ErrorWrapper::from)
+        .and_then(|d| Ok(std::fs::write(super::ssh::key_dir()?.join(HISTORY_FILE), d)?));
+    if let Err(e) = result {
+        eprintln!("Failed to save publish history: {:?}", e.0);
+    }
+}
+
+fn update_job(id: &str, f: impl FnOnce(&mut PublishJob)) {
+    with_jobs(|jobs| {
+        if let Some(job) = jobs.history.iter_mut().find(|j| j.id == id) {
+            f(job);
+        }
+    });
+}
+
+// 传给拉取、提交和推送，用来记录进度和检查是否已取消
+#[derive(Clone)]
+pub(crate) struct JobContext {
+    id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    fn send(&self, state: PublishState, line: Option<String>) {
+        // 没有页面在监听时发送失败，忽略
+        let _ = EVENTS.send(PublishEvent {
+            job_id: self.id.clone(),
+            state,
+            line,
+        });
+    }
+
+    fn state(&self) -> PublishState {
+        let state = with_jobs(|jobs| jobs.history.iter().find(|j| j.id == self.id).map(|j| j.state));
+        state.unwrap_or(PublishState::Failed)
+    }
+
+    pub(crate) fn log(&self, line: impl Into<String>) {
+        let line = line.into();
+        println!("[publish {}] {}", self.id, line);
+        update_job(&self.id, |job| {
+            if job.logs.len() < MAX_LOG_LINES {
+                job.logs.push(line.clone());
+            }
+        });
+        self.send(self.state(), Some(line));
+    }
+
+    pub(crate) fn set_state(&self, state: PublishState) {
+        update_job(&self.id, |job| job.state = state);
+        self.send(state, None);
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // 测试用，不在任务列表里
+    #[cfg(test)]
+    pub(crate) fn detached(cancelled: bool) -> Self {
+        JobContext {
+            id: common::simple_uuid(),
+            cancelled: Arc::new(AtomicBool::new(cancelled)),
+        }
+    }
+
+    pub(crate) fn log_export(&self, result: &GitPushResult) {
+        let c = &result.changes;
+        self.log(format!(
+            "Exported: {} added, {} updated, {} removed, {} unchanged",
+            c.added.len(),
+            c.updated.len(),
+            c.removed.len(),
+            c.unchanged
+        ));
+        for m in result.missing.iter() {
+            self.log(format!("Missing file: {}", m));
+        }
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::BusinessException(String::from("已取消/Cancelled.")).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub async fn start(mut push_info: GitPushInfo) -> Result<PublishJob> {
+    let info = super::git::must_get_repository_info()
+        .await
+        .map_err(Error::BusinessException)?;
+    // 没有输入时使用 Git Pages 保存的密码/令牌
+    let entered = !push_info.repo_credential.is_empty();
+    if !entered {
+        push_info.repo_credential = super::credential::load()?.unwrap_or_default();
+    }
+    let (exporter, subdirectory) = (push_info.exporter.clone(), push_info.subdirectory.clone());
+    let job = spawn(
+        "",
+        &exporter,
+        push_info.render_html,
+        &subdirectory,
+        move |ctx| async move {
+            let result = publish(&ctx, &info, &push_info).await?;
+            if push_info.save_credential && entered {
+                if let Err(e) = super::credential::save(&push_info.repo_credential) {
+                    ctx.log(format!("Failed to save git credential: {:?}", e.0));
+                }
+            }
+            Ok(result)
+        },
+    );
+    Ok(job)
+}
+
+// 创建任务并在后台排队执行，target 是部署目标的名称
+pub(crate) fn spawn<F, T>(target: &str, exporter: &str, render_html: bool, subdirectory: &str, task: F) -> PublishJob
+where
+    F: FnOnce(JobContext) -> T + Send + 'static,
+    T: Future<Output = Result<GitPushResult>> + Send + 'static,
+{
+    let id = format!(
+        "{}-{}",
+        time::unix_epoch_sec(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    );
+    let job = PublishJob {
+        id: id.clone(),
+        target: String::from(target),
+        state: PublishState::Queued,
+        exporter: String::from(exporter),
+        render_html,
+        subdirectory: String::from(subdirectory),
+        created_at: time::unix_epoch_sec(),
+        finished_at: 0,
+        logs: Vec::new(),
+        result: None,
+        error: None,
+    };
+    let ctx = JobContext {
+        id,
+        cancelled: Arc::new(AtomicBool::new(false)),
+    };
+    with_jobs(|jobs| {
+        jobs.history.push_front(job.clone());
+        jobs.cancel_flags.insert(ctx.id.clone(), ctx.cancelled.clone());
+        // 只删除已经结束的任务
+        while jobs.history.len() > MAX_HISTORY {
+            match jobs.history.iter().rposition(|j| j.state.is_finished()) {
+                Some(i) => {
+                    jobs.history.remove(i);
+                },
+                None => break,
+            }
+        }
+    });
+    save_history();
+    tokio::spawn(run(ctx.clone(), task(ctx)));
+    job
+}
+
+async fn run(ctx: JobContext, task: impl Future<Output = Result<GitPushResult>>) {
+    let _running = RUNNING.lock().await;
+    let result = match ctx.check_cancelled() {
+        Ok(_) => task.await,
+        Err(e) => Err(e),
+    };
+    let state = match result {
+        Ok(_) => PublishState::Done,
+        Err(_) if ctx.is_cancelled() => PublishState::Cancelled,
+        Err(_) => PublishState::Failed,
+    };
+    if let Err(e) = &result {
+        ctx.log(format!("{}", e.0));
+    }
+    update_job(&ctx.id, |job| {
+        job.finished_at = time::unix_epoch_sec();
+        match result {
+            Ok(r) => job.result = Some(r),
+            Err(e) => job.error = Some(format!("{}", e.0)),
+        }
+    });
+    with_jobs(|jobs| jobs.cancel_flags.remove(&ctx.id));
+    ctx.set_state(state);
+    save_history();
+}
+
+// 拉取、导出、提交并推送到 Git 仓库
+pub(crate) async fn publish(
+    ctx: &JobContext,
+    info: &GitRepositoryInfo,
+    push_info: &GitPushInfo,
+) -> Result<GitPushResult> {
+    ctx.set_state(PublishState::Pulling);
+    let (c, i, p) = (ctx.clone(), info.clone(), push_info.repo_credential.clone());
+    tokio::task::spawn_blocking(move || super::pull::pull(&i, &p, &c))
+        .await
+        .map_err(|e| Error::BusinessException(format!("Failed pull: {}", e)))?
+        .map_err(|e| Error::BusinessException(format!("Failed pull: {}", e)))?;
+
+    ctx.check_cancelled()?;
+    ctx.set_state(PublishState::Exporting);
+    let result = export::git(info, push_info).await?;
+    ctx.log_export(&result);
+
+    ctx.check_cancelled()?;
+    ctx.set_state(PublishState::Committing);
+    let (c, i, p) = (ctx.clone(), info.clone(), push_info.repo_credential.clone());
+    tokio::task::spawn_blocking(move || super::git::sync_to_remote(&i, &p, &c))
+        .await
+        .map_err(|e| Error::BusinessException(format!("Failed to push posts to git: {}", e)))?
+        .map_err(|e| Error::BusinessException(format!("Failed to push posts to git: {}", e)))?;
+    Ok(result)
+}
+
+// 目标还有没结束的任务
+pub(crate) fn is_running(target: &str) -> bool {
+    with_jobs(|jobs| {
+        jobs.history
+            .iter()
+            .any(|j| j.target == target && !j.state.is_finished())
+    })
+}
+
+// 把 HEAD 的文件复制到临时目录，在那里导出之后和 HEAD 比较；不会修改仓库的工作区，也不会拉取远程的修改
+pub async fn preview(push_info: GitPushInfo) -> Result<GitPreview> {
+    let info = super::git::must_get_repository_info()
+        .await
+        .map_err(Error::BusinessException)?;
+    let dir = std::env::temp_dir().join(format!("blog-preview-{}", common::simple_uuid()));
+    let result = preview_in(&info, &push_info, &dir).await;
+    if dir.exists() {
+        if let Err(e) = std::fs::remove_dir_all(dir.as_path()) {
+            eprintln!("Failed to remove {}: {:?}", dir.display(), e);
+        }
+    }
+    result
+}
+
+async fn preview_in(info: &GitRepositoryInfo, push_info: &GitPushInfo, dir: &Path) -> Result<GitPreview> {
+    let failed = |e: String| Error::BusinessException(format!("Failed to preview changes: {}", e));
+    let (i, d) = (info.clone(), dir.to_path_buf());
+    tokio::task::spawn_blocking(move || super::git::checkout_head_to(&i, &d))
+        .await
+        .map_err(|e| failed(e.to_string()))?
+        .map_err(|e| failed(e.to_string()))?;
+    let root = export::export_root(dir, &push_info.subdirectory)?;
+    let exported = export::to_directory(&root, &push_info.exporter, push_info.render_html).await?;
+    let (i, d) = (info.clone(), dir.to_path_buf());
+    let files = tokio::task::spawn_blocking(move || super::git::changes_against_head(&i, &d))
+        .await
+        .map_err(|e| failed(e.to_string()))?
+        .map_err(|e| failed(e.to_string()))?;
+    Ok(GitPreview {
+        files,
+        missing: exported.missing,
+    })
+}
+
+pub fn cancel(id: &str) -> Result<PublishJob> {
+    let flag = with_jobs(|jobs| jobs.cancel_flags.get(id).cloned());
+    match flag {
+        Some(f) => f.store(true, Ordering::Relaxed),
+        None => return Err(Error::BusinessException(String::from("任务已经结束/The job has finished.")).into()),
+    }
+    job(id)
+}
+
+pub fn job(id: &str) -> Result<PublishJob> {
+    with_jobs(|jobs| jobs.history.iter().find(|j| j.id == id).cloned())
+        .ok_or_else(|| Error::BusinessException(String::from("找不到发布任务/Publish job not found.")).into())
+}
+
+// 列表里不包含日志
+pub fn history() -> Vec<PublishJob> {
+    with_jobs(|jobs| {
+        jobs.history
+            .iter()
+            .map(|j| PublishJob {
+                logs: Vec::new(),
+                ..j.clone()
+            })
+            .collect()
+    })
+}
+
+pub fn subscribe() -> broadcast::Receiver<PublishEvent> {
+    EVENTS.subscribe()
+}
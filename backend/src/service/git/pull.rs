@@ -1,33 +1,42 @@
 // copied from https://github.com/rust-lang/git2-rs/blob/master/examples/pull.rs
 
-use std::io::{self, Write};
+use std::path::Path;
 use std::str;
 
-use blog_common::dto::git::GitRepositoryInfo;
-use git2::Repository;
+use blog_common::dto::git::{ConflictStrategy, GitRepositoryInfo};
+use git2::{
+    build::CheckoutBuilder, FileFavor, MergeOptions, ObjectType, Repository, RepositoryState, ResetType, StatusOptions,
+};
 
+use super::publish::JobContext;
+
+// GIT_INDEX_ENTRY_STAGEMASK
+const INDEX_ENTRY_STAGE_MASK: u16 = 0x3000;
+
+// 远程是空仓库或者还没有这个分支时返回 None，推送时会创建
 fn do_fetch<'a>(
     repo: &'a git2::Repository,
-    refs: &[&str],
+    remote_branch: &str,
     remote: &'a mut git2::Remote,
-) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
-    let mut cb = git2::RemoteCallbacks::new();
-
-    // Print out our transfer progress.
-    cb.transfer_progress(|stats| {
-        if stats.received_objects() == stats.total_objects() {
-            print!("Resolving deltas {}/{}\r", stats.indexed_deltas(), stats.total_deltas());
-        } else if stats.total_objects() > 0 {
-            print!(
-                "Received {}/{} objects ({}) in {} bytes\r",
-                stats.received_objects(),
-                stats.total_objects(),
-                stats.indexed_objects(),
-                stats.received_bytes()
-            );
+    mut cb: git2::RemoteCallbacks<'a>,
+    job: &'a JobContext,
+) -> Result<Option<git2::AnnotatedCommit<'a>>, git2::Error> {
+    // 每收到 10% 记录一次进度，返回 false 时取消拉取
+    let mut logged = 0;
+    cb.transfer_progress(move |stats| {
+        if stats.total_objects() > 0 {
+            let percent = stats.received_objects() * 100 / stats.total_objects();
+            if percent >= logged + 10 || stats.received_objects() == stats.total_objects() && logged < 100 {
+                logged = percent;
+                job.log(format!(
+                    "Received {}/{} objects in {} bytes",
+                    stats.received_objects(),
+                    stats.total_objects(),
+                    stats.received_bytes()
+                ));
+            }
         }
-        io::stdout().flush().unwrap();
-        true
+        !job.is_cancelled()
     });
 
     let mut fo = git2::FetchOptions::new();
@@ -35,32 +44,89 @@ fn do_fetch<'a>(
     // Always fetch all tags.
     // Perform a download and also update tips
     fo.download_tags(git2::AutotagOption::All);
-    println!("Fetching {} for repo", remote.name().unwrap());
-    remote.fetch(refs, Some(&mut fo), None)?;
+    // 使用默认的 refspec 更新 refs/remotes/origin/*，远程删除的分支也会删除
+    fo.prune(git2::FetchPrune::On);
+    job.log(format!("Fetching {} for repo", remote.name().unwrap_or("origin")));
+    remote.fetch::<&str>(&[], Some(&mut fo), None).map_err(super::ssh::explain)?;
 
     // If there are local objects (we got a thin pack), then tell the user
     // how many objects we saved from having to cross the network.
     let stats = remote.stats();
     if stats.local_objects() > 0 {
-        println!(
-            "\rReceived {}/{} objects in {} bytes (used {} local \
-             objects)",
+        job.log(format!(
+            "Received {}/{} objects in {} bytes (used {} local objects)",
             stats.indexed_objects(),
             stats.total_objects(),
             stats.received_bytes(),
             stats.local_objects()
-        );
+        ));
     } else {
-        println!(
-            "\rReceived {}/{} objects in {} bytes",
+        job.log(format!(
+            "Received {}/{} objects in {} bytes",
             stats.indexed_objects(),
             stats.total_objects(),
             stats.received_bytes()
-        );
+        ));
+    }
+
+    match repo.find_reference(&format!("refs/remotes/origin/{}", remote_branch)) {
+        Ok(reference) => Ok(Some(repo.reference_to_annotated_commit(&reference)?)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// 导出会重新生成所有文件，上次失败留下的修改和未跟踪的文件直接丢弃
+fn clean_working_tree(repo: &Repository, job: &JobContext) -> Result<(), git2::Error> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true);
+    if repo.state() == RepositoryState::Clean && repo.statuses(Some(&mut opts))?.is_empty() {
+        return Ok(());
+    }
+    job.log("Cleaning the working tree");
+    repo.cleanup_state()?;
+    match repo.head() {
+        Ok(head) => reset_hard(repo, &head.peel(ObjectType::Commit)?),
+        // 空仓库
+        Err(_) => Ok(()),
+    }
+}
+
+pub(crate) fn reset_hard(repo: &Repository, target: &git2::Object) -> Result<(), git2::Error> {
+    repo.reset(
+        target,
+        ResetType::Hard,
+        Some(CheckoutBuilder::new().force().remove_untracked(true)),
+    )
+}
+
+fn conflicted_paths(idx: &git2::Index) -> Result<Vec<String>, git2::Error> {
+    let mut paths = Vec::new();
+    for c in idx.conflicts()? {
+        let c = c?;
+        if let Some(e) = c.our.as_ref().or(c.their.as_ref()).or(c.ancestor.as_ref()) {
+            paths.push(String::from_utf8_lossy(&e.path).to_string());
+        }
     }
+    Ok(paths)
+}
 
-    let fetch_head = repo.find_reference("FETCH_HEAD")?;
-    Ok(repo.reference_to_annotated_commit(&fetch_head)?)
+// file_favor 只能解决内容冲突，一边删除、一边修改这样的冲突在这里选择一边
+fn resolve_conflicts(idx: &mut git2::Index, ours: bool) -> Result<(), git2::Error> {
+    let conflicts = idx.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+    for c in conflicts {
+        let path = match c.our.as_ref().or(c.their.as_ref()).or(c.ancestor.as_ref()) {
+            Some(e) => String::from_utf8_lossy(&e.path).to_string(),
+            None => continue,
+        };
+        // 同时删除这个路径的所有冲突条目
+        idx.remove_path(Path::new(&path))?;
+        if let Some(mut entry) = if ours { c.our } else { c.their } {
+            entry.flags &= !INDEX_ENTRY_STAGE_MASK;
+            idx.add(&entry)?;
+        }
+    }
+    Ok(())
 }
 
 fn fast_forward(repo: &Repository, lb: &mut git2::Reference, rc: &git2::AnnotatedCommit) -> Result<(), git2::Error> {
@@ -69,7 +135,6 @@ fn fast_forward(repo: &Repository, lb: &mut git2::Reference, rc: &git2::Annotate
         None => String::from_utf8_lossy(lb.name_bytes()).to_string(),
     };
     let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
-    println!("{}", msg);
     lb.set_target(rc.id(), &msg)?;
     repo.set_head(&name)?;
     repo.checkout_head(Some(
@@ -84,27 +149,47 @@ fn fast_forward(repo: &Repository, lb: &mut git2::Reference, rc: &git2::Annotate
 
 fn normal_merge(
     repo: &Repository,
+    info: &GitRepositoryInfo,
     local: &git2::AnnotatedCommit,
     remote: &git2::AnnotatedCommit,
+    job: &JobContext,
 ) -> Result<(), git2::Error> {
+    let strategy = info.conflict_strategy;
     let local_tree = repo.find_commit(local.id())?.tree()?;
     let remote_tree = repo.find_commit(remote.id())?.tree()?;
     let ancestor = repo.find_commit(repo.merge_base(local.id(), remote.id())?)?.tree()?;
-    let mut idx = repo.merge_trees(&ancestor, &local_tree, &remote_tree, None)?;
+    let mut opts = MergeOptions::new();
+    match strategy {
+        ConflictStrategy::Ours => {
+            opts.file_favor(FileFavor::Ours);
+        },
+        ConflictStrategy::Theirs => {
+            opts.file_favor(FileFavor::Theirs);
+        },
+        _ => {},
+    }
+    let mut idx = repo.merge_trees(&ancestor, &local_tree, &remote_tree, Some(&opts))?;
 
     if idx.has_conflicts() {
-        println!("Merge conficts detected...");
-        repo.checkout_index(Some(&mut idx), None)?;
-        return Ok(());
+        let paths = conflicted_paths(&idx)?;
+        if strategy != ConflictStrategy::Ours && strategy != ConflictStrategy::Theirs {
+            // 冲突的索引没有检出，工作区还是干净的
+            return Err(git2::Error::from_str(&format!(
+                "合并冲突，请选择冲突处理方式后重试/Merge conflicts, choose a conflict strategy and retry: {}",
+                paths.join(", ")
+            )));
+        }
+        job.log(format!("Resolving conflicts with {:?}: {}", strategy, paths.join(", ")));
+        resolve_conflicts(&mut idx, strategy == ConflictStrategy::Ours)?;
     }
     let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
     // now create the merge commit
     let msg = format!("Merge: {} into {}", remote.id(), local.id());
-    let sig = repo.signature()?;
+    let sig = super::git::get_signature(repo, info)?;
     let local_commit = repo.find_commit(local.id())?;
     let remote_commit = repo.find_commit(remote.id())?;
     // Do our merge commit and set current branch head to that commit.
-    let _merge_commit = repo.commit(
+    let merge_commit = repo.commit(
         Some("HEAD"),
         &sig,
         &sig,
@@ -112,22 +197,34 @@ fn normal_merge(
         &result_tree,
         &[&local_commit, &remote_commit],
     )?;
+    job.log(format!("Merged {} into {}: {}", remote.id(), local.id(), merge_commit));
     // Set working tree to match head.
-    repo.checkout_head(None)?;
+    repo.checkout_head(Some(CheckoutBuilder::default().force()))?;
     Ok(())
 }
 
 fn do_merge<'a>(
     repo: &'a Repository,
+    info: &GitRepositoryInfo,
     remote_branch: &str,
     fetch_commit: git2::AnnotatedCommit<'a>,
+    job: &JobContext,
 ) -> Result<(), git2::Error> {
+    // 博客是唯一的来源，直接使用远程的版本，本地没推送的提交会在导出后重新生成
+    if info.conflict_strategy == ConflictStrategy::ResetToRemote {
+        let refname = format!("refs/heads/{}", remote_branch);
+        let commit = repo.find_commit(fetch_commit.id())?;
+        job.log(format!("Resetting {} to {}", remote_branch, commit.id()));
+        repo.reference(&refname, commit.id(), true, "Reset to remote")?;
+        repo.set_head(&refname)?;
+        return reset_hard(repo, commit.as_object());
+    }
     // 1. do a merge analysis
     let analysis = repo.merge_analysis(&[&fetch_commit])?;
 
     // 2. Do the appopriate merge
     if analysis.0.is_fast_forward() {
-        println!("Doing a fast forward");
+        job.log("Doing a fast forward");
         // do a fast forward
         let refname = format!("refs/heads/{}", remote_branch);
         match repo.find_reference(&refname) {
@@ -156,18 +253,96 @@ fn do_merge<'a>(
     } else if analysis.0.is_normal() {
         // do a normal merge
         let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-        normal_merge(&repo, &head_commit, &fetch_commit)?;
+        normal_merge(&repo, info, &head_commit, &fetch_commit, job)?;
     } else {
-        println!("Nothing to do...");
+        job.log("Already up to date");
     }
     Ok(())
 }
 
-pub(crate) fn pull(info: &GitRepositoryInfo) -> Result<(), git2::Error> {
+pub(crate) fn pull(info: &GitRepositoryInfo, password: &str, job: &JobContext) -> Result<(), git2::Error> {
     // let remote_name = args.arg_remote.as_ref().map(|s| &s[..]).unwrap_or("origin");
     let remote_branch = info.branch_name.as_ref().map(|s| &s[..]).unwrap_or("main");
     let repo = super::git::get_repo(info)?;
+    clean_working_tree(&repo, job)?;
     let mut remote = repo.find_remote("origin")?;
-    let fetch_commit = do_fetch(&repo, &[remote_branch], &mut remote)?;
-    do_merge(&repo, &remote_branch, fetch_commit)
+    let callbacks = super::git::create_callbacks(info, password)?;
+    let fetch_commit = do_fetch(&repo, remote_branch, &mut remote, callbacks, job)?;
+    match fetch_commit {
+        Some(fetch_commit) => do_merge(&repo, info, remote_branch, fetch_commit, job),
+        None => {
+            job.log(format!("Remote branch {} not found, skip pulling", remote_branch));
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    fn tree<'a>(repo: &'a Repository, files: &[(&str, &str)]) -> Result<git2::Tree<'a>, git2::Error> {
+        let mut builder = repo.treebuilder(None)?;
+        for (name, content) in files {
+            builder.insert(name, repo.blob(content.as_bytes())?, 0o100644)?;
+        }
+        repo.find_tree(builder.write()?)
+    }
+
+    fn merged(strategy: ConflictStrategy) -> Result<(Vec<String>, Option<String>), git2::Error> {
+        let repo = Repository::init(testing::temp_dir(&format!("pull-{:?}", strategy)))?;
+        let ancestor = tree(&repo, &[("index.html", "base"), ("about.html", "about")])?;
+        let ours = tree(&repo, &[("index.html", "ours")])?;
+        let theirs = tree(&repo, &[("index.html", "theirs"), ("about.html", "about 2")])?;
+        let mut opts = MergeOptions::new();
+        match strategy {
+            ConflictStrategy::Ours => {
+                opts.file_favor(FileFavor::Ours);
+            },
+            ConflictStrategy::Theirs => {
+                opts.file_favor(FileFavor::Theirs);
+            },
+            _ => {},
+        }
+        let mut idx = repo.merge_trees(&ancestor, &ours, &theirs, Some(&opts))?;
+        let mut paths = conflicted_paths(&idx)?;
+        paths.sort();
+        if strategy == ConflictStrategy::Ours || strategy == ConflictStrategy::Theirs {
+            resolve_conflicts(&mut idx, strategy == ConflictStrategy::Ours)?;
+            assert!(!idx.has_conflicts());
+        }
+        let about = match idx.get_path(Path::new("about.html"), 0) {
+            Some(e) => Some(String::from_utf8_lossy(repo.find_blob(e.id)?.content()).to_string()),
+            None => None,
+        };
+        if strategy != ConflictStrategy::Abort {
+            let index = idx.get_path(Path::new("index.html"), 0).unwrap();
+            let expected = if strategy == ConflictStrategy::Ours { "ours" } else { "theirs" };
+            assert_eq!(expected, String::from_utf8_lossy(repo.find_blob(index.id)?.content()));
+        }
+        Ok((paths, about))
+    }
+
+    #[test]
+    fn resolve_by_strategy() -> Result<(), git2::Error> {
+        // 一边删除、一边修改的 about.html 只能由 resolve_conflicts 处理
+        let (paths, _) = merged(ConflictStrategy::Abort)?;
+        assert_eq!(vec![String::from("about.html"), String::from("index.html")], paths);
+        let (paths, about) = merged(ConflictStrategy::Ours)?;
+        assert_eq!(vec![String::from("about.html")], paths);
+        assert_eq!(None, about);
+        let (paths, about) = merged(ConflictStrategy::Theirs)?;
+        assert_eq!(vec![String::from("about.html")], paths);
+        assert_eq!(Some(String::from("about 2")), about);
+        Ok(())
+    }
+
+    #[test]
+    fn strategy_names() {
+        assert_eq!(ConflictStrategy::Abort, ConflictStrategy::default());
+        assert_eq!("\"reset_to_remote\"", serde_json::to_string(&ConflictStrategy::ResetToRemote).unwrap());
+        let strategy: ConflictStrategy = serde_json::from_str("\"theirs\"").unwrap();
+        assert_eq!(ConflictStrategy::Theirs, strategy);
+    }
 }
@@ -0,0 +1,282 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use blog_common::{dto::git::DeployKey, result::Error};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use ssh_key::{rand_core::OsRng, Algorithm, HashAlg, LineEnding, PrivateKey, PublicKey};
+
+use crate::util::{crypt, result::Result};
+
+// 和仓库放在同一个目录
+const KEY_DIR: &str = "git-pages";
+const PRIVATE_KEY_FILE: &str = "deploy_key.enc";
+const PUBLIC_KEY_FILE: &str = "deploy_key.pub";
+const KNOWN_HOSTS_FILE: &str = "known_hosts";
+const KEY_PURPOSE: &str = "git-deploy-key";
+const DEFAULT_PORT: u16 = 22;
+
+lazy_static! {
+    // libgit2 校验主机密钥失败时只返回一个笼统的错误，这里保存具体原因
+    static ref HOST_KEY_ERROR: Mutex<Option<String>> = Mutex::new(None);
+}
+
+pub(crate) fn key_dir() -> Result<PathBuf> {
+    let dir = std::env::current_dir()?.join(KEY_DIR);
+    if !dir.exists() {
+        std::fs::create_dir_all(dir.as_path())?;
+    }
+    Ok(dir)
+}
+
+// ssh://git@host:22/path/repo.git 或者 git@host:path/repo.git
+pub fn is_ssh_url(url: &str) -> bool {
+    url.starts_with("ssh://") || (!url.contains("://") && url.contains('@') && url.contains(':'))
+}
+
+// 只有 ssh:// 格式的地址可以指定端口
+pub(crate) fn url_port(url: &str) -> u16 {
+    url.strip_prefix("ssh://")
+        .and_then(|rest| rest.split('/').next())
+        .map(|authority| authority.rsplit('@').next().unwrap_or(authority))
+        .and_then(|host| host.rsplit_once(':'))
+        .and_then(|(_, port)| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+// known_hosts 里的主机都按 主机:端口 比较
+fn host_key(host: &str, port: u16) -> String {
+    format!("{}:{}", host.to_lowercase(), port)
+}
+
+fn save(key: &PrivateKey) -> Result<DeployKey> {
+    let dir = key_dir()?;
+    let private_key = key.to_openssh(LineEnding::LF)?;
+    let encrypted = crypt::encrypt(KEY_PURPOSE, private_key.as_bytes())?;
+    std::fs::write(dir.join(PRIVATE_KEY_FILE), encrypted)?;
+    let public_key = key.public_key().to_openssh()?;
+    std::fs::write(dir.join(PUBLIC_KEY_FILE), &public_key)?;
+    Ok(DeployKey {
+        fingerprint: key.public_key().fingerprint(HashAlg::Sha256).to_string(),
+        public_key,
+    })
+}
+
+pub fn generate() -> Result<DeployKey> {
+    let mut key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519)?;
+    key.set_comment("blog-deploy-key");
+    save(&key)
+}
+
+pub fn upload(private_key: &str) -> Result<DeployKey> {
+    let key = PrivateKey::from_openssh(private_key.trim())?;
+    if key.is_encrypted() {
+        return Err(Error::BusinessException(String::from(
+            "不支持有密码的私钥/Keys with a passphrase are not supported.",
+        ))
+        .into());
+    }
+    save(&key)
+}
+
+pub fn deploy_key() -> Result<Option<DeployKey>> {
+    let path = key_dir()?.join(PUBLIC_KEY_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let public_key = std::fs::read_to_string(path)?.trim().to_string();
+    let fingerprint = PublicKey::from_openssh(&public_key)?
+        .fingerprint(HashAlg::Sha256)
+        .to_string();
+    Ok(Some(DeployKey {
+        public_key,
+        fingerprint,
+    }))
+}
+
+pub fn remove() -> Result<()> {
+    let dir = key_dir()?;
+    for name in [PRIVATE_KEY_FILE, PUBLIC_KEY_FILE] {
+        let path = dir.join(name);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+// 返回 (公钥, 私钥)
+pub(crate) fn key_pair() -> Result<Option<(String, String)>> {
+    let dir = key_dir()?;
+    let path = dir.join(PRIVATE_KEY_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let private_key = String::from_utf8(crypt::decrypt(KEY_PURPOSE, &std::fs::read(path)?)?)?;
+    let public_key = std::fs::read_to_string(dir.join(PUBLIC_KEY_FILE))?;
+    Ok(Some((public_key.trim().to_string(), private_key)))
+}
+
+// 给外部的 ssh 命令使用，文件只有当前用户可以读写，用完要删除
+pub(crate) fn write_private_key(path: &Path) -> Result<bool> {
+    let private_key = match key_pair()? {
+        Some((_, private_key)) => private_key,
+        None => return Ok(false),
+    };
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(private_key.as_bytes())?;
+    Ok(true)
+}
+
+pub(crate) fn known_hosts_path() -> Result<PathBuf> {
+    Ok(key_dir()?.join(KNOWN_HOSTS_FILE))
+}
+
+pub fn known_hosts() -> Result<String> {
+    let path = key_dir()?.join(KNOWN_HOSTS_FILE);
+    if path.is_file() {
+        Ok(std::fs::read_to_string(path)?)
+    } else {
+        Ok(String::new())
+    }
+}
+
+// 每行：主机[,主机] 密钥类型 base64 [注释]，不支持哈希过的主机名
+fn parse_known_hosts(content: &str) -> Result<Vec<(Vec<String>, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    for line in content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+    {
+        let invalid = || Error::BusinessException(format!("known_hosts 格式不正确/Invalid known_hosts line: {}", line));
+        let parts = line.split_whitespace().collect::<Vec<&str>>();
+        if parts.len() < 3 || parts[0].starts_with('|') || parts[0].starts_with('@') {
+            return Err(invalid().into());
+        }
+        let blob = base64::decode(parts[2]).map_err(|_| invalid())?;
+        let mut hosts = Vec::new();
+        for h in parts[0].split(',') {
+            // [host]:port 是非默认端口
+            let host = match h.strip_prefix('[').and_then(|h| h.split_once("]:")) {
+                Some((host, port)) => host_key(host, port.parse().map_err(|_| invalid())?),
+                None => host_key(h, DEFAULT_PORT),
+            };
+            hosts.push(host);
+        }
+        entries.push((hosts, blob));
+    }
+    Ok(entries)
+}
+
+pub fn update_known_hosts(content: &str) -> Result<()> {
+    parse_known_hosts(content)?;
+    std::fs::write(key_dir()?.join(KNOWN_HOSTS_FILE), content.trim())?;
+    Ok(())
+}
+
+fn fingerprint(hash: &[u8]) -> String {
+    format!("SHA256:{}", base64::encode_config(hash, base64::STANDARD_NO_PAD))
+}
+
+fn is_known(entries: &[(Vec<String>, Vec<u8>)], host: &str, hash: &[u8]) -> bool {
+    entries
+        .iter()
+        .any(|(hosts, blob)| hosts.iter().any(|h| h == host) && Sha256::digest(blob).as_slice() == hash)
+}
+
+// hash 是服务器提供的主机密钥的 SHA256，libgit2 回调里没有端口，从仓库地址里取
+pub(crate) fn verify_host(host: &str, port: u16, hash: &[u8]) -> bool {
+    let host = host_key(host, port);
+    let result = known_hosts()
+        .and_then(|c| parse_known_hosts(&c))
+        .map(|entries| is_known(&entries, &host, hash));
+    let message = match result {
+        Ok(true) => return true,
+        Ok(false) => format!(
+            "未知的主机密钥，请先添加到 known_hosts/Unknown host key for {} ({}), add it to known_hosts first.",
+            host,
+            fingerprint(hash)
+        ),
+        Err(e) => format!("{:?}", e.0),
+    };
+    eprintln!("{}", message);
+    *HOST_KEY_ERROR.lock() = Some(message);
+    false
+}
+
+// 如果是主机密钥校验失败，换成具体的原因
+pub(crate) fn explain(e: git2::Error) -> git2::Error {
+    match HOST_KEY_ERROR.lock().take() {
+        Some(message) => git2::Error::from_str(&message),
+        None => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    const KNOWN_HOSTS: &str = "\
+# comment
+github.com,GitHub.com ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl
+[git.example.com]:2222 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIFF1c2sgZXhhbXBsZSBrZXkgZm9yIHRlc3Rpbmcg
+";
+
+    #[test]
+    fn parse_hosts_with_ports() -> Result<()> {
+        let entries = parse_known_hosts(KNOWN_HOSTS)?;
+        assert_eq!(2, entries.len());
+        assert_eq!(vec!["github.com:22", "github.com:22"], entries[0].0);
+        assert_eq!(vec!["git.example.com:2222"], entries[1].0);
+        assert!(parse_known_hosts("[git.example.com]:ssh ssh-ed25519 AAAA").is_err());
+        assert!(parse_known_hosts("|1|hashed ssh-ed25519 AAAA").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn match_host_and_port() -> Result<()> {
+        let entries = parse_known_hosts(KNOWN_HOSTS)?;
+        let hash = Sha256::digest(&entries[1].1);
+        assert!(is_known(&entries, &host_key("git.example.com", 2222), hash.as_slice()));
+        // 同一个主机的其它端口是另外一台服务器
+        assert!(!is_known(&entries, &host_key("git.example.com", 22), hash.as_slice()));
+        // 主机名对了但是密钥不对
+        assert!(!is_known(&entries, &host_key("github.com", 22), hash.as_slice()));
+        assert!(!is_known(&entries, &host_key("gitlab.com", 22), hash.as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn port_from_url() {
+        assert_eq!(22, url_port("git@github.com:user/repo.git"));
+        assert_eq!(22, url_port("ssh://git@github.com/user/repo.git"));
+        assert_eq!(2222, url_port("ssh://git@git.example.com:2222/user/repo.git"));
+        assert_eq!(2222, url_port("ssh://[::1]:2222/repo.git"));
+        assert_eq!(22, url_port("ssh://[::1]/repo.git"));
+    }
+
+    #[test]
+    fn generate_and_reload_key() -> Result<()> {
+        testing::work_dir();
+        let key = generate()?;
+        assert!(key.public_key.starts_with("ssh-ed25519 "));
+        assert_eq!(Some(key.fingerprint.clone()), deploy_key()?.map(|k| k.fingerprint));
+
+        // 私钥加密保存，不能直接读出来
+        let encrypted = std::fs::read(key_dir()?.join(PRIVATE_KEY_FILE))?;
+        assert!(!String::from_utf8_lossy(&encrypted).contains("PRIVATE KEY"));
+        let (public_key, private_key) = key_pair()?.unwrap();
+        assert_eq!(key.public_key.trim(), public_key);
+        let reloaded = PrivateKey::from_openssh(&private_key)?;
+        assert_eq!(key.fingerprint, reloaded.public_key().fingerprint(HashAlg::Sha256).to_string());
+        Ok(())
+    }
+}
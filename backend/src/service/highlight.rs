@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use syntect::{
+    highlighting::{Color, Theme, ThemeSet},
+    html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle},
+    parsing::{ParseState, Scope, ScopeStack, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+use crate::util::result::Result;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+pub(crate) const DEFAULT_THEME: &'static str = "inspiredgithub";
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    static ref HL_LINES_REGEX: Regex = Regex::new(r#"hl_lines\s*=\s*\[?([0-9,\s"\-]+)\]?"#).unwrap();
+    static ref LINENOS_REGEX: Regex = Regex::new(r"\blinenos\b(\s*=\s*(\w+))?").unwrap();
+}
+
+// 代码块的 info string，例如：```rust {linenos hl_lines=[2,4-6]}
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct FenceInfo {
+    pub lang: String,
+    pub line_numbers: bool,
+    pub highlight_lines: HashSet<usize>,
+}
+
+// line_count 是代码块的行数，超出的 hl_lines 直接忽略，避免很大的范围占用内存
+pub(crate) fn parse_fence_info(info: &str, line_count: usize) -> FenceInfo {
+    let info = info.trim();
+    let (lang, rest) = match info.find(|c: char| c.is_whitespace() || c == '{') {
+        Some(pos) => (&info[..pos], &info[pos..]),
+        None => (info, ""),
+    };
+    let mut fence_info = FenceInfo {
+        lang: lang.to_string(),
+        ..Default::default()
+    };
+    if let Some(c) = LINENOS_REGEX.captures(rest) {
+        fence_info.line_numbers = c.get(2).map_or(true, |v| v.as_str() != "false");
+    }
+    if let Some(c) = HL_LINES_REGEX.captures(rest) {
+        for range in c[1].split(|c: char| c == ',' || c.is_whitespace()) {
+            let range = range.trim_matches('"');
+            if range.is_empty() {
+                continue;
+            }
+            let mut bounds = range.splitn(2, '-').map(|n| n.trim().parse::<usize>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(start)), Some(Ok(end))) if start <= end => {
+                    fence_info.highlight_lines.extend(start..=end.min(line_count))
+                },
+                (Some(Ok(line)), None) if line <= line_count => {
+                    fence_info.highlight_lines.insert(line);
+                },
+                _ => {},
+            }
+        }
+    }
+    fence_info
+}
+
+fn theme_slug(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    slug.split('-').filter(|s| !s.is_empty()).collect::<Vec<&str>>().join("-")
+}
+
+pub fn themes() -> Vec<String> {
+    THEME_SET.themes.keys().map(|name| theme_slug(name)).collect()
+}
+
+fn find_theme(slug: &str) -> Option<&'static Theme> {
+    THEME_SET
+        .themes
+        .iter()
+        .find(|(name, _)| theme_slug(name) == slug)
+        .map(|(_, theme)| theme)
+}
+
+fn css_color(c: Color) -> String {
+    format!("rgba({},{},{},{:.2})", c.r, c.g, c.b, c.a as f32 / 255.0)
+}
+
+pub fn theme_css(slug: &str) -> Result<Option<String>> {
+    let theme = match find_theme(slug) {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)?;
+    // Bulma 的 `.content pre` 会覆盖主题的背景色
+    if let Some(background) = theme.settings.background {
+        css.push_str(&format!(".content pre.hl-code {{ background-color: {}; }}\n", css_color(background)));
+    }
+    let line_highlight = theme
+        .settings
+        .line_highlight
+        .map_or(String::from("rgba(255,235,59,0.25)"), css_color);
+    css.push_str(".hl-code .code-line { display: inline-block; width: 100%; }\n");
+    css.push_str(&format!(".hl-code .code-line.highlighted {{ background-color: {}; }}\n", line_highlight));
+    css.push_str(".hl-code .line-number { display: inline-block; min-width: 2.5em; padding-right: 1em; text-align: right; opacity: 0.5; user-select: none; }\n");
+    Ok(Some(css))
+}
+
+fn scope_classes(scope: &Scope) -> String {
+    let name = scope.build_string();
+    name.split('.')
+        .map(|atom| format!("hl-{}", atom))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn escape(s: &str) -> String {
+    v_htmlescape::escape(s).to_string()
+}
+
+pub fn highlight(info: &str, code: &str) -> Result<String> {
+    let fence_info = parse_fence_info(info, LinesWithEndings::from(code).count());
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(&fence_info.lang)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut parse_state = ParseState::new(syntax);
+    let mut scope_stack = ScopeStack::new();
+
+    let mut html = String::with_capacity(code.len() * 4);
+    html.push_str("<pre class=\"hl-code\"><code");
+    if !fence_info.lang.is_empty() {
+        html.push_str(" class=\"language-");
+        html.push_str(&escape(&fence_info.lang));
+        html.push('"');
+    }
+    html.push('>');
+    for (idx, line) in LinesWithEndings::from(code).enumerate() {
+        let line_number = idx + 1;
+        html.push_str("<span class=\"code-line");
+        if fence_info.highlight_lines.contains(&line_number) {
+            html.push_str(" highlighted");
+        }
+        html.push_str("\">");
+        if fence_info.line_numbers {
+            html.push_str("<span class=\"line-number\">");
+            html.push_str(&line_number.to_string());
+            html.push_str("</span>");
+        }
+        // 每一行都是完整闭合的 span，这样才能单独给某一行加背景色
+        for scope in scope_stack.as_slice() {
+            html.push_str("<span class=\"");
+            html.push_str(&scope_classes(scope));
+            html.push_str("\">");
+        }
+        let ops = parse_state.parse_line(line, &SYNTAX_SET)?;
+        let (mut spans, _delta) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack)?;
+        if let Some(pos) = spans.rfind('\n') {
+            spans.remove(pos);
+        }
+        html.push_str(&spans);
+        for _ in 0..scope_stack.as_slice().len() {
+            html.push_str("</span>");
+        }
+        html.push_str("</span>\n");
+    }
+    html.push_str("</code></pre>\n");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fence_info() {
+        let info = parse_fence_info("rust {linenos hl_lines=[2,4-6]}", 10);
+        assert_eq!("rust", info.lang);
+        assert!(info.line_numbers);
+        assert_eq!(HashSet::from([2, 4, 5, 6]), info.highlight_lines);
+
+        let info = parse_fence_info("python{linenos=false hl_lines=\"1 3\"}", 10);
+        assert_eq!("python", info.lang);
+        assert!(!info.line_numbers);
+        assert_eq!(HashSet::from([1, 3]), info.highlight_lines);
+
+        assert_eq!(FenceInfo::default(), parse_fence_info("", 10));
+        assert_eq!("sh", parse_fence_info("  sh  ", 10).lang);
+    }
+
+    #[test]
+    fn clamp_highlight_lines() {
+        let info = parse_fence_info("rust {hl_lines=[1-99999999999]}", 3);
+        assert_eq!(HashSet::from([1, 2, 3]), info.highlight_lines);
+        assert!(parse_fence_info("rust {hl_lines=[5-2]}", 10).highlight_lines.is_empty());
+        assert_eq!(HashSet::from([2]), parse_fence_info("rust {hl_lines=[2 7]}", 5).highlight_lines);
+    }
+}
@@ -0,0 +1,580 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use blog_common::{
+    dto::{
+        import::{ImportReport, ImportSkipped, ImportedPost},
+        post::{PostData, Visibility},
+    },
+    result::Error,
+    util::time,
+};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    db::post,
+    util::{
+        date,
+        io::{self, SupportFileType},
+        result::Result,
+    },
+};
+
+// 解压后的总大小上限，避免压缩炸弹
+const MAX_UNCOMPRESSED_SIZE: u64 = 512 * 1024 * 1024;
+const IGNORED_DIRS: [&str; 6] = [".git", "node_modules", "__MACOSX", "archetypes", "themes", "scaffolds"];
+const IGNORED_POSTS: [&str; 3] = ["_index.md", "readme.md", "license.md"];
+
+lazy_static! {
+    static ref MARKDOWN_IMAGE_REGEX: Regex = Regex::new(r#"!\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+["'][^"']*["'])?\s*\)"#).unwrap();
+    static ref HTML_IMAGE_REGEX: Regex = Regex::new(r#"(?i)<img\s[^>]*?src\s*=\s*["']([^"']+)["']"#).unwrap();
+    static ref JEKYLL_FILENAME_REGEX: Regex = Regex::new(r"^(\d{4}-\d{2}-\d{2})-(.+)$").unwrap();
+}
+
+// 准备导入的一篇博客，images 是正文里引用的图片和它在导入文件里的路径，找不到时为 None
+pub(crate) struct Candidate {
+    pub(crate) source: String,
+    pub(crate) title: String,
+    pub(crate) created_at: Option<i64>,
+    pub(crate) tags: Vec<String>,
+    pub(crate) draft: bool,
+    pub(crate) content: String,
+    pub(crate) images: Vec<(String, Option<String>)>,
+    // 题图在导入文件里的路径
+    pub(crate) title_image: Option<String>,
+    // 原来的地址，用来生成跳转对照表
+    pub(crate) old_url: Option<String>,
+}
+
+fn is_markdown(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.ends_with(".md") || lower.ends_with(".markdown")
+}
+
+fn is_image(path: &str) -> bool {
+    extension(path).parse::<SupportFileType>().is_ok()
+}
+
+fn extension(path: &str) -> String {
+    path.rfind('.').map_or(String::new(), |pos| path[pos + 1..].to_lowercase())
+}
+
+fn is_ignored(path: &str) -> bool {
+    path.split('/').any(|p| IGNORED_DIRS.contains(&p))
+}
+
+// 只读取 Markdown 文件和图片
+pub(crate) fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut files = HashMap::new();
+    let mut total = 0u64;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = match file.enclosed_name() {
+            Some(p) => p.to_string_lossy().replace('\\', "/"),
+            None => continue,
+        };
+        if is_ignored(&path) || !(is_markdown(&path) || is_image(&path)) {
+            continue;
+        }
+        total += file.size();
+        if total > MAX_UNCOMPRESSED_SIZE {
+            return Err(Error::BusinessException(String::from("导入的文件太大/Import archive is too large.")).into());
+        }
+        let mut buf = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut buf)?;
+        files.insert(path, buf);
+    }
+    Ok(files)
+}
+
+pub(crate) fn read_dir(root: &Path) -> Result<HashMap<String, Vec<u8>>> {
+    if !root.is_dir() {
+        return Err(Error::BusinessException(String::from("目录不存在/Directory not found.")).into());
+    }
+    let mut files = HashMap::new();
+    let mut total = 0u64;
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = match path.strip_prefix(root) {
+                Ok(p) => p.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+            if is_ignored(&relative) {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() && (is_markdown(&relative) || is_image(&relative)) {
+                total += entry.metadata()?.len();
+                if total > MAX_UNCOMPRESSED_SIZE {
+                    return Err(Error::BusinessException(String::from("导入的文件太大/Import directory is too large.")).into());
+                }
+                files.insert(relative, std::fs::read(&path)?);
+            }
+        }
+    }
+    Ok(files)
+}
+
+// 去掉 ./ 和 ..，路径跳出根目录时返回 None
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for p in path.split('/') {
+        match p {
+            "" | "." => {},
+            ".." => {
+                parts.pop()?;
+            },
+            _ => parts.push(p),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rfind('/').map_or("", |pos| &path[..pos])
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rfind('/').map_or(path, |pos| &path[pos + 1..]);
+    name.rfind('.').map_or(name, |pos| &name[..pos])
+}
+
+pub(crate) fn is_local(url: &str) -> bool {
+    !(url.contains("://") || url.starts_with("//") || url.starts_with("data:") || url.starts_with("mailto:"))
+}
+
+// 相对路径按博客所在目录查找，再找 Hexo 的资源目录（和博客同名的目录）
+// 绝对路径按结尾匹配，这样 Hugo 的 static/、Hexo 的 source/ 以及压缩包里多出来的顶层目录都能找到
+pub(crate) fn resolve_image(files: &HashMap<String, Vec<u8>>, source: &str, url: &str) -> Option<String> {
+    let url = url.split(|c| c == '?' || c == '#').next().unwrap_or("").replace("%20", " ");
+    if url.is_empty() || !is_local(&url) || !is_image(&url) {
+        return None;
+    }
+    if url.starts_with('/') {
+        let relative = url.trim_start_matches('/');
+        let suffix = format!("/{}", relative);
+        return files
+            .keys()
+            .filter(|k| *k == relative || k.ends_with(&suffix))
+            .min_by_key(|k| k.len())
+            .cloned();
+    }
+    let dir = parent_dir(source);
+    let candidates = [
+        format!("{}/{}", dir, url),
+        format!("{}/{}/{}", dir, file_stem(source), url),
+    ];
+    candidates
+        .iter()
+        .filter_map(|c| normalize(c))
+        .find(|c| files.contains_key(c))
+}
+
+// 所有图片地址，包括外部图片
+pub(crate) fn image_urls(content: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    for regex in [&*MARKDOWN_IMAGE_REGEX, &*HTML_IMAGE_REGEX] {
+        for caps in regex.captures_iter(content) {
+            let url = caps[1].to_string();
+            if !refs.contains(&url) {
+                refs.push(url);
+            }
+        }
+    }
+    refs
+}
+
+// 需要从导入文件里找的本地图片
+pub(crate) fn image_references(content: &str) -> Vec<String> {
+    image_urls(content).into_iter().filter(|url| is_local(url)).collect()
+}
+
+// 只替换图片地址那一部分
+fn replace_url(caps: &Captures, mapping: &HashMap<String, String>) -> String {
+    let whole = caps.get(0).unwrap();
+    let url = caps.get(1).unwrap();
+    match mapping.get(url.as_str()) {
+        Some(new_url) => {
+            let s = whole.as_str();
+            let start = url.start() - whole.start();
+            let end = url.end() - whole.start();
+            format!("{}{}{}", &s[..start], new_url, &s[end..])
+        },
+        None => whole.as_str().to_string(),
+    }
+}
+
+pub(crate) fn rewrite_images(content: &str, mapping: &HashMap<String, String>) -> String {
+    let content = MARKDOWN_IMAGE_REGEX.replace_all(content, |caps: &Captures| replace_url(caps, mapping));
+    HTML_IMAGE_REGEX
+        .replace_all(&content, |caps: &Captures| replace_url(caps, mapping))
+        .to_string()
+}
+
+fn find_closing(lines: &[&str], marks: &[&str]) -> Option<usize> {
+    lines.iter().skip(1).position(|l| marks.contains(&l.trim_end())).map(|p| p + 1)
+}
+
+// ---: YAML，+++: TOML，另外支持 Hexo 省略开头 --- 的写法
+fn split_front_matter(text: &str) -> core::result::Result<(Option<Value>, String), String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let lines = text.split_inclusive('\n').collect::<Vec<&str>>();
+    let first = lines.first().map_or("", |l| l.trim_end());
+    let body_after = |end: usize| lines[end + 1..].concat();
+    if first == "---" {
+        if let Some(end) = find_closing(&lines, &["---", "..."]) {
+            let front = lines[1..end].concat();
+            let value = serde_yaml::from_str::<Value>(&front).map_err(|e| e.to_string())?;
+            return Ok((Some(value), body_after(end)));
+        }
+    } else if first == "+++" {
+        if let Some(end) = find_closing(&lines, &["+++"]) {
+            let front = lines[1..end].concat();
+            let value = toml::from_str::<toml::Value>(&front).map_err(|e| e.to_string())?;
+            return Ok((Some(serde_json::to_value(value).map_err(|e| e.to_string())?), body_after(end)));
+        }
+    } else if first.contains(':') {
+        if let Some(end) = lines.iter().position(|l| l.trim_end() == "---") {
+            let front = lines[..end].concat();
+            if let Ok(value) = serde_yaml::from_str::<Value>(&front) {
+                if value.get("title").is_some() || value.get("date").is_some() {
+                    return Ok((Some(value), body_after(end)));
+                }
+            }
+        }
+    }
+    Ok((None, String::from(text)))
+}
+
+// TOML 的日期时间转成 JSON 后是只有一个字段的对象
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Object(m) if m.len() == 1 => m.values().next().and_then(as_text),
+        _ => None,
+    }
+}
+
+fn front_matter_date(front: &Value) -> Option<i64> {
+    for key in ["date", "publishDate", "published", "created"] {
+        if let Some(v) = front.get(key) {
+            if let Some(n) = v.as_i64() {
+                return Some(n);
+            }
+            if let Some(d) = as_text(v).and_then(|s| date::parse_datetime(&s)) {
+                return Some(d);
+            }
+        }
+    }
+    None
+}
+
+fn front_matter_tags(front: &Value) -> Vec<String> {
+    let mut tags = Vec::new();
+    match front.get("tags") {
+        Some(Value::Array(list)) => {
+            for t in list.iter().filter_map(as_text) {
+                tags.push(t);
+            }
+        },
+        Some(Value::String(s)) => {
+            if s.contains(',') {
+                tags.extend(s.split(',').map(String::from));
+            } else {
+                tags.extend(s.split_whitespace().map(String::from));
+            }
+        },
+        _ => {},
+    }
+    let mut seen = HashSet::new();
+    tags.into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty() && seen.insert(t.clone()))
+        .collect()
+}
+
+fn front_matter_draft(front: &Value) -> bool {
+    front.get("draft").and_then(|v| v.as_bool()).unwrap_or(false)
+        || front.get("published").and_then(|v| v.as_bool()) == Some(false)
+}
+
+// 没有标题时用第一个一级标题，并把它从正文里去掉
+fn take_heading(body: &str) -> Option<(String, String)> {
+    let mut lines = body.lines();
+    let first = lines.by_ref().find(|l| !l.trim().is_empty())?;
+    let title = first.strip_prefix("# ")?.trim().to_string();
+    let rest = lines.collect::<Vec<&str>>().join("\n");
+    Some((title, rest))
+}
+
+fn parse_post(source: &str, data: &[u8], files: &HashMap<String, Vec<u8>>) -> core::result::Result<Candidate, String> {
+    let text = String::from_utf8(data.to_vec()).map_err(|_| String::from("不是 UTF-8 编码/Not UTF-8 encoded."))?;
+    let (front, body) = split_front_matter(&text).map_err(|e| format!("Front matter: {}", e))?;
+    let front = front.unwrap_or(Value::Null);
+
+    // Hugo 的 page bundle 用目录名，Jekyll 的文件名带日期
+    let mut stem = file_stem(source);
+    if stem == "index" {
+        stem = file_stem(parent_dir(source));
+    }
+    let (filename_date, slug) = match JEKYLL_FILENAME_REGEX.captures(stem) {
+        Some(caps) => (date::parse_datetime(&caps[1]), caps[2].to_string()),
+        None => (None, stem.to_string()),
+    };
+
+    let mut content = body;
+    let title = match front.get("title").and_then(as_text).filter(|t| !t.trim().is_empty()) {
+        Some(t) => t.trim().to_string(),
+        None => match take_heading(&content) {
+            Some((t, rest)) => {
+                content = rest;
+                t
+            },
+            None => slug.replace(|c| c == '-' || c == '_', " ").trim().to_string(),
+        },
+    };
+    if title.is_empty() && content.trim().is_empty() {
+        return Err(String::from("空文件/Empty file."));
+    }
+
+    let images = image_references(&content)
+        .into_iter()
+        .map(|url| {
+            let found = resolve_image(files, source, &url);
+            (url, found)
+        })
+        .collect();
+    Ok(Candidate {
+        source: source.to_string(),
+        title,
+        created_at: front_matter_date(&front).or(filename_date),
+        tags: front_matter_tags(&front),
+        draft: front_matter_draft(&front),
+        content: content.trim_start_matches('\n').to_string(),
+        images,
+        title_image: None,
+        old_url: None,
+    })
+}
+
+pub(crate) fn parse_posts(files: &HashMap<String, Vec<u8>>) -> (Vec<Candidate>, Vec<ImportSkipped>) {
+    let mut sources = files
+        .keys()
+        .filter(|k| is_markdown(k))
+        .filter(|k| {
+            let name = k.rsplit('/').next().unwrap_or("").to_lowercase();
+            !IGNORED_POSTS.contains(&name.as_str())
+        })
+        .collect::<Vec<_>>();
+    sources.sort();
+    let mut candidates = Vec::with_capacity(sources.len());
+    let mut skipped = Vec::new();
+    for source in sources {
+        match parse_post(source, &files[source], files) {
+            Ok(c) => candidates.push(c),
+            Err(reason) => skipped.push(ImportSkipped {
+                source: source.clone(),
+                reason,
+            }),
+        }
+    }
+    (candidates, skipped)
+}
+
+// 返回图片的访问地址
+async fn copy_image(id: i64, key: &str, files: &HashMap<String, Vec<u8>>) -> Result<String> {
+    // 用完整路径计算文件名，避免不同目录下的同名图片冲突
+    let (mut file, _, relative_path) = io::get_save_file(id as u64, key, &extension(key), true).await?;
+    file.write_all(&files[key]).await?;
+    file.shutdown().await?;
+    Ok(format!("/{}", relative_path))
+}
+
+async fn copy_images(
+    id: i64,
+    images: &Vec<(String, Option<String>)>,
+    files: &HashMap<String, Vec<u8>>,
+) -> Result<HashMap<String, String>> {
+    let mut mapping = HashMap::new();
+    let mut copied: HashMap<&str, String> = HashMap::new();
+    for (url, found) in images.iter() {
+        let key = match found {
+            Some(k) => k.as_str(),
+            None => continue,
+        };
+        // 同一张图片的不同写法只复制一次
+        let path = match copied.get(key) {
+            Some(p) => p.clone(),
+            None => {
+                let p = copy_image(id, key, files).await?;
+                copied.insert(key, p.clone());
+                p
+            },
+        };
+        mapping.insert(url.clone(), path);
+    }
+    Ok(mapping)
+}
+
+async fn commit(candidate: &Candidate, files: &HashMap<String, Vec<u8>>) -> Result<i64> {
+    let id = post::new_post().await?;
+    let result: Result<i64> = async {
+        let mapping = copy_images(id, &candidate.images, files).await?;
+        let title_image = match candidate.title_image.as_ref() {
+            Some(key) => copy_image(id, key, files).await?,
+            None => String::new(),
+        };
+        let post_data = PostData {
+            id,
+            title: candidate.title.clone(),
+            title_image,
+            content: rewrite_images(&candidate.content, &mapping),
+            tags: if candidate.tags.is_empty() {
+                None
+            } else {
+                Some(candidate.tags.clone())
+            },
+            category_id: None,
+            language: String::new(),
+            translation_of: None,
+            visibility: if candidate.draft {
+                Visibility::Private
+            } else {
+                Visibility::Public
+            },
+            password: None,
+        };
+        post::save(post_data).await?;
+        if let Some(created_at) = candidate.created_at {
+            post::update_created_at(id, created_at).await?;
+        }
+        Ok(id)
+    }
+    .await;
+    if result.is_err() {
+        post::delete(id as u64).await?;
+    }
+    result
+}
+
+// dry_run 时只生成报告；已经存在的同名博客不会重复导入
+pub(crate) async fn import_candidates(
+    candidates: Vec<Candidate>,
+    mut skipped: Vec<ImportSkipped>,
+    files: &HashMap<String, Vec<u8>>,
+    dry_run: bool,
+) -> Result<ImportReport> {
+    let mut titles = HashSet::new();
+    let mut posts = Vec::with_capacity(candidates.len());
+    for candidate in candidates.iter() {
+        let duplicate = !titles.insert(candidate.title.clone()) || post::exists_by_title(&candidate.title).await?;
+        let mut imported = ImportedPost {
+            source: candidate.source.clone(),
+            title: candidate.title.clone(),
+            created_at: candidate.created_at.unwrap_or(time::unix_epoch_sec() as i64).max(0) as u64,
+            tags: candidate.tags.clone(),
+            draft: candidate.draft,
+            images: candidate.images.iter().filter(|(_, f)| f.is_some()).count(),
+            missing_images: candidate
+                .images
+                .iter()
+                .filter(|(_, f)| f.is_none())
+                .map(|(url, _)| url.clone())
+                .collect(),
+            duplicate,
+            old_url: candidate.old_url.clone(),
+            id: None,
+        };
+        if !dry_run && !duplicate {
+            match commit(candidate, files).await {
+                Ok(id) => imported.id = Some(id),
+                Err(e) => {
+                    skipped.push(ImportSkipped {
+                        source: candidate.source.clone(),
+                        reason: e.0.to_string(),
+                    });
+                    continue;
+                },
+            }
+        }
+        posts.push(imported);
+    }
+    Ok(ImportReport {
+        dry_run,
+        posts,
+        skipped,
+        mapping_file: None,
+    })
+}
+
+pub async fn import_markdown(files: HashMap<String, Vec<u8>>, dry_run: bool) -> Result<ImportReport> {
+    let (candidates, skipped) = parse_posts(&files);
+    import_candidates(candidates, skipped, &files, dry_run).await
+}
+
+pub async fn import_zip(data: &[u8], dry_run: bool) -> Result<ImportReport> {
+    import_markdown(read_zip(data)?, dry_run).await
+}
+
+pub async fn import_dir(path: &str, dry_run: bool) -> Result<ImportReport> {
+    import_markdown(read_dir(Path::new(path))?, dry_run).await
+}
+
+// 命令行导入，可以是 zip 文件或者目录
+pub async fn import_path(path: &str, dry_run: bool) -> Result<ImportReport> {
+    if path.to_lowercase().ends_with(".zip") {
+        import_zip(&std::fs::read(path)?, dry_run).await
+    } else {
+        import_dir(path, dry_run).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_and_toml_front_matter() {
+        let (front, body) = split_front_matter("\u{feff}---\ntitle: Hello\ntags: [a, b]\n---\nBody\n").unwrap();
+        let front = front.unwrap();
+        assert_eq!(Some("Hello"), front["title"].as_str());
+        assert_eq!(vec![String::from("a"), String::from("b")], front_matter_tags(&front));
+        assert_eq!("Body\n", body);
+
+        let (front, body) = split_front_matter("+++\ntitle = \"Hi\"\ndate = 2021-03-04T12:00:00Z\n+++\n\nText").unwrap();
+        let front = front.unwrap();
+        assert_eq!(Some("Hi"), front["title"].as_str());
+        assert_eq!(Some(1614859200), front_matter_date(&front));
+        assert_eq!("\nText", body);
+    }
+
+    #[test]
+    fn hexo_and_missing_front_matter() {
+        let (front, body) = split_front_matter("title: Hexo\ndate: 2021-03-04\n---\nBody").unwrap();
+        assert_eq!(Some("Hexo"), front.unwrap()["title"].as_str());
+        assert_eq!("Body", body);
+
+        for text in ["# Title\n\nBody", "Note: not front matter\n---\nBody", "---\nno closing\n"] {
+            let (front, body) = split_front_matter(text).unwrap();
+            assert!(front.is_none());
+            assert_eq!(text, body);
+        }
+        assert!(split_front_matter("---\n: [\n---\n").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use blog_common::{dto::export::ExportChanges, util::time};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::util::result::Result;
+
+// 保存在导出目录里，和生成的文件放在一起
+const MANIFEST_NAME: &str = ".blog-export.json";
+
+#[derive(Default, Deserialize, Serialize)]
+struct Manifest {
+    generated_at: u64,
+    // 相对导出目录的路径和 sha256
+    files: BTreeMap<String, String>,
+}
+
+// 记录每个生成文件的 sha256：内容没有变化的文件不再写入，上次生成、这次没有生成的文件会被删除
+pub(crate) struct BuildOutput {
+    root: PathBuf,
+    previous: BTreeMap<String, String>,
+    current: BTreeMap<String, String>,
+}
+
+impl BuildOutput {
+    pub(crate) fn open(root: &Path) -> Result<Self> {
+        let path = root.join(MANIFEST_NAME);
+        let previous = if path.is_file() {
+            match serde_json::from_slice::<Manifest>(&std::fs::read(path)?) {
+                Ok(m) => m.files,
+                Err(e) => {
+                    eprintln!("Ignore broken export manifest: {}", e);
+                    BTreeMap::new()
+                },
+            }
+        } else {
+            BTreeMap::new()
+        };
+        Ok(BuildOutput {
+            root: root.to_path_buf(),
+            previous,
+            current: BTreeMap::new(),
+        })
+    }
+
+    pub(crate) fn write(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        let hash = format!("{:x}", Sha256::digest(data));
+        // 同一个文件可能写两次，例如主题里的样式覆盖内置样式
+        if self.current.get(path) == Some(&hash) {
+            return Ok(());
+        }
+        let target = self.root.join(path);
+        if self.previous.get(path) != Some(&hash) || self.current.contains_key(path) || !target.is_file() {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            println!("export to file {}", target.display());
+            std::fs::write(target.as_path(), data)?;
+        }
+        self.current.insert(path.to_string(), hash);
+        Ok(())
+    }
+
+    pub(crate) fn copy(&mut self, path: &str, source: &Path) -> Result<()> {
+        let data = std::fs::read(source)?;
+        self.write(path, &data)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.current.len()
+    }
+
+    // 删除不再生成的文件，保存 manifest，返回和上次相比的变化
+    pub(crate) fn finish(self) -> Result<ExportChanges> {
+        let mut changes = ExportChanges::default();
+        for (path, hash) in self.current.iter() {
+            match self.previous.get(path) {
+                None => changes.added.push(path.clone()),
+                Some(h) if h != hash => changes.updated.push(path.clone()),
+                _ => changes.unchanged += 1,
+            }
+        }
+        for path in self.previous.keys().filter(|p| !self.current.contains_key(*p)) {
+            // manifest 可能被改过，不删除导出目录以外的文件
+            if path.starts_with('/') || path.split('/').any(|p| p == ".." || p == ".git") {
+                continue;
+            }
+            let target = self.root.join(path);
+            if target.is_file() {
+                println!("remove exported file {}", target.display());
+                std::fs::remove_file(target.as_path())?;
+                self.remove_empty_parents(target.as_path());
+            }
+            changes.removed.push(path.clone());
+        }
+
+        let manifest = Manifest {
+            generated_at: time::unix_epoch_sec(),
+            files: self.current,
+        };
+        std::fs::create_dir_all(self.root.as_path())?;
+        std::fs::write(self.root.join(MANIFEST_NAME), serde_json::to_string_pretty(&manifest)?)?;
+        Ok(changes)
+    }
+
+    fn remove_empty_parents(&self, file: &Path) {
+        let mut dir = file.parent();
+        while let Some(d) = dir {
+            if d == self.root.as_path() || !d.starts_with(self.root.as_path()) {
+                break;
+            }
+            // 目录不为空时删除失败，停止向上
+            if std::fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn track_changes_between_builds() -> Result<()> {
+        let root = testing::temp_dir("manifest");
+        let mut output = BuildOutput::open(&root)?;
+        output.write("index.html", b"home")?;
+        output.write("posts/a/index.html", b"a")?;
+        output.write("posts/b/index.html", b"b")?;
+        assert_eq!(3, output.len());
+        let changes = output.finish()?;
+        assert_eq!(3, changes.added.len());
+        assert!(root.join(MANIFEST_NAME).is_file());
+
+        let mut output = BuildOutput::open(&root)?;
+        output.write("index.html", b"home")?;
+        output.write("posts/a/index.html", b"A")?;
+        let changes = output.finish()?;
+        assert!(changes.added.is_empty());
+        assert_eq!(vec![String::from("posts/a/index.html")], changes.updated);
+        assert_eq!(vec![String::from("posts/b/index.html")], changes.removed);
+        assert_eq!(1, changes.unchanged);
+        assert_eq!("A", std::fs::read_to_string(root.join("posts/a/index.html"))?);
+        // 空目录也一起删除
+        assert!(!root.join("posts/b").exists());
+
+        // 被手动删除的文件会重新写入
+        std::fs::remove_file(root.join("index.html"))?;
+        let mut output = BuildOutput::open(&root)?;
+        output.write("index.html", b"home")?;
+        output.write("posts/a/index.html", b"A")?;
+        output.finish()?;
+        assert!(root.join("index.html").is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_files_outside_output() -> Result<()> {
+        let root = testing::temp_dir("manifest-outside");
+        let outside = root.with_file_name(format!("{}.keep", root.file_name().unwrap().to_string_lossy()));
+        std::fs::write(outside.as_path(), b"keep")?;
+        let path = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+        let manifest = Manifest {
+            generated_at: 0,
+            files: BTreeMap::from([(path, String::new())]),
+        };
+        std::fs::write(root.join(MANIFEST_NAME), serde_json::to_string(&manifest)?)?;
+        BuildOutput::open(&root)?.finish()?;
+        assert!(outside.is_file());
+        std::fs::remove_file(outside)?;
+        Ok(())
+    }
+}
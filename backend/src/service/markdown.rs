@@ -0,0 +1,364 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use blog_common::{
+    dto::{markdown::MarkdownOptions, post::TocItem},
+    result::Error,
+};
+use comrak::{
+    arena_tree::Node,
+    format_html,
+    nodes::{Ast, AstNode, NodeCode, NodeHeading, NodeValue},
+    parse_document, Arena, ComrakOptions,
+};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::{
+    db::{management, model::Setting, post},
+    service::{diagram, highlight, math, shortcode},
+    util::{common, result::Result, val},
+};
+
+lazy_static! {
+    // 缓存 settings 表里的渲染选项，避免每次保存博客都查询一次数据库
+    static ref OPTIONS: RwLock<Option<MarkdownOptions>> = RwLock::new(None);
+}
+
+fn to_comrak_options(o: &MarkdownOptions) -> ComrakOptions {
+    let mut options = ComrakOptions::default();
+    options.extension.table = o.table;
+    options.extension.strikethrough = o.strikethrough;
+    options.extension.tasklist = o.tasklist;
+    options.extension.autolink = o.autolink;
+    options.extension.footnotes = o.footnotes;
+    options.extension.superscript = o.superscript;
+    options.extension.description_lists = o.description_lists;
+    options.parse.smart = o.smart_punctuation;
+    options.render.hardbreaks = o.hard_breaks;
+    options.render.github_pre_lang = o.github_pre_lang;
+    options
+}
+
+pub async fn get_options() -> Result<MarkdownOptions> {
+    let cached = OPTIONS.read().clone();
+    if let Some(o) = cached {
+        return Ok(o);
+    }
+    let options = match management::get_setting(val::MARKDOWN_OPTIONS_SETTING).await? {
+        Some(setting) if !setting.content.is_empty() => serde_json::from_str::<MarkdownOptions>(&setting.content)?,
+        _ => MarkdownOptions::default(),
+    };
+    *OPTIONS.write() = Some(options.clone());
+    Ok(options)
+}
+
+pub async fn update_options(options: MarkdownOptions) -> Result<()> {
+    if !highlight::themes().contains(&options.highlight_theme) {
+        return Err(Error::BusinessException(String::from("代码高亮主题不存在/Unknown highlight theme.")).into());
+    }
+    let setting = Setting {
+        item: String::from(val::MARKDOWN_OPTIONS_SETTING),
+        content: serde_json::to_string(&options)?,
+    };
+    management::update_setting(setting).await?;
+    *OPTIONS.write() = Some(options);
+    Ok(())
+}
+
+// 先把需要特殊处理的节点替换成占位符，comrak 输出 HTML 之后再换回来
+struct Placeholders {
+    nonce: String,
+    contents: Vec<String>,
+}
+
+impl Placeholders {
+    fn new() -> Self {
+        Placeholders {
+            nonce: common::simple_uuid(),
+            contents: Vec::new(),
+        }
+    }
+
+    // 结尾的 z 避免 x1 和 x10 这样的前缀冲突
+    fn token(&self, idx: usize) -> String {
+        format!("placeholder{}x{}z", self.nonce, idx)
+    }
+
+    fn push(&mut self, html: String) -> String {
+        let token = self.token(self.contents.len());
+        self.contents.push(html);
+        token
+    }
+
+    // 倒序还原，短代码里的公式等占位符在短代码还原之后才会出现
+    fn restore(&self, mut html: String) -> String {
+        for (idx, content) in self.contents.iter().enumerate().rev() {
+            let token = self.token(idx);
+            for wrapped in [
+                format!("<pre><code>{}</code></pre>\n", token),
+                format!("<p>{}</p>\n", token),
+                token.clone(),
+            ] {
+                if html.contains(&wrapped) {
+                    html = html.replacen(&wrapped, content, 1);
+                    break;
+                }
+            }
+        }
+        html
+    }
+
+    // 标题里的公式等占位符换成去掉标签的文字，用于目录和锚点
+    fn plain_text(&self, mut text: String) -> String {
+        for (idx, content) in self.contents.iter().enumerate().rev() {
+            let token = self.token(idx);
+            if text.contains(&token) {
+                text = text.replace(&token, &strip_tags(content));
+            }
+        }
+        text
+    }
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {},
+        }
+    }
+    text
+}
+
+fn transform_code_blocks<'a>(root: &'a AstNode<'a>, options: &MarkdownOptions, placeholders: &mut Placeholders) {
+    for node in root.descendants() {
+        if let NodeValue::CodeBlock(ref mut code_block) = node.data.borrow_mut().value {
+            let info = String::from_utf8_lossy(&code_block.info).to_string();
+            let code = String::from_utf8_lossy(&code_block.literal).to_string();
+            let lang = info.split_whitespace().next().unwrap_or("");
+            let html = if options.diagram && diagram::is_diagram(lang) {
+                diagram::render_svg(&code)
+            } else {
+                None
+            };
+            // 图表解析失败时按普通代码块处理，高亮失败时保留 comrak 默认的输出
+            let html = match html {
+                Some(h) => Some(h),
+                None if options.syntax_highlight => highlight::highlight(&info, &code).ok(),
+                None => None,
+            };
+            if let Some(html) = html {
+                code_block.info.clear();
+                code_block.literal = placeholders.push(html).into_bytes();
+            }
+        }
+    }
+}
+
+fn collect_text<'a>(node: &'a AstNode<'a>, placeholders: &Placeholders) -> String {
+    let mut text = String::new();
+    for n in node.descendants() {
+        match &n.data.borrow().value {
+            NodeValue::Text(t) | NodeValue::Code(NodeCode { literal: t, .. }) => {
+                text.push_str(&String::from_utf8_lossy(t))
+            },
+            _ => {},
+        }
+    }
+    placeholders.plain_text(text).trim().to_string()
+}
+
+// 和 GitHub 类似：转小写，空白换成 -，去掉标点，重复的标题加上序号
+fn anchor_slug(text: &str, used: &mut HashMap<String, usize>) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            slug.push(c);
+        } else if c.is_whitespace() {
+            slug.push('-');
+        }
+    }
+    if slug.is_empty() {
+        slug.push_str("section");
+    }
+    let count = used.entry(slug.clone()).or_insert(0);
+    let anchor = if *count == 0 {
+        slug
+    } else {
+        format!("{}-{}", slug, count)
+    };
+    *count += 1;
+    anchor
+}
+
+fn new_text_node<'a>(arena: &'a Arena<AstNode<'a>>, text: String) -> &'a AstNode<'a> {
+    arena.alloc(Node::new(RefCell::new(Ast::new(NodeValue::Text(text.into_bytes())))))
+}
+
+fn anchor_headings<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    placeholders: &mut Placeholders,
+) -> Vec<TocItem> {
+    let mut used = HashMap::new();
+    let mut toc = Vec::new();
+    for node in root.descendants() {
+        let level = match node.data.borrow().value {
+            NodeValue::Heading(NodeHeading { level, .. }) => level as u8,
+            _ => continue,
+        };
+        let text = collect_text(node, placeholders);
+        let anchor = anchor_slug(&text, &mut used);
+        let escaped = v_htmlescape::escape(&anchor).to_string();
+        let html = format!(
+            "<a id=\"{}\" class=\"anchor\" href=\"#{}\" aria-hidden=\"true\"></a>",
+            escaped, escaped
+        );
+        node.prepend(new_text_node(arena, placeholders.push(html)));
+        toc.push(TocItem { level, text, anchor });
+    }
+    toc
+}
+
+fn toc_html(toc: &[TocItem]) -> String {
+    let min_level = toc.iter().map(|t| t.level).min().unwrap_or(1);
+    let mut html = String::from("<nav class=\"toc\">\n<ul>\n");
+    for item in toc.iter() {
+        html.push_str(&format!(
+            "<li style=\"margin-left:{}em\"><a href=\"#{}\">{}</a></li>\n",
+            item.level - min_level,
+            v_htmlescape::escape(&item.anchor),
+            v_htmlescape::escape(&item.text)
+        ));
+    }
+    html.push_str("</ul>\n</nav>\n");
+    html
+}
+
+// 单独一段的 [TOC] 替换成文章目录
+fn replace_toc_markers<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    toc: &[TocItem],
+    placeholders: &mut Placeholders,
+) {
+    let paragraphs = root
+        .descendants()
+        .filter(|n| matches!(n.data.borrow().value, NodeValue::Paragraph))
+        .filter(|n| n.children().all(|c| matches!(c.data.borrow().value, NodeValue::Text(_))))
+        .filter(|n| collect_text(n, placeholders) == "[TOC]")
+        .collect::<Vec<_>>();
+    for paragraph in paragraphs {
+        while let Some(child) = paragraph.first_child() {
+            child.detach();
+        }
+        let html = if toc.is_empty() { String::new() } else { toc_html(toc) };
+        paragraph.append(new_text_node(arena, placeholders.push(html)));
+    }
+}
+
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocItem>,
+}
+
+pub async fn render(markdown: &str) -> Result<Rendered> {
+    render_with_options(markdown, &get_options().await?).await
+}
+
+async fn render_with_options(markdown: &str, options: &MarkdownOptions) -> Result<Rendered> {
+    let comrak_options = to_comrak_options(options);
+    let mut placeholders = Placeholders::new();
+    let markdown = if options.math {
+        math::replace_math(markdown, |mathml| placeholders.push(mathml))
+    } else {
+        String::from(markdown)
+    };
+    let markdown = if options.shortcodes {
+        shortcode::expand(&markdown, &comrak_options, |html| placeholders.push(html)).await?
+    } else {
+        markdown
+    };
+    let arena = Arena::new();
+    let root = parse_document(&arena, &markdown, &comrak_options);
+    transform_code_blocks(root, options, &mut placeholders);
+    // 关闭时没有锚点，也就没有文章目录
+    let toc = if options.header_ids {
+        anchor_headings(&arena, root, &mut placeholders)
+    } else {
+        Vec::new()
+    };
+    replace_toc_markers(&arena, root, &toc, &mut placeholders);
+    let mut html = Vec::with_capacity(markdown.len() * 2);
+    format_html(root, &comrak_options, &mut html)?;
+    let html = String::from_utf8(html)?;
+    Ok(Rendered {
+        html: placeholders.restore(html),
+        toc,
+    })
+}
+
+// 渲染选项变动后，重新生成所有博客的 rendered_content
+pub async fn rerender_all() -> Result<usize> {
+    let posts = post::all().await?;
+    for p in posts.iter() {
+        let rendered = render(&p.markdown_content).await?;
+        post::update_rendered_content(p.id, &rendered.html, &rendered.toc).await?;
+    }
+    Ok(posts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn restore_placeholders() {
+        let mut placeholders = Placeholders::new();
+        let code = placeholders.push(String::from("<pre>code</pre>"));
+        let math = placeholders.push(String::from("<math><mi>x</mi></math>"));
+        let html = format!("<pre><code>{}</code></pre>\n<p>a {} b</p>\n", code, math);
+
+        assert_eq!(
+            "<pre>code</pre><p>a <math><mi>x</mi></math> b</p>\n",
+            placeholders.restore(html)
+        );
+    }
+
+    #[test]
+    fn similar_tokens() {
+        let mut placeholders = Placeholders::new();
+        let tokens = (0..11).map(|i| placeholders.push(i.to_string())).collect::<Vec<String>>();
+        let html = format!("{} {}", tokens[1], tokens[10]);
+
+        assert_eq!("1 10", placeholders.restore(html));
+    }
+
+    #[test]
+    fn heading_text_without_placeholders() {
+        let mut placeholders = Placeholders::new();
+        let math = placeholders.push(String::from("<math><msup><mi>e</mi><mi>x</mi></msup></math>"));
+        let text = format!("Derivative of {}", math);
+
+        assert_eq!("Derivative of ex", placeholders.plain_text(text));
+    }
+
+    #[test]
+    fn toggle_header_ids() {
+        let markdown = "[TOC]\n\n# Title\n\n## Section\n";
+        let mut options = MarkdownOptions::default();
+        let rendered = testing::block_on(render_with_options(markdown, &options)).unwrap();
+        assert_eq!(2, rendered.toc.len());
+        assert!(rendered.html.contains("id=\"title\""));
+
+        options.header_ids = false;
+        let rendered = testing::block_on(render_with_options(markdown, &options)).unwrap();
+        assert!(rendered.toc.is_empty());
+        assert!(!rendered.html.contains("id=\"title\""));
+    }
+}
@@ -0,0 +1,239 @@
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+
+// 把 Markdown 里的 $inline$ 和 $$display$$ 公式转换成 MathML，代码块和行内代码里的内容保持不变
+// convert 用来生成占位符，保证公式里的 `_`、`*` 之类不会被 comrak 当成 Markdown 语法
+pub(crate) fn replace_math<F>(markdown: &str, mut convert: F) -> String
+where
+    F: FnMut(String) -> String,
+{
+    let mut output = String::with_capacity(markdown.len());
+    let mut fence: Option<String> = None;
+    let mut paragraph = String::new();
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(f) = &fence {
+            if trimmed.starts_with(f.as_str()) {
+                fence = None;
+            }
+            output.push_str(line);
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let c = trimmed.chars().next().unwrap();
+            fence = Some(trimmed.chars().take_while(|ch| *ch == c).collect());
+            output.push_str(&replace_in_text(&paragraph, &mut convert));
+            paragraph.clear();
+            output.push_str(line);
+            continue;
+        }
+        paragraph.push_str(line);
+    }
+    output.push_str(&replace_in_text(&paragraph, &mut convert));
+    output
+}
+
+// latex2mathml 只在少数情况下返回 Err，不认识的命令会输出 [PARSE ERROR: ...]，这时保留原来的公式
+fn to_mathml(latex: &str, display: bool) -> Option<String> {
+    let style = if display { DisplayStyle::Block } else { DisplayStyle::Inline };
+    let mathml = latex_to_mathml(latex.trim(), style).ok()?;
+    if mathml.contains("[PARSE ERROR") {
+        return None;
+    }
+    Some(escape_tokens(&mathml))
+}
+
+// latex2mathml 不会转义 mi、mo、mtext 等元素里的文字，例如 a<b 会输出 <mo><</mo>
+fn escape_tokens(mathml: &str) -> String {
+    const TOKEN_ELEMENTS: [&str; 5] = ["mi", "mn", "mo", "ms", "mtext"];
+    let mut output = String::with_capacity(mathml.len());
+    let mut rest = mathml;
+    while let Some(start) = rest.find('<') {
+        let tag_end = match rest[start..].find('>') {
+            Some(pos) => start + pos + 1,
+            None => break,
+        };
+        let tag = &rest[start..tag_end];
+        output.push_str(&rest[..tag_end]);
+        rest = &rest[tag_end..];
+        let name = tag[1..].split(|c: char| c == ' ' || c == '>' || c == '/').next().unwrap_or("");
+        if TOKEN_ELEMENTS.contains(&name) && !tag.ends_with("/>") {
+            // 元素里没有子元素，第一个 </ 就是结束标签
+            let end = rest.find("</").unwrap_or(rest.len());
+            output.push_str(&escape_text(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            // latex2mathml 自己输出的 &#x2061; 之类保持不变
+            '&' if !is_char_ref(&text[i..]) => escaped.push_str("&amp;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_char_ref(s: &str) -> bool {
+    match (s.strip_prefix("&#"), s.find(';')) {
+        (Some(code), Some(end)) => end > 2 && code[..end - 2].chars().all(|c| c == 'x' || c.is_ascii_hexdigit()),
+        _ => false,
+    }
+}
+
+fn replace_in_text<F>(text: &str, convert: &mut F) -> String
+where
+    F: FnMut(String) -> String,
+{
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() {
+            output.push(c);
+            output.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        // 行内代码
+        if c == '`' {
+            let run = chars[i..].iter().take_while(|ch| **ch == '`').count();
+            let close = find_backticks(&chars, i + run, run);
+            let end = close.map_or(i + run, |pos| pos + run);
+            output.extend(chars[i..end].iter());
+            i = end;
+            continue;
+        }
+        if c == '$' {
+            if let Some((end, latex, display)) = find_math(&chars, i) {
+                if let Some(mathml) = to_mathml(&latex, display) {
+                    output.push_str(&convert(mathml));
+                    i = end;
+                    continue;
+                }
+            }
+        }
+        output.push(c);
+        i += 1;
+    }
+    output
+}
+
+fn find_backticks(chars: &[char], from: usize, run: usize) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() {
+        if chars[i] == '`' {
+            let n = chars[i..].iter().take_while(|ch| **ch == '`').count();
+            if n == run {
+                return Some(i);
+            }
+            i += n;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+// 返回公式结束的位置、公式内容以及是否为块级公式
+fn find_math(chars: &[char], start: usize) -> Option<(usize, String, bool)> {
+    if chars.get(start + 1) == Some(&'$') {
+        let mut i = start + 2;
+        while i + 1 < chars.len() {
+            if chars[i] == '$' && chars[i + 1] == '$' {
+                let latex = chars[start + 2..i].iter().collect::<String>();
+                if latex.trim().is_empty() {
+                    return None;
+                }
+                return Some((i + 2, latex, true));
+            }
+            i += 1;
+        }
+        return None;
+    }
+    // 和 Pandoc 一样：开头的 $ 后面不能是空白，结尾的 $ 前面不能是空白、后面不能是数字，避免把金额当成公式
+    match chars.get(start + 1) {
+        Some(c) if !c.is_whitespace() => {},
+        _ => return None,
+    }
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\n' if chars.get(i + 1) == Some(&'\n') => return None,
+            '$' => {
+                if chars[i - 1].is_whitespace() || chars.get(i + 1).map_or(false, |c| c.is_ascii_digit()) {
+                    return None;
+                }
+                return Some((i + 1, chars[start + 1..i].iter().collect(), false));
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(markdown: &str) -> String {
+        replace_math(markdown, |_| String::from("[M]"))
+    }
+
+    #[test]
+    fn inline_and_display_math() {
+        assert_eq!("Euler: [M].\n", replace("Euler: $e^{i\\pi}+1=0$.\n"));
+        assert_eq!("[M]\n", replace("$$\\sum_{i=1}^n i$$\n"));
+        let mut formulas = Vec::new();
+        replace_math("$x$ and $$y$$", |mathml| {
+            formulas.push(mathml);
+            String::new()
+        });
+        assert_eq!(2, formulas.len());
+        assert!(formulas[0].contains("<math"));
+        assert!(formulas[1].contains("display=\"block\""));
+    }
+
+    #[test]
+    fn keep_text_that_is_not_math() {
+        for text in [
+            "It costs $5 and $10.\n",
+            "A $ lonely dollar\n",
+            "Escaped \\$x\\$\n",
+            "Code `$x$` here\n",
+            "```\n$x$\n```\n",
+            "$a\n\nb$\n",
+            "$$ $$\n",
+        ] {
+            assert_eq!(text, replace(text));
+        }
+        assert_eq!("~~~\n$x$\n~~~\n[M]\n", replace("~~~\n$x$\n~~~\n$y$\n"));
+    }
+
+    #[test]
+    fn keep_source_on_parse_error() {
+        assert_eq!(None, to_mathml("\\foo", false));
+        assert_eq!("Unknown $\\foo{x}$ here\n", replace("Unknown $\\foo{x}$ here\n"));
+    }
+
+    #[test]
+    fn escape_token_text() {
+        let mathml = to_mathml("a<b", false).unwrap();
+        assert!(mathml.contains("<mo>&lt;</mo>"), "{}", mathml);
+        assert!(!mathml.contains("<mo><</mo>"));
+        assert_eq!(
+            "<mo>&gt;</mo><mi>x&#x0338;</mi><mtext>a&amp;b</mtext>",
+            escape_tokens("<mo>></mo><mi>x&#x0338;</mi><mtext>a&b</mtext>")
+        );
+    }
+}
@@ -1,6 +1,18 @@
 pub(crate) mod asset;
+pub mod backup;
+pub mod deploy;
+pub(crate) mod diagram;
 pub(crate) mod export;
 pub(crate) mod git;
+pub(crate) mod highlight;
 pub(crate) mod image;
+pub mod import;
+pub(crate) mod manifest;
+pub(crate) mod markdown;
+pub(crate) mod math;
+pub(crate) mod related;
 pub mod server;
+pub(crate) mod shortcode;
+pub(crate) mod site;
 pub mod status;
+pub mod wordpress;
@@ -0,0 +1,194 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use blog_common::dto::post::{RelatedPost, Visibility};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+
+use crate::{
+    db::{post, tag},
+    util::{common, result::Result},
+};
+
+const MAX_RELATED: usize = 5;
+// 正文词语重合度的权重，设为 0 则只按标签计算
+const TERM_OVERLAP_WEIGHT: f64 = 1.0;
+const MAX_TERMS: usize = 2000;
+
+struct Cache {
+    // 每次 invalidate 加一，计算结果只在代数没变时才保存
+    generation: u64,
+    computing: bool,
+    related: Option<Arc<HashMap<i64, Vec<RelatedPost>>>>,
+}
+
+lazy_static! {
+    // 博客或标签有变动时清空，并在后台重新计算所有博客的相关文章
+    static ref RELATED: RwLock<Cache> = RwLock::new(Cache {
+        generation: 0,
+        computing: false,
+        related: None,
+    });
+}
+
+pub fn invalidate() {
+    let mut cache = RELATED.write();
+    cache.generation += 1;
+    cache.related = None;
+    recompute(&mut cache);
+}
+
+fn recompute(cache: &mut Cache) {
+    cache.computing = true;
+    let generation = cache.generation;
+    tokio::spawn(async move {
+        match compute().await {
+            Ok(related) => {
+                store(generation, related);
+            },
+            Err(e) => {
+                eprintln!("{:?}", e);
+                let mut cache = RELATED.write();
+                if cache.generation == generation {
+                    cache.computing = false;
+                }
+            },
+        }
+    });
+}
+
+// 计算期间又有变动的话，这次的结果已经过时，丢弃
+fn store(generation: u64, related: HashMap<i64, Vec<RelatedPost>>) -> bool {
+    let mut cache = RELATED.write();
+    if cache.generation != generation {
+        return false;
+    }
+    cache.computing = false;
+    cache.related = Some(Arc::new(related));
+    true
+}
+
+// 还没算好时先返回空列表，不在请求里等待计算
+pub fn related_posts(post_id: i64) -> Vec<RelatedPost> {
+    if let Some(related) = RELATED.read().related.as_ref() {
+        return related.get(&post_id).cloned().unwrap_or_default();
+    }
+    let mut cache = RELATED.write();
+    if cache.related.is_none() && !cache.computing {
+        recompute(&mut cache);
+    }
+    Vec::new()
+}
+
+// 英文按单词，中日韩等没有空格的文字按相邻两个字切分
+fn terms(rendered_content: &str) -> HashSet<String> {
+    let text = common::HTML_TAG_REGEX.replace_all(rendered_content, " ").to_lowercase();
+    let mut terms = HashSet::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_ascii() {
+            if word.len() > 2 {
+                terms.insert(word.to_string());
+            }
+        } else {
+            let chars = word.chars().collect::<Vec<char>>();
+            for pair in chars.windows(2) {
+                terms.insert(pair.iter().collect());
+            }
+        }
+        if terms.len() >= MAX_TERMS {
+            break;
+        }
+    }
+    terms
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let intersection = a.intersection(b).count();
+    intersection as f64 / (a.len() + b.len() - intersection) as f64
+}
+
+async fn compute() -> Result<HashMap<i64, Vec<RelatedPost>>> {
+    // 只推荐公开的博客
+    let posts = post::all()
+        .await?
+        .into_iter()
+        .filter(|p| p.visibility == Visibility::Public.as_str())
+        .collect::<Vec<_>>();
+    let post_ids = posts.iter().map(|p| p.id).collect::<HashSet<i64>>();
+
+    let mut post_tags: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut tag_posts: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (post_id, tag_id) in tag::all_usages().await? {
+        if !post_ids.contains(&post_id) {
+            continue;
+        }
+        post_tags.entry(post_id).or_default().push(tag_id);
+        tag_posts.entry(tag_id).or_default().push(post_id);
+    }
+    // 越少博客使用的标签权重越高
+    let total = posts.len() as f64;
+    let tag_weights = tag_posts
+        .iter()
+        .map(|(tag_id, ids)| (*tag_id, (1.0 + total / ids.len() as f64).ln()))
+        .collect::<HashMap<i64, f64>>();
+
+    let post_terms = if TERM_OVERLAP_WEIGHT > 0.0 {
+        posts.iter().map(|p| terms(&p.rendered_content)).collect::<Vec<_>>()
+    } else {
+        vec![HashSet::new(); posts.len()]
+    };
+
+    let mut related = HashMap::with_capacity(posts.len());
+    for (i, p) in posts.iter().enumerate() {
+        let mut scores: HashMap<i64, f64> = HashMap::new();
+        for tag_id in post_tags.get(&p.id).map_or(&[][..], |t| &t[..]) {
+            for other in tag_posts[tag_id].iter().filter(|id| **id != p.id) {
+                *scores.entry(*other).or_default() += tag_weights[tag_id];
+            }
+        }
+        let mut candidates = posts
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(j, other)| {
+                let score = scores.get(&other.id).copied().unwrap_or(0.0)
+                    + TERM_OVERLAP_WEIGHT * jaccard(&post_terms[i], &post_terms[j]);
+                (score, other)
+            })
+            .filter(|(score, _)| *score > 0.0)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(b.1.id.cmp(&a.1.id)));
+        let list = candidates
+            .into_iter()
+            .take(MAX_RELATED)
+            .map(|(_, other)| RelatedPost {
+                id: other.id,
+                title: other.title.clone(),
+                title_image: other.title_image.clone(),
+            })
+            .collect::<Vec<_>>();
+        related.insert(p.id, list);
+    }
+    Ok(related)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing;
+
+    #[test]
+    fn discard_outdated_result() {
+        testing::block_on(async {
+            let generation = RELATED.read().generation;
+            invalidate();
+            // invalidate 之前开始的计算，结果不能覆盖缓存
+            assert!(!store(generation, HashMap::new()));
+        });
+    }
+}
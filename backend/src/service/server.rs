@@ -7,16 +7,21 @@ use warp::{self, reject, Filter, Reply};
 
 use blog_common::{
     dto::{
-        git::GitPushInfo,
+        deploy::DeployTarget,
+        git::{DeployKeyUpload, GitCredential, GitPullOptions, GitPushInfo, KnownHosts},
+        import::ImportDirectory,
+        category::CategoryData,
         management::{AdminUser, Setting},
-        post::PostData,
+        markdown::{MarkdownOptions, MarkdownPreview, ShortcodeTemplate},
+        post::{PostAutosave, PostData, PostUnlock},
+        site::SiteOptions,
         user::UserInfo,
     },
     val,
 };
 
 use crate::{
-    facade::{self, asset, export, git, image, management, post, tag, user},
+    facade::{self, asset, category, deploy, export, git, image, management, post, tag, user},
     service::status,
     util::result::Result,
 };
@@ -225,6 +230,100 @@ pub fn blog_filter(
         .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
         .and(warp::body::form::<HashMap<String, String>>())
         .and_then(management::update_render_templates);
+    let management_markdown_options = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("markdown-options"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::markdown_options);
+    let management_update_markdown_options = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("markdown-options"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<MarkdownOptions>())
+        .and_then(management::update_markdown_options);
+    let management_highlight_themes = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("highlight-themes"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::highlight_themes);
+    let management_shortcodes = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("shortcodes"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::shortcode_templates);
+    let management_update_shortcodes = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("shortcodes"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<Vec<ShortcodeTemplate>>())
+        .and_then(management::update_shortcode_templates);
+    let management_rerender_posts = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("rerender-posts"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::rerender_posts);
+    let management_site_options = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("site-options"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::site_options);
+    let management_update_site_options = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("site-options"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<SiteOptions>())
+        .and_then(management::update_site_options);
+    let management_site_themes = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("site-themes"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::site_themes);
+    let management_build_site = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("build-site"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::build_site);
+    let management_import = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::content_length_limit(val::MAX_IMPORT_ARCHIVE_SIZE as u64))
+        .and(warp::body::bytes())
+        .and_then(management::import_archive);
+    let management_import_dir = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("import-dir"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<ImportDirectory>())
+        .and_then(management::import_directory);
+    let management_import_wordpress = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("import-wordpress"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::content_length_limit(val::MAX_IMPORT_ARCHIVE_SIZE as u64))
+        .and(warp::body::bytes())
+        .and_then(management::import_wordpress);
+    let management_backup = warp::post()
+        .and(warp::path("management"))
+        .and(warp::path("backup"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(management::backup);
     let user_logout = warp::get()
         .and(warp::path("user"))
         .and(warp::path("logout"))
@@ -265,6 +364,8 @@ pub fn blog_filter(
         .and(warp::path::param::<String>())
         .and(warp::path::param::<u64>())
         .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth())
         .and_then(post::list);
     let post_seach_by_key = warp::get()
         .and(warp::path("post"))
@@ -273,6 +374,8 @@ pub fn blog_filter(
         .and(warp::path::param::<String>())
         .and(warp::path::param::<u64>())
         .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth())
         .and_then(post::seach_by_key);
     let post_list_by_tag = warp::get()
         .and(warp::path("post"))
@@ -281,7 +384,55 @@ pub fn blog_filter(
         .and(warp::path::param::<String>())
         .and(warp::path::param::<u64>())
         .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth())
         .and_then(post::list_by_tag);
+    let post_list_by_category = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("category"))
+        .and(warp::path::param::<i64>())
+        .and(warp::path::param::<String>())
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth())
+        .and_then(post::list_by_category);
+    let post_archive = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("archive"))
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(post::archive);
+    let post_list_by_month = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("archive"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::param::<u8>())
+        .and(warp::path::param::<String>())
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(auth())
+        .and_then(post::list_by_month);
+    let category_tree = warp::get()
+        .and(warp::path("category"))
+        .and(warp::path("tree"))
+        .and(warp::path::end())
+        .and_then(category::tree);
+    let category_new = warp::post()
+        .and(warp::path("category"))
+        .and(warp::path("new"))
+        .and(warp::path::end())
+        .and(auth())
+        .and(warp::body::json::<CategoryData>())
+        .and_then(category::new);
+    let category_delete = warp::post()
+        .and(warp::path("category"))
+        .and(warp::path("delete"))
+        .and(warp::path::param::<i64>())
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(category::delete);
     let post_new = warp::get()
         .and(warp::path("post"))
         .and(warp::path("new"))
@@ -295,6 +446,35 @@ pub fn blog_filter(
         .and(auth())
         .and(warp::body::json::<PostData>())
         .and_then(post::save);
+    let post_preview = warp::post()
+        .and(warp::path("post"))
+        .and(warp::path("preview"))
+        .and(warp::path::end())
+        .and(auth())
+        .and(warp::body::json::<MarkdownPreview>())
+        .and_then(post::preview);
+    let post_autosave = warp::post()
+        .and(warp::path("post"))
+        .and(warp::path("autosave"))
+        .and(warp::path::end())
+        .and(auth())
+        .and(warp::body::json::<PostAutosave>())
+        .and_then(post::autosave);
+    let post_autosave_detail = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("autosave"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(post::autosave_detail);
+    let post_discard_autosave = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("autosave"))
+        .and(warp::path("discard"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(post::discard_autosave);
     let post_delete = warp::get()
         .and(warp::path("post"))
         .and(warp::path("delete"))
@@ -302,6 +482,27 @@ pub fn blog_filter(
         .and(warp::path::end())
         .and(auth())
         .and_then(post::delete);
+    let post_featured = warp::get()
+        .and(warp::path("post"))
+        .and(warp::path("featured"))
+        .and(warp::path::end())
+        .and_then(post::featured);
+    let post_pin = warp::post()
+        .and(warp::path("post"))
+        .and(warp::path("pin"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::param::<bool>())
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(post::pin);
+    let post_feature = warp::post()
+        .and(warp::path("post"))
+        .and(warp::path("feature"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::param::<bool>())
+        .and(warp::path::end())
+        .and(auth())
+        .and_then(post::feature);
     let post_show = warp::get()
         .and(warp::path("post"))
         .and(warp::path("show"))
@@ -310,6 +511,13 @@ pub fn blog_filter(
         .and(warp::query::<HashMap<String, String>>())
         .and(warp::path::end())
         .and_then(post::show);
+    let post_unlock = warp::post()
+        .and(warp::path("post"))
+        .and(warp::path("unlock"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::body::json::<PostUnlock>())
+        .and_then(post::unlock);
     let upload_image = warp::post()
         .and(warp::path("image"))
         .and(warp::path("upload"))
@@ -364,12 +572,14 @@ pub fn blog_filter(
         .and(warp::path("git"))
         .and(warp::path("new"))
         .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
         .and(warp::body::form::<HashMap<String, String>>())
         .and_then(git::new_repository);
     let git_remove = warp::get()
         .and(warp::path("git"))
         .and(warp::path("remove"))
         .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
         .and_then(git::remove_repository);
     let git_set_branch = warp::get()
         .and(warp::path("git"))
@@ -377,13 +587,164 @@ pub fn blog_filter(
         .and(warp::path("set"))
         .and(warp::path::tail())
         .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
         .and_then(git::set_branch);
     let git_push = warp::post()
         .and(warp::path("git"))
         .and(warp::path("push"))
-        .and(warp::body::json::<GitPushInfo>())
         .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<GitPushInfo>())
         .and_then(git::push);
+    let git_pull_options = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("pull-options"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<GitPullOptions>())
+        .and_then(git::update_pull_options);
+    let git_status = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("status"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::repository_status);
+    let git_preview = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("preview"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<GitPushInfo>())
+        .and_then(git::preview);
+    let git_publish_jobs = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("publish"))
+        .and(warp::path("jobs"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::publish_jobs);
+    let git_publish_job = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("publish"))
+        .and(warp::path("jobs"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::publish_job);
+    let git_publish_events = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("publish"))
+        .and(warp::path("jobs"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("events"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::publish_events);
+    let git_cancel_publish_job = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("publish"))
+        .and(warp::path("jobs"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("cancel"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::cancel_publish_job);
+    let git_credential = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("credential"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::credential_status);
+    let git_save_credential = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("credential"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<GitCredential>())
+        .and_then(git::save_credential);
+    let git_forget_credential = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("credential"))
+        .and(warp::path("forget"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::forget_credential);
+    let git_deploy_key = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("ssh-key"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::deploy_key);
+    let git_generate_deploy_key = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("ssh-key"))
+        .and(warp::path("generate"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::generate_deploy_key);
+    let git_upload_deploy_key = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("ssh-key"))
+        .and(warp::path("upload"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<DeployKeyUpload>())
+        .and_then(git::upload_deploy_key);
+    let git_remove_deploy_key = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("ssh-key"))
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::remove_deploy_key);
+    let git_known_hosts = warp::get()
+        .and(warp::path("git"))
+        .and(warp::path("known-hosts"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(git::known_hosts);
+    let git_update_known_hosts = warp::post()
+        .and(warp::path("git"))
+        .and(warp::path("known-hosts"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<KnownHosts>())
+        .and_then(git::update_known_hosts);
+    let management_deploy_targets = warp::get()
+        .and(warp::path("management"))
+        .and(warp::path("deploy-targets"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(deploy::show);
+    let deploy_targets = warp::get()
+        .and(warp::path("deploy"))
+        .and(warp::path("targets"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(deploy::targets);
+    let deploy_save_target = warp::post()
+        .and(warp::path("deploy"))
+        .and(warp::path("targets"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and(warp::body::json::<DeployTarget>())
+        .and_then(deploy::save_target);
+    let deploy_remove_target = warp::post()
+        .and(warp::path("deploy"))
+        .and(warp::path("targets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(deploy::remove_target);
+    let deploy_now = warp::post()
+        .and(warp::path("deploy"))
+        .and(warp::path("targets"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("deploy"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional(val::SESSION_ID_HEADER_NAME))
+        .and_then(deploy::deploy_now);
 
     // Setting CORS
     let mut host = String::with_capacity(32);
@@ -425,18 +786,47 @@ pub fn blog_filter(
         .or(management_update_settings)
         .or(management_templates)
         .or(management_update_templates)
+        .or(management_markdown_options)
+        .or(management_update_markdown_options)
+        .or(management_highlight_themes)
+        .or(management_shortcodes)
+        .or(management_update_shortcodes)
+        .or(management_rerender_posts)
+        .or(management_site_options)
+        .or(management_update_site_options)
+        .or(management_site_themes)
+        .or(management_build_site)
+        .or(management_import)
+        .or(management_import_dir)
+        .or(management_import_wordpress)
+        .or(management_backup)
         .or(user_logout)
         .or(user_info)
         .or(verify_image)
         .or(random_title_image)
         .or(post_list)
+        .or(post_seach_by_key)
         .or(tags_all)
         .or(top_tags)
         .or(post_list_by_tag)
+        .or(post_list_by_category)
+        .or(post_archive)
+        .or(post_list_by_month)
+        .or(category_tree)
+        .or(category_new)
+        .or(category_delete)
         .or(post_new)
         .or(post_save)
+        .or(post_preview)
+        .or(post_autosave)
+        .or(post_autosave_detail)
+        .or(post_discard_autosave)
         .or(post_delete)
+        .or(post_featured)
+        .or(post_pin)
+        .or(post_feature)
         .or(post_show)
+        .or(post_unlock)
         .or(upload_image)
         .or(upload_title_image)
         .or(save_image)
@@ -448,6 +838,27 @@ pub fn blog_filter(
         .or(git_remove)
         .or(git_set_branch)
         .or(git_push)
+        .or(git_pull_options)
+        .or(git_status)
+        .or(git_preview)
+        .or(git_publish_jobs)
+        .or(git_publish_job)
+        .or(git_publish_events)
+        .or(git_cancel_publish_job)
+        .or(git_credential)
+        .or(git_save_credential)
+        .or(git_forget_credential)
+        .or(git_deploy_key)
+        .or(git_generate_deploy_key)
+        .or(git_upload_deploy_key)
+        .or(git_remove_deploy_key)
+        .or(git_known_hosts)
+        .or(git_update_known_hosts)
+        .or(management_deploy_targets)
+        .or(deploy_targets)
+        .or(deploy_save_target)
+        .or(deploy_remove_target)
+        .or(deploy_now)
         .with(logger)
         .with(cors);
     // End
@@ -0,0 +1,571 @@
+use std::{collections::HashMap, iter::Peekable, str::Chars, sync::Arc};
+
+use blog_common::{dto::markdown::ShortcodeTemplate, result::Error};
+use comrak::{markdown_to_html, ComrakOptions};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use regex::Regex;
+use tera::Tera;
+
+use crate::{
+    db::{management, model::Setting, post},
+    util::{result::Result, val},
+};
+
+type Handler = fn(&Shortcode, &Context) -> Option<String>;
+
+lazy_static! {
+    static ref NAME_REGEX: Regex = Regex::new(r"^[a-z][a-z0-9_-]*$").unwrap();
+    // 视频、gist 等的 id 会拼到链接里，只允许这些字符
+    static ref ID_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_.-]+$").unwrap();
+    static ref BUILTINS: HashMap<&'static str, Handler> = {
+        let mut m: HashMap<&'static str, Handler> = HashMap::new();
+        m.insert("video", video);
+        m.insert("youtube", youtube);
+        m.insert("vimeo", vimeo);
+        m.insert("bilibili", bilibili);
+        m.insert("gist", gist);
+        m.insert("tweet", tweet);
+        m.insert("figure", figure);
+        m.insert("callout", callout);
+        m.insert("admonition", callout);
+        m.insert("post-link", post_link);
+        m
+    };
+    // 用户自定义的短代码模板，修改后清空
+    static ref TEMPLATES: RwLock<Option<Arc<Tera>>> = RwLock::new(None);
+}
+
+// {{< name arg1 key="value" >}}，成对使用时 {{< name >}}...{{< /name >}} 中间的内容为 inner
+#[derive(Debug, Default)]
+pub(crate) struct Shortcode {
+    pub name: String,
+    pub args: Vec<String>,
+    pub params: HashMap<String, String>,
+    pub inner: Option<String>,
+}
+
+pub(crate) struct Context {
+    post_titles: HashMap<i64, String>,
+    // post-link 指向的地址，博客自己的页面、静态网站和各种导出格式各不相同
+    post_urls: HashMap<i64, String>,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    // 解析出来的短代码以及它的原文，展开失败时原样保留
+    Shortcode(Shortcode, &'a str),
+}
+
+fn escape(s: &str) -> String {
+    v_htmlescape::escape(s).to_string()
+}
+
+// 按位置或者名称取参数，例如 {{< youtube abc >}} 和 {{< youtube id="abc" >}}
+fn arg<'a>(code: &'a Shortcode, idx: usize, key: &str) -> Option<&'a str> {
+    code.params.get(key).or_else(|| code.args.get(idx)).map(|s| s.as_str())
+}
+
+fn valid_id(id: &str) -> Option<&str> {
+    if ID_REGEX.is_match(id) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+// 只允许 http(s) 和相对路径，避免 javascript: 之类的链接
+fn safe_url(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let lower = url.to_lowercase();
+    match lower.find(':') {
+        Some(pos) if !lower[..pos].contains('/') && &lower[..pos] != "http" && &lower[..pos] != "https" => None,
+        _ if url.is_empty() => None,
+        _ => Some(url),
+    }
+}
+
+fn iframe(src: &str) -> String {
+    format!(
+        "<figure class=\"video\"><iframe src=\"{}\" width=\"560\" height=\"315\" frameborder=\"0\" allow=\"fullscreen; picture-in-picture\" allowfullscreen loading=\"lazy\"></iframe></figure>\n",
+        escape(src)
+    )
+}
+
+fn embed_video(provider: &str, id: &str) -> Option<String> {
+    let id = valid_id(id)?;
+    let src = match provider {
+        "youtube" => format!("https://www.youtube-nocookie.com/embed/{}", id),
+        "vimeo" => format!("https://player.vimeo.com/video/{}", id),
+        "bilibili" => format!("https://player.bilibili.com/player.html?bvid={}&autoplay=0", id),
+        _ => return None,
+    };
+    Some(iframe(&src))
+}
+
+// {{< video youtube ID >}} 或者 {{< video src="/upload/a.mp4" >}}
+fn video(code: &Shortcode, _: &Context) -> Option<String> {
+    if let Some(src) = code.params.get("src") {
+        return Some(format!(
+            "<figure class=\"video\"><video src=\"{}\" controls preload=\"metadata\"></video></figure>\n",
+            escape(safe_url(src)?)
+        ));
+    }
+    embed_video(arg(code, 0, "provider")?, arg(code, 1, "id")?)
+}
+
+fn youtube(code: &Shortcode, _: &Context) -> Option<String> {
+    embed_video("youtube", arg(code, 0, "id")?)
+}
+
+fn vimeo(code: &Shortcode, _: &Context) -> Option<String> {
+    embed_video("vimeo", arg(code, 0, "id")?)
+}
+
+fn bilibili(code: &Shortcode, _: &Context) -> Option<String> {
+    embed_video("bilibili", arg(code, 0, "id")?)
+}
+
+// {{< gist user id [file] >}}
+fn gist(code: &Shortcode, _: &Context) -> Option<String> {
+    let user = valid_id(arg(code, 0, "user")?)?;
+    let id = valid_id(arg(code, 1, "id")?)?;
+    let file = match arg(code, 2, "file") {
+        Some(f) => format!("?file={}", valid_id(f)?),
+        None => String::new(),
+    };
+    Some(format!(
+        "<script src=\"https://gist.github.com/{}/{}.js{}\"></script>\n",
+        user, id, file
+    ))
+}
+
+// {{< tweet user id >}}
+fn tweet(code: &Shortcode, _: &Context) -> Option<String> {
+    let user = valid_id(arg(code, 0, "user")?)?;
+    let id = valid_id(arg(code, 1, "id")?)?;
+    Some(format!(
+        "<blockquote class=\"twitter-tweet\"><a href=\"https://twitter.com/{}/status/{}\">https://twitter.com/{}/status/{}</a></blockquote>\n<script async src=\"https://platform.twitter.com/widgets.js\" charset=\"utf-8\"></script>\n",
+        user, id, user, id
+    ))
+}
+
+// {{< figure src="a.png" caption="说明" >}}，成对使用时中间的内容作为说明
+fn figure(code: &Shortcode, _: &Context) -> Option<String> {
+    let src = safe_url(arg(code, 0, "src")?)?;
+    let caption = match &code.inner {
+        Some(inner) => inner.trim().to_string(),
+        None => code.params.get("caption").map_or(String::new(), |c| escape(c)),
+    };
+    let alt = code.params.get("alt").or_else(|| code.params.get("caption")).map_or("", |a| a.as_str());
+    let img = format!("<img src=\"{}\" alt=\"{}\" loading=\"lazy\">", escape(src), escape(alt));
+    let mut html = String::from("<figure class=\"image\">");
+    match code.params.get("link").and_then(|l| safe_url(l)) {
+        Some(link) => html.push_str(&format!("<a href=\"{}\">{}</a>", escape(link), img)),
+        None => html.push_str(&img),
+    }
+    if !caption.is_empty() {
+        html.push_str(&format!("<figcaption>{}</figcaption>", caption));
+    }
+    html.push_str("</figure>\n");
+    Some(html)
+}
+
+// {{< callout warning "标题" >}}内容{{< /callout >}}
+fn callout(code: &Shortcode, _: &Context) -> Option<String> {
+    let kind = arg(code, 0, "type").unwrap_or("note");
+    let class = match kind {
+        "note" => "is-link",
+        "info" => "is-info",
+        "tip" => "is-success",
+        "warning" => "is-warning",
+        "danger" => "is-danger",
+        _ => return None,
+    };
+    let mut html = format!("<div class=\"notification {} is-light callout callout-{}\">", class, kind);
+    if let Some(title) = arg(code, 1, "title") {
+        html.push_str(&format!("<p class=\"callout-title\"><strong>{}</strong></p>", escape(title)));
+    }
+    match &code.inner {
+        Some(inner) => html.push_str(inner),
+        None => html.push_str(&code.params.get("text").map_or(String::new(), |t| escape(t))),
+    }
+    html.push_str("</div>\n");
+    Some(html)
+}
+
+// {{< post-link 123 >}}，没有指定 text 时使用博客标题
+fn post_link(code: &Shortcode, context: &Context) -> Option<String> {
+    let (url, text) = post_link_target(code, context)?;
+    Some(format!("<a class=\"post-link\" href=\"{}\">{}</a>", escape(url), escape(text)))
+}
+
+fn post_link_target<'a>(code: &'a Shortcode, context: &'a Context) -> Option<(&'a str, &'a str)> {
+    let id = arg(code, 0, "id")?.parse::<i64>().ok()?;
+    let text = match arg(code, 1, "text") {
+        Some(t) => t,
+        None => context.post_titles.get(&id)?.as_str(),
+    };
+    Some((context.post_urls.get(&id)?.as_str(), text))
+}
+
+fn post_link_ids(segments: &[Segment]) -> Vec<i64> {
+    segments
+        .iter()
+        .filter_map(|s| match s {
+            Segment::Shortcode(code, _) if code.name == "post-link" => arg(code, 0, "id")?.parse::<i64>().ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+fn markdown_link(url: &str, text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    format!("[{}]({})", escaped, url)
+}
+
+// 静态网站和导出的 Markdown 里，把 post-link 换成 Markdown 链接，urls 是导出的博客在这个输出里的地址
+// 链接的博客没有导出时只保留文字，其它短代码保持不变
+fn replace_post_links(markdown: &str, context: &Context) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    for segment in parse(markdown) {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Shortcode(code, _) if code.name == "post-link" => match post_link_target(&code, context) {
+                Some((url, text)) => output.push_str(&markdown_link(url, text)),
+                None => output.push_str(arg(&code, 1, "text").unwrap_or_default()),
+            },
+            Segment::Shortcode(_, source) => output.push_str(source),
+        }
+    }
+    output
+}
+
+pub(crate) fn has_post_links(markdown: &str) -> bool {
+    markdown.contains("post-link") && !post_link_ids(&parse(markdown)).is_empty()
+}
+
+pub(crate) async fn link_posts(markdown: &str, urls: &HashMap<i64, String>) -> Result<String> {
+    let ids = post_link_ids(&parse(markdown));
+    if ids.is_empty() {
+        return Ok(String::from(markdown));
+    }
+    let context = Context {
+        post_urls: ids.iter().filter_map(|id| urls.get(id).map(|url| (*id, url.clone()))).collect(),
+        post_titles: post::titles(ids).await?,
+    };
+    Ok(replace_post_links(markdown, &context))
+}
+
+fn quoted(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '\\' => value.push(chars.next()?),
+            '"' => return Some(value),
+            c => value.push(c),
+        }
+    }
+}
+
+// 参数用空白分隔，包含空白时用双引号，key=value 为命名参数
+fn tokenize(s: &str) -> Option<Vec<(Option<String>, String)>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = None;
+        let mut value = String::new();
+        loop {
+            match chars.peek() {
+                None => break,
+                Some(c) if c.is_whitespace() => break,
+                Some('"') => {
+                    chars.next();
+                    value.push_str(&quoted(&mut chars)?);
+                },
+                Some('=') if key.is_none() && !value.is_empty() => {
+                    chars.next();
+                    key = Some(std::mem::take(&mut value));
+                },
+                Some(_) => value.push(chars.next().unwrap()),
+            }
+        }
+        tokens.push((key, value));
+    }
+    Some(tokens)
+}
+
+// 返回短代码以及它在原文中的长度，成对使用时包括结束标签
+fn parse_shortcode(s: &str) -> Option<(Shortcode, usize)> {
+    let end = s.find(">}}")?;
+    let mut tokens = tokenize(&s[3..end])?.into_iter();
+    let name = match tokens.next()? {
+        (None, name) if NAME_REGEX.is_match(&name) => name,
+        _ => return None,
+    };
+    let mut code = Shortcode {
+        name,
+        ..Default::default()
+    };
+    for (key, value) in tokens {
+        match key {
+            Some(k) => {
+                code.params.insert(k, value);
+            },
+            None => code.args.push(value),
+        }
+    }
+    let mut len = end + 3;
+    // 下一个同名短代码之前有结束标签才算成对使用
+    let name = regex::escape(&code.name);
+    let closing = Regex::new(&format!(r"\{{\{{<\s*/\s*{}\s*>\}}\}}", name)).ok()?;
+    let opening = Regex::new(&format!(r"\{{\{{<\s*{}[\s>]", name)).ok()?;
+    if let Some(m) = closing.find(&s[len..]) {
+        if opening.find(&s[len..]).map_or(true, |o| o.start() > m.start()) {
+            code.inner = Some(s[len..len + m.start()].to_string());
+            len += m.end();
+        }
+    }
+    Some((code, len))
+}
+
+// 代码块和行内代码里的短代码保持不变
+fn parse(markdown: &str) -> Vec<Segment<'_>> {
+    let bytes = markdown.as_bytes();
+    let mut segments = Vec::new();
+    let mut fence: Option<String> = None;
+    let mut text_start = 0;
+    let mut pos = 0;
+    while pos < markdown.len() {
+        if pos == 0 || bytes[pos - 1] == b'\n' {
+            let line_end = markdown[pos..].find('\n').map_or(markdown.len(), |i| pos + i + 1);
+            let trimmed = markdown[pos..line_end].trim_start();
+            if let Some(f) = &fence {
+                if trimmed.starts_with(f.as_str()) {
+                    fence = None;
+                }
+                pos = line_end;
+                continue;
+            }
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                let c = trimmed.chars().next().unwrap();
+                fence = Some(trimmed.chars().take_while(|ch| *ch == c).collect());
+                pos = line_end;
+                continue;
+            }
+        }
+        let rest = &markdown[pos..];
+        if rest.starts_with('`') {
+            let run = rest.chars().take_while(|c| *c == '`').count();
+            pos += rest[run..].find(&rest[..run]).map_or(run, |i| run + i + run);
+            continue;
+        }
+        if rest.starts_with("{{<") {
+            if let Some((code, len)) = parse_shortcode(rest) {
+                segments.push(Segment::Text(&markdown[text_start..pos]));
+                segments.push(Segment::Shortcode(code, &rest[..len]));
+                pos += len;
+                text_start = pos;
+                continue;
+            }
+        }
+        pos += rest.chars().next().unwrap().len_utf8();
+    }
+    segments.push(Segment::Text(&markdown[text_start..]));
+    segments
+}
+
+fn template_name(name: &str) -> String {
+    // .html 结尾的模板 Tera 才会自动转义参数
+    format!("{}.html", name)
+}
+
+fn render_template(templates: &Tera, code: &Shortcode) -> Option<String> {
+    let name = template_name(&code.name);
+    if !templates.get_template_names().any(|n| n == name) {
+        return None;
+    }
+    let mut context = tera::Context::new();
+    context.insert("args", &code.args);
+    context.insert("params", &code.params);
+    context.insert("inner", code.inner.as_ref().map_or("", |i| i.as_str()));
+    match templates.render(&name, &context) {
+        Ok(html) => Some(html),
+        Err(e) => {
+            eprintln!("{:?}", e);
+            None
+        },
+    }
+}
+
+pub async fn get_templates() -> Result<Vec<ShortcodeTemplate>> {
+    match management::get_setting(val::SHORTCODE_TEMPLATES_SETTING).await? {
+        Some(setting) if !setting.content.is_empty() => Ok(serde_json::from_str(&setting.content)?),
+        _ => Ok(vec![]),
+    }
+}
+
+pub async fn update_templates(templates: Vec<ShortcodeTemplate>) -> Result<()> {
+    let mut tera = Tera::default();
+    for t in templates.iter() {
+        if !NAME_REGEX.is_match(&t.name) || BUILTINS.contains_key(t.name.as_str()) {
+            return Err(Error::BusinessException(format!("短代码名称不可用/Invalid shortcode name: {}", t.name)).into());
+        }
+        if tera.get_template_names().any(|n| n == template_name(&t.name)) {
+            return Err(Error::BusinessException(format!("短代码名称重复/Duplicated shortcode name: {}", t.name)).into());
+        }
+        tera.add_raw_template(&template_name(&t.name), &t.template)?;
+    }
+    let setting = Setting {
+        item: String::from(val::SHORTCODE_TEMPLATES_SETTING),
+        content: serde_json::to_string(&templates)?,
+    };
+    management::update_setting(setting).await?;
+    *TEMPLATES.write() = Some(Arc::new(tera));
+    Ok(())
+}
+
+async fn templates() -> Result<Arc<Tera>> {
+    let cached = TEMPLATES.read().clone();
+    if let Some(t) = cached {
+        return Ok(t);
+    }
+    let mut tera = Tera::default();
+    for t in get_templates().await? {
+        if let Err(e) = tera.add_raw_template(&template_name(&t.name), &t.template) {
+            eprintln!("{:?}", e);
+        }
+    }
+    let tera = Arc::new(tera);
+    *TEMPLATES.write() = Some(tera.clone());
+    Ok(tera)
+}
+
+// 展开所有短代码，convert 用来生成占位符，避免生成的 HTML 被 comrak 转义
+pub(crate) async fn expand<F>(markdown: &str, comrak_options: &ComrakOptions, mut convert: F) -> Result<String>
+where
+    F: FnMut(String) -> String,
+{
+    let segments = parse(markdown);
+    if segments.len() == 1 {
+        return Ok(String::from(markdown));
+    }
+    let post_ids = post_link_ids(&segments);
+    // 博客自己的页面
+    let context = Context {
+        post_urls: post_ids.iter().map(|id| (*id, format!("/posts/{}", id))).collect(),
+        post_titles: post::titles(post_ids).await?,
+    };
+    let templates = templates().await?;
+    let mut output = String::with_capacity(markdown.len());
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Shortcode(mut code, source) => {
+                code.inner = code.inner.map(|inner| markdown_to_html(&inner, comrak_options));
+                let html = match BUILTINS.get(code.name.as_str()) {
+                    Some(handler) => handler(&code, &context),
+                    None => render_template(&templates, &code),
+                };
+                match html {
+                    Some(html) => output.push_str(&convert(html)),
+                    None => output.push_str(source),
+                }
+            },
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcodes(markdown: &str) -> Vec<(String, &str)> {
+        parse(markdown)
+            .into_iter()
+            .filter_map(|s| match s {
+                Segment::Shortcode(code, source) => Some((code.name, source)),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_args() {
+        let tokens = tokenize(r#" youtube  abc title="A \"b\" c" w=640 "#).unwrap();
+        assert_eq!(
+            vec![
+                (None, String::from("youtube")),
+                (None, String::from("abc")),
+                (Some(String::from("title")), String::from("A \"b\" c")),
+                (Some(String::from("w")), String::from("640")),
+            ],
+            tokens
+        );
+        assert_eq!(vec![(None, String::from("a=b"))], tokenize(r#""a=b""#).unwrap());
+        assert!(tokenize(r#"title="unclosed"#).is_none());
+    }
+
+    #[test]
+    fn parse_single_and_paired() {
+        let (code, len) = parse_shortcode(r#"{{< figure src="/a.png" caption=Hi >}} rest"#).unwrap();
+        assert_eq!("figure", code.name);
+        assert_eq!(Some(&String::from("/a.png")), code.params.get("src"));
+        assert_eq!(Some(&String::from("Hi")), code.params.get("caption"));
+        assert!(code.inner.is_none());
+        assert_eq!(38, len);
+
+        let s = "{{< callout warning >}}Be **careful**{{< /callout >}} after";
+        let (code, len) = parse_shortcode(s).unwrap();
+        assert_eq!(vec![String::from("warning")], code.args);
+        assert_eq!(Some(String::from("Be **careful**")), code.inner);
+        assert_eq!(" after", &s[len..]);
+
+        // 下一个同名短代码之前没有结束标签
+        let s = "{{< callout >}}a{{< callout >}}b{{< /callout >}}";
+        assert!(parse_shortcode(s).unwrap().0.inner.is_none());
+
+        assert!(parse_shortcode("{{< Bad-Name >}}").is_none());
+        assert!(parse_shortcode("{{< youtube abc").is_none());
+    }
+
+    #[test]
+    fn replace_post_links_for_output() {
+        let context = Context {
+            post_titles: HashMap::from([(1, String::from("A [draft]")), (2, String::from("Private"))]),
+            post_urls: HashMap::from([(1, String::from("{{< relref \"posts/a\" >}}"))]),
+        };
+        let link = "({{< relref \"posts/a\" >}})";
+        assert_eq!(format!("[A \\[draft\\]]{}", link), replace_post_links("{{< post-link 1 >}}", &context));
+        let markdown = "{{< post-link 1 \"see *this*\" >}}";
+        assert_eq!(format!("[see \\*this\\*]{}", link), replace_post_links(markdown, &context));
+        // 没有导出的博客只保留文字，其它短代码不变
+        let markdown = "{{< post-link 2 >}}, {{< post-link 2 text=Hi >}}, {{< youtube x >}}";
+        assert_eq!(", Hi, {{< youtube x >}}", replace_post_links(markdown, &context));
+        assert!(has_post_links("{{< post-link 1 >}}"));
+        assert!(!has_post_links("`{{< post-link 1 >}}`"));
+    }
+
+    #[test]
+    fn skip_code() {
+        let markdown = "a {{< youtube x >}}\n```\n{{< youtube y >}}\n```\n`{{< youtube z >}}` {{< gist u/1 >}}";
+        assert_eq!(
+            vec![(String::from("youtube"), "{{< youtube x >}}"), (String::from("gist"), "{{< gist u/1 >}}")],
+            shortcodes(markdown)
+        );
+        assert!(shortcodes("~~~\n{{< youtube y >}}\n").is_empty());
+    }
+}
@@ -0,0 +1,662 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use blog_common::{
+    dto::site::{SiteBuildReport, SiteOptions},
+    result::Error,
+    util::time,
+};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{
+    db::{management, model::Setting, post},
+    service::{
+        backup,
+        export::{self, ExportPost, Exporter},
+        highlight,
+        manifest::BuildOutput,
+        markdown,
+        shortcode,
+    },
+    util::{date, result::Result, val},
+};
+
+const THEME_DIR: &str = "themes";
+const OUTPUT_DIR: &str = "site";
+const FEED_SIZE: usize = 20;
+const SUMMARY_LENGTH: usize = 200;
+
+// 内置主题，themes/<主题>/templates 里的同名文件会覆盖它们
+static BUILTIN_TEMPLATES: [(&str, &str); 12] = [
+    ("base.html", include_str!("../resource/static-site/theme/base.html")),
+    ("index.html", include_str!("../resource/static-site/theme/index.html")),
+    (
+        "post.html",
+        include_str!("../resource/static-site/template/post_detail.html"),
+    ),
+    (
+        "post_list.html",
+        include_str!("../resource/static-site/theme/post_list.html"),
+    ),
+    (
+        "pagination.html",
+        include_str!("../resource/static-site/theme/pagination.html"),
+    ),
+    ("tag.html", include_str!("../resource/static-site/theme/tag.html")),
+    ("tags.html", include_str!("../resource/static-site/theme/tags.html")),
+    (
+        "archive.html",
+        include_str!("../resource/static-site/theme/archive.html"),
+    ),
+    ("404.html", include_str!("../resource/static-site/theme/404.html")),
+    ("atom.xml", include_str!("../resource/static-site/theme/atom.xml")),
+    ("rss.xml", include_str!("../resource/static-site/theme/rss.xml")),
+    ("sitemap.xml", include_str!("../resource/static-site/theme/sitemap.xml")),
+];
+static BUILTIN_STYLE: &str = include_str!("../resource/static-site/theme/style.css");
+
+lazy_static! {
+    static ref OPTIONS: RwLock<Option<SiteOptions>> = RwLock::new(None);
+    static ref HTML_TAG_REGEX: Regex = Regex::new(r"<[^>]*>").unwrap();
+}
+
+pub async fn get_options() -> Result<SiteOptions> {
+    let cached = OPTIONS.read().clone();
+    if let Some(o) = cached {
+        return Ok(o);
+    }
+    let options = match management::get_setting(val::STATIC_SITE_OPTIONS_SETTING).await? {
+        Some(setting) if !setting.content.is_empty() => serde_json::from_str::<SiteOptions>(&setting.content)?,
+        _ => SiteOptions::default(),
+    };
+    *OPTIONS.write() = Some(options.clone());
+    Ok(options)
+}
+
+pub async fn update_options(options: SiteOptions) -> Result<()> {
+    if options.page_size == 0 {
+        return Err(Error::BusinessException(String::from("每页数量不正确/Invalid page size.")).into());
+    }
+    let base_url = options.base_url.trim();
+    if !base_url.is_empty() && !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+        return Err(Error::BusinessException(String::from("网站地址不正确/Invalid base URL.")).into());
+    }
+    theme_dir(&options.theme)?;
+    let setting = Setting {
+        item: String::from(val::STATIC_SITE_OPTIONS_SETTING),
+        content: serde_json::to_string(&options)?,
+    };
+    management::update_setting(setting).await?;
+    *OPTIONS.write() = Some(options);
+    Ok(())
+}
+
+// themes 目录下的子目录
+pub fn themes() -> Result<Vec<String>> {
+    let dir = std::env::current_dir()?.join(THEME_DIR);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut themes = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            themes.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    themes.sort();
+    Ok(themes)
+}
+
+fn theme_dir(theme: &str) -> Result<Option<PathBuf>> {
+    let theme = theme.trim();
+    if theme.is_empty() {
+        return Ok(None);
+    }
+    let dir = std::env::current_dir()?.join(THEME_DIR).join(theme);
+    if theme.contains('/') || theme.contains('\\') || theme.starts_with('.') || !dir.is_dir() {
+        return Err(Error::BusinessException(String::from("主题不存在/Theme not found.")).into());
+    }
+    Ok(Some(dir))
+}
+
+fn template_error(e: tera::Error) -> Error {
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(s) = source {
+        message.push_str(": ");
+        message.push_str(&s.to_string());
+        source = s.source();
+    }
+    Error::BusinessException(format!("模板错误/Template error: {}", message))
+}
+
+// 内置模板 < 博客详情模板（只对应 post.html）< 主题里的模板
+async fn load_templates(theme: Option<&Path>) -> Result<Tera> {
+    let mut templates = BUILTIN_TEMPLATES
+        .iter()
+        .map(|(name, content)| (name.to_string(), content.to_string()))
+        .collect::<HashMap<String, String>>();
+    if let Some(setting) = management::get_setting(val::POST_DETAIL_RENDER_TEMPLATE).await? {
+        if !setting.content.trim().is_empty() {
+            templates.insert(String::from("post.html"), setting.content);
+        }
+    }
+    if let Some(dir) = theme.map(|t| t.join("templates")).filter(|d| d.is_dir()) {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                templates.insert(name, std::fs::read_to_string(entry.path())?);
+            }
+        }
+    }
+    // 模板之间有继承关系，需要一次全部加进去
+    let mut tera = Tera::default();
+    tera.add_raw_templates(templates.iter().map(|(n, c)| (n.as_str(), c.as_str())))
+        .map_err(template_error)?;
+    Ok(tera)
+}
+
+// 博客页面和引用的图片放在 posts/<slug>/ 目录下
+struct SitePages {
+    root: String,
+}
+
+impl Exporter for SitePages {
+    fn name(&self) -> &'static str {
+        "site"
+    }
+
+    fn default_template(&self) -> &'static str {
+        include_str!("../resource/static-site/template/post_detail.html")
+    }
+
+    fn file_path(&self, post: &ExportPost) -> String {
+        format!("{}index.html", post_dir(post))
+    }
+
+    fn asset_path(&self, post: &ExportPost, file_name: &str) -> (String, String) {
+        let path = format!("{}{}", post_dir(post), file_name);
+        let link = format!("{}{}", self.root, path);
+        (path, link)
+    }
+
+    fn post_url(&self, post: &ExportPost) -> String {
+        format!("{}{}", self.root, post_dir(post))
+    }
+}
+
+fn post_dir(post: &ExportPost) -> String {
+    format!("posts/{}/", post.slug)
+}
+
+// 没有设置网站地址时使用 /，这时 feed 和 sitemap 里的地址不完整
+fn site_root(base_url: &str) -> String {
+    format!("{}/", base_url.trim().trim_end_matches('/'))
+}
+
+// 标签可能是中文，保留 Unicode 字母和数字
+fn tag_slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("tag")
+    } else {
+        slug.to_string()
+    }
+}
+
+fn summary(html: &str) -> String {
+    let text = HTML_TAG_REGEX.replace_all(html, " ");
+    let text = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    if text.chars().count() > SUMMARY_LENGTH {
+        let mut s = text.chars().take(SUMMARY_LENGTH).collect::<String>();
+        s.push('…');
+        s
+    } else {
+        text
+    }
+}
+
+#[derive(Serialize)]
+struct SiteInfo {
+    title: String,
+    description: String,
+    base_url: String,
+    // 以 / 结尾，页面里的地址都以它开头
+    root: String,
+    updated: String,
+    updated_rfc2822: String,
+}
+
+#[derive(Clone, Serialize)]
+struct TagLink {
+    name: String,
+    url: String,
+    count: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct TranslationLink {
+    language: String,
+    title: String,
+    url: String,
+}
+
+// 模板里使用的博客，content 是渲染后的 HTML
+#[derive(Clone, Serialize)]
+struct SitePost {
+    id: i64,
+    title: String,
+    url: String,
+    #[serde(skip)]
+    path: String,
+    content: String,
+    summary: String,
+    title_image: String,
+    tags: Vec<TagLink>,
+    language: String,
+    translations: Vec<TranslationLink>,
+    unlisted: bool,
+    created_at: i64,
+    updated_at: i64,
+    date: String,
+    updated: String,
+    datetime: String,
+    updated_datetime: String,
+    // RSS 的 pubDate
+    pub_date: String,
+}
+
+#[derive(Serialize)]
+struct Paginator {
+    current: usize,
+    total: usize,
+    previous: Option<String>,
+    next: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ArchiveYear<'a> {
+    year: String,
+    posts: Vec<&'a SitePost>,
+}
+
+#[derive(Serialize)]
+struct SitemapUrl {
+    loc: String,
+    lastmod: String,
+}
+
+struct Output {
+    files: BuildOutput,
+    tera: Tera,
+    site: SiteInfo,
+}
+
+impl Output {
+    fn render(&mut self, template: &str, path: &str, mut context: Context) -> Result<()> {
+        context.insert("site", &self.site);
+        let content = self.tera.render(template, &context).map_err(template_error)?;
+        self.files.write(path, content.as_bytes())
+    }
+
+    // 第一页是 <dir>index.html，之后是 <dir>page/2/index.html
+    fn paginate(
+        &mut self,
+        template: &str,
+        dir: &str,
+        posts: &[&SitePost],
+        page_size: usize,
+        context: &Context,
+    ) -> Result<()> {
+        let total = std::cmp::max(1, posts.len().div_ceil(page_size));
+        let url = |page: usize| {
+            if page <= 1 {
+                format!("{}{}", self.site.root, dir)
+            } else {
+                format!("{}{}page/{}/", self.site.root, dir, page)
+            }
+        };
+        let pages = (1..=total)
+            .map(|page| Paginator {
+                current: page,
+                total,
+                previous: if page > 1 { Some(url(page - 1)) } else { None },
+                next: if page < total { Some(url(page + 1)) } else { None },
+            })
+            .collect::<Vec<Paginator>>();
+        for paginator in pages {
+            let start = (paginator.current - 1) * page_size;
+            let end = std::cmp::min(start + page_size, posts.len());
+            let path = if paginator.current <= 1 {
+                format!("{}index.html", dir)
+            } else {
+                format!("{}page/{}/index.html", dir, paginator.current)
+            };
+            let mut context = context.clone();
+            context.insert("posts", &posts[start..end]);
+            context.insert("paginator", &paginator);
+            self.render(template, &path, context)?;
+        }
+        Ok(())
+    }
+}
+
+fn site_posts(posts: &[ExportPost], root: &str) -> (Vec<SitePost>, Vec<TagLink>) {
+    // 只为公开列出的博客生成标签页
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for post in posts.iter().filter(|p| !p.unlisted) {
+        for tag in post.tags.iter() {
+            *counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut names = counts.keys().copied().collect::<Vec<&str>>();
+    names.sort();
+    let mut used = HashSet::new();
+    let mut tags = HashMap::new();
+    for name in names {
+        let mut slug = tag_slug(name);
+        let mut n = 2;
+        while used.contains(&slug) {
+            slug = format!("{}-{}", tag_slug(name), n);
+            n += 1;
+        }
+        used.insert(slug.clone());
+        let link = TagLink {
+            name: name.to_string(),
+            url: format!("{}tags/{}/", root, slug),
+            count: counts[name],
+        };
+        tags.insert(name, link);
+    }
+
+    let urls = posts
+        .iter()
+        .map(|p| (p.id, format!("{}{}", root, post_dir(p))))
+        .collect::<HashMap<i64, String>>();
+    let list = posts
+        .iter()
+        .map(|p| SitePost {
+            id: p.id,
+            title: p.title.clone(),
+            url: urls[&p.id].clone(),
+            path: post_dir(p),
+            summary: summary(&p.content),
+            content: p.content.clone(),
+            title_image: p.title_image.clone(),
+            tags: p.tags.iter().filter_map(|t| tags.get(t.as_str()).cloned()).collect(),
+            language: p.language.clone(),
+            translations: p
+                .translations
+                .iter()
+                .filter_map(|t| {
+                    urls.get(&t.id).map(|url| TranslationLink {
+                        language: t.language.clone(),
+                        title: t.title.clone(),
+                        url: url.clone(),
+                    })
+                })
+                .collect(),
+            unlisted: p.unlisted,
+            created_at: p.created_at,
+            updated_at: p.updated_at,
+            date: p.date.clone(),
+            updated: p.updated.clone(),
+            datetime: p.datetime.clone(),
+            updated_datetime: p.updated_datetime.clone(),
+            pub_date: date::format_rfc2822(p.created_at),
+        })
+        .collect();
+    let mut tags = tags.into_values().collect::<Vec<TagLink>>();
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    (list, tags)
+}
+
+// 生成完整的静态网站：首页和分页、博客、标签、归档、feed、sitemap、404，以及样式和图片
+pub async fn build(dir: &Path) -> Result<SiteBuildReport> {
+    let options = get_options().await?;
+    let theme = theme_dir(&options.theme)?;
+    let tera = load_templates(theme.as_deref()).await?;
+    let root = site_root(&options.base_url);
+    let pages = SitePages { root: root.clone() };
+
+    let posts = post::all().await?;
+    let mut rendered = posts
+        .iter()
+        .map(|p| (p.id, p.rendered_content.clone()))
+        .collect::<HashMap<i64, String>>();
+    let mut posts = export::export_posts(posts, false).await?;
+    posts.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    let mut missing = Vec::new();
+    let mut assets = Vec::new();
+    let urls = export::post_urls(&pages, &posts);
+    for post in posts.iter_mut() {
+        // 保存时 post-link 渲染成了博客自己的地址，需要换成静态网站里的地址重新渲染
+        post.content = if shortcode::has_post_links(&post.content) {
+            markdown::render(&shortcode::link_posts(&post.content, &urls).await?).await?.html
+        } else {
+            rendered.remove(&post.id).unwrap_or_default()
+        };
+        assets.extend(export::bundle_assets(&pages, post, &mut missing)?);
+    }
+    let (posts, tags) = site_posts(&posts, &root);
+
+    let updated_at = posts
+        .iter()
+        .map(|p| p.updated_at)
+        .max()
+        .unwrap_or(time::unix_epoch_sec() as i64);
+    let mut output = Output {
+        files: BuildOutput::open(dir)?,
+        tera,
+        site: SiteInfo {
+            title: options.title.clone(),
+            description: options.description.clone(),
+            base_url: options.base_url.clone(),
+            root: root.clone(),
+            updated: date::format_rfc3339(updated_at),
+            updated_rfc2822: date::format_rfc2822(updated_at),
+        },
+    };
+
+    for post in posts.iter() {
+        // 兼容以前的博客详情模板，博客的字段也放在最外层
+        let mut context = Context::new();
+        context.insert("post", post);
+        context.insert("title", &post.title);
+        context.insert("content", &post.content);
+        context.insert("language", &post.language);
+        context.insert("translations", &post.translations);
+        output.render("post.html", &format!("{}index.html", post.path), context)?;
+    }
+
+    let listed = posts.iter().filter(|p| !p.unlisted).collect::<Vec<&SitePost>>();
+    let page_size = options.page_size as usize;
+    output.paginate("index.html", "", &listed, page_size, &Context::new())?;
+    for tag in tags.iter() {
+        let tagged = listed
+            .iter()
+            .filter(|p| p.tags.iter().any(|t| t.name == tag.name))
+            .copied()
+            .collect::<Vec<&SitePost>>();
+        let mut context = Context::new();
+        context.insert("tag", tag);
+        let tag_dir = tag.url[root.len()..].to_string();
+        output.paginate("tag.html", &tag_dir, &tagged, page_size, &context)?;
+    }
+    let mut context = Context::new();
+    context.insert("tags", &tags);
+    output.render("tags.html", "tags/index.html", context)?;
+
+    let mut years: Vec<ArchiveYear> = Vec::new();
+    for post in listed.iter().copied() {
+        let year = &post.datetime[..4];
+        match years.last_mut() {
+            Some(y) if y.year == year => y.posts.push(post),
+            _ => years.push(ArchiveYear {
+                year: year.to_string(),
+                posts: vec![post],
+            }),
+        }
+    }
+    let mut context = Context::new();
+    context.insert("years", &years);
+    output.render("archive.html", "archive/index.html", context)?;
+
+    let feed = &listed[..std::cmp::min(FEED_SIZE, listed.len())];
+    for name in ["atom.xml", "rss.xml"] {
+        let mut context = Context::new();
+        context.insert("posts", feed);
+        output.render(name, name, context)?;
+    }
+
+    let mut urls = vec![root.clone(), format!("{}archive/", root), format!("{}tags/", root)]
+        .into_iter()
+        .map(|loc| SitemapUrl {
+            loc,
+            lastmod: output.site.updated.clone(),
+        })
+        .collect::<Vec<SitemapUrl>>();
+    urls.extend(tags.iter().map(|t| SitemapUrl {
+        loc: t.url.clone(),
+        lastmod: String::new(),
+    }));
+    urls.extend(listed.iter().map(|p| SitemapUrl {
+        loc: p.url.clone(),
+        lastmod: p.updated.clone(),
+    }));
+    let mut context = Context::new();
+    context.insert("urls", &urls);
+    output.render("sitemap.xml", "sitemap.xml", context)?;
+    output.render("404.html", "404.html", Context::new())?;
+
+    // 服务端高亮的代码块使用设置里的高亮主题，主题的 static 目录会覆盖内置的样式
+    let mut style = String::from(BUILTIN_STYLE);
+    let markdown_options = markdown::get_options().await?;
+    if markdown_options.syntax_highlight {
+        if let Some(css) = highlight::theme_css(&markdown_options.highlight_theme)? {
+            style.push('\n');
+            style.push_str(&css);
+        }
+    }
+    output.files.write("style.css", style.as_bytes())?;
+    if let Some(static_dir) = theme.map(|t| t.join("static")) {
+        let mut files = Vec::new();
+        backup::list_files(&static_dir, "", &mut files)?;
+        for (name, source) in files {
+            output.files.copy(name.trim_start_matches('/'), &source)?;
+        }
+    }
+    for asset in assets {
+        output.files.copy(&asset.path, &asset.source)?;
+    }
+
+    let files = output.files.len();
+    Ok(SiteBuildReport {
+        output: dir.display().to_string(),
+        posts: posts.len(),
+        tags: tags.len(),
+        files,
+        changes: output.files.finish()?,
+        missing,
+    })
+}
+
+// 在管理页面生成到 site 目录，可以用任意的 Web 服务器部署
+pub async fn build_local() -> Result<SiteBuildReport> {
+    let dir = std::env::current_dir()?.join(OUTPUT_DIR);
+    build(&dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use blog_common::dto::post::{PostData, Visibility};
+
+    use super::*;
+    use crate::util::testing;
+
+    async fn new_post(title: &str, tags: &[&str], content: &str) -> i64 {
+        let id = post::new_post().await.unwrap();
+        let data = PostData {
+            id,
+            title: String::from(title),
+            title_image: String::new(),
+            content: String::from(content),
+            // 编辑器没有标签时提交 null
+            tags: Some(tags.iter().map(|t| t.to_string()).collect::<Vec<String>>()).filter(|t| !t.is_empty()),
+            category_id: None,
+            language: String::new(),
+            translation_of: None,
+            visibility: Visibility::Public,
+            password: None,
+        };
+        post::save(data).await.unwrap();
+        id
+    }
+
+    #[test]
+    fn build_into_dir() {
+        testing::block_on(async {
+            let code = "Hello\n\n```rust {hl_lines=[1]}\nfn main() {}\n```\n";
+            let one = new_post("Site One", &["rust"], code).await;
+            new_post("Site Two", &["rust", "site"], code).await;
+            new_post("Site Three", &[], &format!("See {{{{< post-link {} >}}}}.", one)).await;
+            let options = SiteOptions {
+                title: String::from("Test Site"),
+                base_url: String::from("https://example.com/blog/"),
+                page_size: 1,
+                ..Default::default()
+            };
+            update_options(options).await.unwrap();
+
+            let dir = testing::temp_dir("site");
+            let report = build(&dir).await.unwrap();
+            assert!(report.posts >= 3);
+            assert!(report.missing.is_empty());
+            // Tera 转义后的 / 是 &#x2F;
+            let read = |path: &str| std::fs::read_to_string(dir.join(path)).unwrap().replace("&#x2F;", "/");
+
+            assert!(read("index.html").contains("Test Site"));
+            assert!(read("page/2/index.html").contains("https://example.com/blog/page/3/"));
+            assert!(read("posts/site-one/index.html").contains("hl-code"));
+            assert!(read("tags/rust/index.html").contains("Site Two"));
+            assert!(read("tags/rust/page/2/index.html").contains("Site One"));
+            assert!(read("tags/index.html").contains("https://example.com/blog/tags/site/"));
+            assert!(read("archive/index.html").contains("Site Three"));
+            let three = read("posts/site-three/index.html");
+            assert!(three.contains("<a href=\"https://example.com/blog/posts/site-one/\">Site One</a>"), "{}", three);
+            assert!(read("atom.xml").contains("https://example.com/blog/posts/site-two/"));
+            assert!(read("rss.xml").contains("<rss"));
+            assert!(read("sitemap.xml").contains("<loc>https://example.com/blog/tags/rust/</loc>"));
+            assert!(read("style.css").contains(".hl-code .code-line.highlighted"));
+            assert!(dir.join("404.html").is_file());
+
+            // 再生成一次没有变化
+            let report = build(&dir).await.unwrap();
+            assert!(report.changes.added.is_empty() && report.changes.updated.is_empty());
+        });
+    }
+}
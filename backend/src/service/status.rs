@@ -10,13 +10,22 @@ use crate::util::result::Result;
 
 const MAX_USER_IDLE_MILLIS: u64 = 1800000;
 const MAX_VERIFY_CODE_IDLE_MILLIS: u64 = 300000;
+// 输入密码解锁博客后，授权的有效时间（秒）
+pub(crate) const POST_GRANT_SECS: u64 = 1800;
+// 同一篇博客连续输错这么多次后，每次失败等待的秒数翻倍，最长等 UNLOCK_MAX_WAIT_SECS
+const UNLOCK_FREE_ATTEMPTS: u32 = 5;
+const UNLOCK_MAX_WAIT_SECS: u64 = 300;
 
 type OnlineUsers = HashMap<String, OnlineUser>;
 type VerifyCodes = HashMap<String, VerifyCode>;
+type PostGrants = HashMap<String, PostGrant>;
+type UnlockFailures = HashMap<i64, UnlockFailure>;
 
 lazy_static! {
     static ref ONLINE_USERS: Arc<RwLock<OnlineUsers>> = Arc::new(RwLock::new(HashMap::with_capacity(32)));
     static ref VERIFY_CODES: Arc<RwLock<VerifyCodes>> = Arc::new(RwLock::new(HashMap::with_capacity(128)));
+    static ref POST_GRANTS: Arc<RwLock<PostGrants>> = Arc::new(RwLock::new(HashMap::with_capacity(32)));
+    static ref UNLOCK_FAILURES: Arc<RwLock<UnlockFailures>> = Arc::new(RwLock::new(HashMap::with_capacity(32)));
 }
 
 struct OnlineUser {
@@ -33,6 +42,26 @@ struct VerifyCode {
     pub last_active_time: u64,
 }
 
+struct PostGrant {
+    post_id: i64,
+    granted_time: u64,
+}
+
+struct UnlockFailure {
+    count: u32,
+    last_failed_time: u64,
+}
+
+impl UnlockFailure {
+    fn wait_secs(&self) -> u64 {
+        if self.count < UNLOCK_FREE_ATTEMPTS {
+            return 0;
+        }
+        let exp = (self.count - UNLOCK_FREE_ATTEMPTS).min(16);
+        (1u64 << exp).min(UNLOCK_MAX_WAIT_SECS)
+    }
+}
+
 pub async fn scanner() {
     let mut current_timestamp = 0u64;
     loop {
@@ -62,6 +91,14 @@ pub async fn scanner() {
                 }
             });
         }
+        {
+            let mut post_grants = POST_GRANTS.write();
+            post_grants.retain(|_, v| current_timestamp - v.granted_time <= POST_GRANT_SECS);
+        }
+        {
+            let mut unlock_failures = UNLOCK_FAILURES.write();
+            unlock_failures.retain(|_, v| current_timestamp - v.last_failed_time <= UNLOCK_MAX_WAIT_SECS);
+        }
         sleep(Duration::from_secs(10)).await;
     }
 }
@@ -83,6 +120,55 @@ pub(crate) fn check_auth(token: Option<String>) -> Result<UserInfo> {
     Err(Error::NotAuthed.into())
 }
 
+pub(crate) fn grant_post_access(post_id: i64) -> String {
+    let token = crate::util::common::simple_uuid();
+    POST_GRANTS.write().insert(
+        token.clone(),
+        PostGrant {
+            post_id,
+            granted_time: time::unix_epoch_sec(),
+        },
+    );
+    token
+}
+
+pub(crate) fn check_post_grant(token: &str, post_id: i64) -> bool {
+    match POST_GRANTS.read().get(token) {
+        Some(g) => g.post_id == post_id && time::unix_epoch_sec() - g.granted_time <= POST_GRANT_SECS,
+        None => false,
+    }
+}
+
+// 输错密码太多次时，要等一段时间才能再试
+pub(crate) fn check_unlock_attempt(post_id: i64) -> Result<()> {
+    if let Some(f) = UNLOCK_FAILURES.read().get(&post_id) {
+        let elapsed = time::unix_epoch_sec().saturating_sub(f.last_failed_time);
+        let wait = f.wait_secs();
+        if elapsed < wait {
+            return Err(Error::BusinessException(format!(
+                "尝试次数过多，请 {0} 秒后再试/Too many attempts, please retry in {0} seconds.",
+                wait - elapsed
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn record_unlock_failure(post_id: i64) {
+    let mut unlock_failures = UNLOCK_FAILURES.write();
+    let f = unlock_failures.entry(post_id).or_insert(UnlockFailure {
+        count: 0,
+        last_failed_time: 0,
+    });
+    f.count += 1;
+    f.last_failed_time = time::unix_epoch_sec();
+}
+
+pub(crate) fn clear_unlock_failures(post_id: i64) {
+    UNLOCK_FAILURES.write().remove(&post_id);
+}
+
 pub(crate) fn user_online(token: &str, user: UserInfo) {
     ONLINE_USERS.write().insert(
         String::from(token),
@@ -144,3 +230,37 @@ pub fn check_verify_code(token: Option<String>, code: &str) -> Result<String> {
     }
     Ok(token)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_unlock_attempts() {
+        let post_id = -35;
+        for _ in 0..UNLOCK_FREE_ATTEMPTS - 1 {
+            record_unlock_failure(post_id);
+            assert!(check_unlock_attempt(post_id).is_ok());
+        }
+        record_unlock_failure(post_id);
+        assert!(check_unlock_attempt(post_id).is_err());
+
+        clear_unlock_failures(post_id);
+        assert!(check_unlock_attempt(post_id).is_ok());
+    }
+
+    #[test]
+    fn double_unlock_wait() {
+        let wait = |count| {
+            UnlockFailure {
+                count,
+                last_failed_time: 0,
+            }
+            .wait_secs()
+        };
+        assert_eq!(0, wait(UNLOCK_FREE_ATTEMPTS - 1));
+        assert_eq!(1, wait(UNLOCK_FREE_ATTEMPTS));
+        assert_eq!(4, wait(UNLOCK_FREE_ATTEMPTS + 2));
+        assert_eq!(UNLOCK_MAX_WAIT_SECS, wait(u32::MAX));
+    }
+}
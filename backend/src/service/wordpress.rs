@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::Path,
+};
+
+use blog_common::{
+    dto::import::{ImportReport, ImportSkipped},
+    result::Error,
+    util::time,
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use roxmltree::{Document, Node};
+
+use crate::{
+    service::import::{self, Candidate},
+    util::{date, result::Result},
+};
+
+const CONTENT_NAMESPACE: &str = "http://purl.org/rss/1.0/modules/content/";
+const UPLOADS_PATH: &str = "wp-content/uploads/";
+
+lazy_static! {
+    static ref BLOCK_COMMENT_REGEX: Regex = Regex::new(r"<!--\s*/?wp:[^>]*-->").unwrap();
+    static ref CAPTION_REGEX: Regex = Regex::new(r"\[/?caption[^\]]*\]").unwrap();
+    // WordPress 生成的缩略图：photo-300x200.jpg
+    static ref THUMBNAIL_REGEX: Regex = Regex::new(r"-\d+x\d+(\.[A-Za-z]+)$").unwrap();
+}
+
+// WXR 的版本号在命名空间里（1.0/1.1/1.2），所以只按前缀判断
+fn is_wp(node: &Node) -> bool {
+    node.tag_name()
+        .namespace()
+        .map_or(false, |ns| ns.starts_with("http://wordpress.org/export/") && !ns.ends_with("/excerpt/"))
+}
+
+fn child<'a, 'input>(node: &Node<'a, 'input>, name: &str, wp: bool) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name && is_wp(n) == wp)
+}
+
+fn child_text(node: &Node, name: &str, wp: bool) -> String {
+    child(node, name, wp)
+        .and_then(|n| n.text())
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+fn content_of(item: &Node) -> String {
+    item.children()
+        .find(|n| n.is_element() && n.tag_name().name() == "encoded" && n.tag_name().namespace() == Some(CONTENT_NAMESPACE))
+        .and_then(|n| n.text())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn post_meta(item: &Node, key: &str) -> Option<String> {
+    item.children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "postmeta" && is_wp(n))
+        .find(|n| child_text(n, "meta_key", true) == key)
+        .map(|n| child_text(&n, "meta_value", true))
+}
+
+// 经典编辑器保存的内容没有 <p>，按空行分段，和 WordPress 的 wpautop 类似
+fn autop(html: &str) -> String {
+    if html.contains("<p") {
+        return html.to_string();
+    }
+    let mut output = String::with_capacity(html.len() + 64);
+    for block in html.replace("\r\n", "\n").split("\n\n") {
+        let block = block.trim();
+        if block.is_empty() {
+            continue;
+        }
+        let is_block_element = ["<h", "<ul", "<ol", "<pre", "<blockquote", "<table", "<div", "<figure", "<hr"]
+            .iter()
+            .any(|tag| block.starts_with(tag));
+        if is_block_element {
+            output.push_str(block);
+        } else {
+            output.push_str("<p>");
+            output.push_str(&block.replace('\n', "<br />\n"));
+            output.push_str("</p>");
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn to_markdown(html: &str) -> String {
+    let html = BLOCK_COMMENT_REGEX.replace_all(html, "");
+    let html = CAPTION_REGEX.replace_all(&html, "");
+    html2md::parse_html(&autop(&html)).trim().to_string()
+}
+
+// 上传目录可以是 wp-content/uploads，也可以是它的上级目录，所以按结尾匹配
+fn resolve_upload(files: &HashMap<String, Vec<u8>>, url: &str) -> Option<String> {
+    let url = url.split(|c| c == '?' || c == '#').next().unwrap_or("");
+    let path = match url.find(UPLOADS_PATH) {
+        Some(pos) => &url[pos + UPLOADS_PATH.len()..],
+        None => match url.find("://") {
+            Some(pos) => url[pos + 3..].find('/').map_or("", |p| &url[pos + 3 + p + 1..]),
+            None => url.trim_start_matches('/'),
+        },
+    };
+    if path.is_empty() {
+        return None;
+    }
+    let path = format!("/{}", path);
+    import::resolve_image(files, "", &path).or_else(|| {
+        // 找不到缩略图时使用原图
+        let original = THUMBNAIL_REGEX.replace(&path, "$1");
+        import::resolve_image(files, "", &original)
+    })
+}
+
+fn item_date(item: &Node) -> Option<i64> {
+    date::parse_datetime(&child_text(item, "post_date_gmt", true))
+        .or_else(|| date::parse_datetime(&child_text(item, "post_date", true)))
+}
+
+fn item_tags(item: &Node) -> Vec<String> {
+    let mut seen = HashSet::new();
+    item.children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "category" && !is_wp(n))
+        .filter(|n| matches!(n.attribute("domain"), Some("category") | Some("post_tag")))
+        // 默认分类不导入
+        .filter(|n| n.attribute("nicename") != Some("uncategorized"))
+        .filter_map(|n| n.text().map(|t| t.trim().to_string()))
+        .filter(|t| !t.is_empty() && seen.insert(t.clone()))
+        .collect()
+}
+
+fn parse(xml: &str, files: &HashMap<String, Vec<u8>>) -> Result<(Vec<Candidate>, Vec<ImportSkipped>)> {
+    let doc = Document::parse(xml).map_err(|e| {
+        eprintln!("{}", e);
+        Error::BusinessException(String::from("WXR 文件格式不正确/Invalid WXR file."))
+    })?;
+    let channel = doc
+        .root_element()
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name() == "channel")
+        .ok_or_else(|| Error::BusinessException(String::from("WXR 文件格式不正确/Invalid WXR file.")))?;
+    let items = channel
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "item")
+        .collect::<Vec<_>>();
+
+    // 附件的 id 和地址，用来找题图
+    let attachments = items
+        .iter()
+        .filter(|i| child_text(i, "post_type", true) == "attachment")
+        .map(|i| (child_text(i, "post_id", true), child_text(i, "attachment_url", true)))
+        .collect::<HashMap<String, String>>();
+
+    let mut candidates = Vec::new();
+    let mut skipped = Vec::new();
+    for item in items.iter().filter(|i| child_text(i, "post_type", true) == "post") {
+        let link = child_text(item, "link", false);
+        let source = if link.is_empty() {
+            format!("post {}", child_text(item, "post_id", true))
+        } else {
+            link.clone()
+        };
+        let status = child_text(item, "status", true);
+        if status == "trash" || status == "auto-draft" {
+            skipped.push(ImportSkipped {
+                source,
+                reason: format!("状态/Status: {}", status),
+            });
+            continue;
+        }
+        let content = to_markdown(&content_of(item));
+        let mut title = child_text(item, "title", false);
+        if title.is_empty() {
+            title = child_text(item, "post_name", true);
+        }
+        if title.is_empty() && content.is_empty() {
+            skipped.push(ImportSkipped {
+                source,
+                reason: String::from("空文章/Empty post."),
+            });
+            continue;
+        }
+        // 图片通常是本站 wp-content/uploads 下的完整地址，其它外部图片保持原样
+        let images = import::image_urls(&content)
+            .into_iter()
+            .filter(|url| import::is_local(url) || url.contains(UPLOADS_PATH))
+            .map(|url| {
+                let found = resolve_upload(files, &url);
+                (url, found)
+            })
+            .collect();
+        let title_image = post_meta(item, "_thumbnail_id")
+            .and_then(|id| attachments.get(&id))
+            .and_then(|url| resolve_upload(files, url));
+        candidates.push(Candidate {
+            source,
+            title,
+            created_at: item_date(item),
+            tags: item_tags(item),
+            draft: status != "publish",
+            content,
+            images,
+            title_image,
+            old_url: if link.is_empty() { None } else { Some(link) },
+        });
+    }
+    Ok((candidates, skipped))
+}
+
+// RFC 4180：包含逗号、引号或换行的字段用引号括起来，引号写两次
+fn csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+// 每行一条：旧地址,新地址
+fn write_mapping(report: &ImportReport) -> Result<Option<String>> {
+    let lines = report
+        .posts
+        .iter()
+        .filter_map(|p| match (&p.old_url, p.id) {
+            (Some(old_url), Some(id)) => Some(format!("{},/posts/{}\r\n", csv_field(old_url), id)),
+            _ => None,
+        })
+        .collect::<Vec<String>>();
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    let dir = std::env::current_dir()?.join("export");
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    let filename = format!("wordpress-redirects-{}.csv", time::unix_epoch_sec());
+    let mut file = std::fs::File::create(dir.join(&filename))?;
+    file.write_all(b"old_url,new_url\r\n")?;
+    for line in lines {
+        file.write_all(line.as_bytes())?;
+    }
+    Ok(Some(format!("/export/{}", filename)))
+}
+
+// uploads 是 WordPress 的上传目录，为空时不导入图片
+pub async fn import_wxr(xml: &str, uploads: Option<&str>, dry_run: bool) -> Result<ImportReport> {
+    let files = match uploads.filter(|u| !u.trim().is_empty()) {
+        Some(dir) => import::read_dir(Path::new(dir))?,
+        None => HashMap::new(),
+    };
+    let (candidates, skipped) = parse(xml, &files)?;
+    let mut report = import::import_candidates(candidates, skipped, &files, dry_run).await?;
+    if !dry_run {
+        report.mapping_file = write_mapping(&report)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_csv_fields() {
+        assert_eq!("https://example.com/?p=1", csv_field("https://example.com/?p=1"));
+        assert_eq!("\"https://example.com/a,b\"", csv_field("https://example.com/a,b"));
+        assert_eq!("\"say \"\"hi\"\"\"", csv_field("say \"hi\""));
+        assert_eq!("\"a\nb\"", csv_field("a\nb"));
+    }
+}
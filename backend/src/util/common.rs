@@ -19,4 +19,6 @@ lazy_static! {
     pub static ref BLANKS: Regex = Regex::new(r"\s\s+").unwrap();
     pub static ref EMAIL_REGEX: Regex = Regex::new(r"[^@ \t\r\n]+@[^@ \t\r\n]+\.[^@ \t\r\n]+").unwrap();
     pub static ref HTML_TAG_REGEX: Regex = Regex::new(r"<[^>]+>|<[^>]>|</[^>]>").unwrap();
+    // BCP 47 语言代码，例如：en、zh-CN、zh-Hant-TW
+    pub static ref LANGUAGE_CODE_REGEX: Regex = Regex::new(r"^[A-Za-z]{2,3}(-[A-Za-z0-9]{2,8})*$").unwrap();
 }
@@ -1,13 +1,27 @@
-use std::vec::Vec;
+use std::{io::Write, vec::Vec};
 
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
 // https://medium.com/analytics-vidhya/password-hashing-pbkdf2-scrypt-bcrypt-and-argon2-e25aaf41598e
 use argon2::Argon2;
 use base64;
+use blog_common::result::Error;
+use hkdf::Hkdf;
+use once_cell::sync::OnceCell;
 use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
 // use subtle::ConstantTimeEq;
 
 use crate::util::result::Result;
 
+// 服务器密钥，第一次使用时随机生成；备份时和数据库一起备份，丢失后需要重新保存加密的数据
+pub(crate) const SECRET_FILE: &str = "server.secret";
+const NONCE_SIZE: usize = 12;
+
+static SECRET: OnceCell<[u8; 32]> = OnceCell::new();
+
 // https://github.com/P-H-C/phc-string-format/blob/master/phc-sf-spec.md#phc-string-format
 fn to_phc_string(salt: &[u8], encrypted_password: &[u8]) -> String {
     // $argon2i$v=19$m=512,t=4,p=2$eM+ZMyYkpDRGaI3xXmuNcQ$c5DeJg3eb5dskVt1mDdxfw
@@ -44,3 +58,67 @@ pub fn verify_password(password: &str, encrypted_password: &str) -> Result<bool>
     let p = encrypt_password_salt(salt.as_slice(), password.as_bytes())?;
     Ok(p.eq(encrypted_password))
 }
+
+fn create_secret(path: &std::path::Path) -> Result<[u8; 32]> {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(base64::encode(secret).as_bytes())?;
+    Ok(secret)
+}
+
+fn server_secret() -> Result<&'static [u8; 32]> {
+    SECRET.get_or_try_init(|| {
+        let path = std::env::current_dir()?.join(SECRET_FILE);
+        if !path.exists() {
+            return create_secret(&path);
+        }
+        let secret = base64::decode(std::fs::read_to_string(path)?.trim())?;
+        secret
+            .try_into()
+            .map_err(|_| Error::BusinessException(String::from("服务器密钥不正确/Invalid server secret.")).into())
+    })
+}
+
+// 不同用途使用不同的密钥
+fn cipher(purpose: &str) -> Result<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, server_secret()?)
+        .expand(purpose.as_bytes(), &mut key)
+        .map_err(|_| Error::InternalServerError)?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+// 返回 nonce + 密文
+pub(crate) fn encrypt(purpose: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(purpose)?
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| Error::InternalServerError)?;
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+pub(crate) fn decrypt(purpose: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let failed = || {
+        Error::BusinessException(String::from(
+            "无法解密，服务器密钥可能已更换/Cannot decrypt, the server secret may have changed.",
+        ))
+    };
+    if data.len() <= NONCE_SIZE {
+        return Err(failed().into());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+    cipher(purpose)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| failed().into())
+}
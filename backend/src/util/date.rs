@@ -0,0 +1,155 @@
+// 解析导入文件里常见的日期格式，返回 Unix 时间戳（秒）
+// 支持：2021-03-04、2021-03-04 12:00[:00]、2021-03-04T12:00:00Z、2021-03-04T12:00:00+08:00、2021-03-04 12:00:00 +0800
+// 没有时区时按 UTC 处理
+pub(crate) fn parse_datetime(s: &str) -> Option<i64> {
+    let s = s.trim().trim_matches('"').trim_matches('\'');
+    if s.len() < 10 {
+        return None;
+    }
+    let (date, rest) = s.split_at(10);
+    let mut parts = date.split(|c| c == '-' || c == '/');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<u32>().ok()?;
+    let day = parts.next()?.parse::<u32>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+
+    let rest = rest.trim_start_matches(|c| c == 'T' || c == 't' || c == ' ');
+    let (time, zone) = match rest.find(|c: char| c == 'Z' || c == 'z' || c == '+' || c == '-' || c == ' ') {
+        Some(pos) => (&rest[..pos], rest[pos..].trim()),
+        None => (rest, ""),
+    };
+    let mut seconds = 0i64;
+    if !time.is_empty() {
+        // 去掉秒后面的小数部分
+        let time = time.split('.').next()?;
+        let mut fields = time.split(':');
+        let hour = fields.next()?.parse::<i64>().ok()?;
+        let minute = fields.next().map_or(Some(0), |m| m.parse::<i64>().ok())?;
+        let second = fields.next().map_or(Some(0), |s| s.parse::<i64>().ok())?;
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        seconds = hour * 3600 + minute * 60 + second;
+    }
+    let offset = parse_offset(zone)?;
+    Some(days * 86400 + seconds - offset)
+}
+
+// 返回时区相对 UTC 的秒数
+fn parse_offset(zone: &str) -> Option<i64> {
+    if zone.is_empty() || zone.eq_ignore_ascii_case("z") || zone.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+    let sign = match zone.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = zone[1..].replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hour = digits[..2].parse::<i64>().ok()?;
+    let minute = digits[2..].parse::<i64>().ok()?;
+    Some(sign * (hour * 3600 + minute * 60))
+}
+
+// 公历日期到 1970-01-01 的天数，算法来自 http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// days_from_civil 的逆运算
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// 2021-08-30 13:44:28（UTC）
+pub(crate) fn format_datetime(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86400));
+    let seconds = timestamp.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// 2021-08-30T13:44:28Z
+pub(crate) fn format_rfc3339(timestamp: i64) -> String {
+    format!("{}Z", format_datetime(timestamp).replacen(' ', "T", 1))
+}
+
+// RSS 使用的格式：Mon, 30 Aug 2021 13:44:28 +0000
+pub(crate) fn format_rfc2822(timestamp: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let days = timestamp.div_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {:04} {} +0000",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        &format_datetime(timestamp)[11..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!(Some(1614816000), parse_datetime("2021-03-04"));
+        assert_eq!(Some(1614816000), parse_datetime("'2021/03/04'"));
+        assert_eq!(Some(1614859200), parse_datetime("2021-03-04 12:00"));
+        assert_eq!(Some(1614859200), parse_datetime("2021-03-04T12:00:00Z"));
+        assert_eq!(Some(1614859200), parse_datetime("2021-03-04T12:00:00.123Z"));
+        assert_eq!(Some(1614830400), parse_datetime("2021-03-04T12:00:00+08:00"));
+        assert_eq!(Some(1614830400), parse_datetime("\"2021-03-04 12:00:00 +0800\""));
+        assert_eq!(Some(1614888000), parse_datetime("2021-03-04T12:00:00-08:00"));
+    }
+
+    #[test]
+    fn reject_invalid_dates() {
+        let invalid = ["", "2021-3-4", "2021-13-01", "2021-03-32", "2021-03-04 25:00", "2021-03-04T12:00:00+8", "x"];
+        for s in invalid {
+            assert_eq!(None, parse_datetime(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!("2021-08-30 13:44:28", format_datetime(1630331068));
+        assert_eq!("2021-08-30T13:44:28Z", format_rfc3339(1630331068));
+        assert_eq!("Mon, 30 Aug 2021 13:44:28 +0000", format_rfc2822(1630331068));
+        assert_eq!("1970-01-01 00:00:00", format_datetime(0));
+        assert_eq!("1969-12-31 23:59:59", format_datetime(-1));
+        assert_eq!("Wed, 31 Dec 1969 23:59:59 +0000", format_rfc2822(-1));
+        assert_eq!(Some(1630331068), parse_datetime(&format_rfc3339(1630331068)));
+    }
+}
@@ -0,0 +1,90 @@
+use blog_common::dto::diff::{DiffLine, DiffOp};
+
+// 超过这个大小时不再逐行比较，直接显示为全部删除再全部新增
+const MAX_CELLS: usize = 4_000_000;
+
+fn line(op: DiffOp, text: &str) -> DiffLine {
+    DiffLine {
+        op,
+        text: String::from(text),
+    }
+}
+
+// 按最长公共子序列逐行比较，先去掉相同的开头和结尾
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    let old = old.lines().collect::<Vec<&str>>();
+    let new = new.lines().collect::<Vec<&str>>();
+    let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    diff.extend(old[..prefix].iter().map(|l| line(DiffOp::Equal, l)));
+    if a.len() * b.len() > MAX_CELLS {
+        diff.extend(a.iter().map(|l| line(DiffOp::Delete, l)));
+        diff.extend(b.iter().map(|l| line(DiffOp::Insert, l)));
+    } else {
+        // lengths[i * width + j] 为 a[i..] 和 b[j..] 的最长公共子序列长度
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = if a[i] == b[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                diff.push(line(DiffOp::Equal, a[i]));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                diff.push(line(DiffOp::Delete, a[i]));
+                i += 1;
+            } else {
+                diff.push(line(DiffOp::Insert, b[j]));
+                j += 1;
+            }
+        }
+        diff.extend(a[i..].iter().map(|l| line(DiffOp::Delete, l)));
+        diff.extend(b[j..].iter().map(|l| line(DiffOp::Insert, l)));
+    }
+    diff.extend(old[old.len() - suffix..].iter().map(|l| line(DiffOp::Equal, l)));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(diff: &[DiffLine]) -> String {
+        diff.iter()
+            .map(|l| match l.op {
+                DiffOp::Equal => format!(" {}", l.text),
+                DiffOp::Insert => format!("+{}", l.text),
+                DiffOp::Delete => format!("-{}", l.text),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn diff_lines() {
+        assert_eq!(" a\n-b\n+B\n c", ops(&line_diff("a\nb\nc", "a\nB\nc")));
+        assert_eq!(" a\n+x\n b", ops(&line_diff("a\nb", "a\nx\nb")));
+        assert_eq!("-a\n b\n c\n-d\n+e", ops(&line_diff("a\nb\nc\nd", "b\nc\ne")));
+        assert_eq!(" same", ops(&line_diff("same\n", "same")));
+        assert!(line_diff("", "").is_empty());
+        assert_eq!("+a\n+b", ops(&line_diff("", "a\nb")));
+    }
+}
@@ -1,7 +1,11 @@
 pub(crate) mod common;
 pub(crate) mod crypt;
+pub(crate) mod date;
+pub(crate) mod diff;
 pub(crate) mod io;
 pub(crate) mod num;
 pub mod result;
 pub(crate) mod snowflake;
+#[cfg(test)]
+pub(crate) mod testing;
 pub(crate) mod val;
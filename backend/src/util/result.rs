@@ -139,6 +139,27 @@ impl From<base64::DecodeError> for ErrorWrapper {
     }
 }
 
+impl From<ssh_key::Error> for ErrorWrapper {
+    fn from(e: ssh_key::Error) -> Self {
+        eprintln!("{:?}", e);
+        ErrorWrapper(Error::BusinessException(String::from("SSH 密钥不正确/Invalid SSH key.")))
+    }
+}
+
+impl From<syntect::Error> for ErrorWrapper {
+    fn from(e: syntect::Error) -> Self {
+        eprintln!("{:?}", e);
+        ErrorWrapper(Error::InternalServerError)
+    }
+}
+
+impl From<syntect::parsing::ParsingError> for ErrorWrapper {
+    fn from(e: syntect::parsing::ParsingError) -> Self {
+        eprintln!("{:?}", e);
+        ErrorWrapper(Error::InternalServerError)
+    }
+}
+
 // impl From<scrypt::errors::InvalidOutputLen> for ErrorWrapper {
 //     fn from(e: scrypt::errors::InvalidOutputLen) -> Self {
 //         eprintln!("{:?}", e);
@@ -1,36 +1,47 @@
+use std::time::Duration;
+
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
 
 use blog_common::util::time;
 
 lazy_static! {
-    static ref LAST_TIMESTAMP: Mutex<u64> = Mutex::new(0);
+    // 上次生成 id 的时间（秒）和这一秒里的序号
+    static ref LAST_ID: Mutex<(u64, u8)> = Mutex::new((0, 0));
 }
 
 const START_TIME_MILLIS: u64 = 1643212800;
 
 pub(crate) fn gen_id() -> u64 {
     loop {
-        let current_timestamp = time::unix_epoch_sec();
-        loop {
-            let mut last_timestamp = LAST_TIMESTAMP.lock();
-            let mut sequence = 0u8;
-            if *last_timestamp == current_timestamp {
-                // u16::MAX;
-                if sequence == u8::MAX {
-                    break;
-                } else {
-                    sequence += 1;
-                }
-            } else {
-                *last_timestamp = current_timestamp;
-            }
-            let id = (current_timestamp - START_TIME_MILLIS) << 8;
-            if sequence == 0 {
-                return id;
-            } else {
-                return id | sequence as u64;
+        let mut last = LAST_ID.lock();
+        // 时钟回拨时继续使用上次的时间
+        let current_timestamp = std::cmp::max(time::unix_epoch_sec(), last.0);
+        let sequence = if last.0 == current_timestamp {
+            // 这一秒的序号用完了，等到下一秒
+            if last.1 == u8::MAX {
+                drop(last);
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
             }
-        }
+            last.1 + 1
+        } else {
+            0
+        };
+        *last = (current_timestamp, sequence);
+        return ((current_timestamp - START_TIME_MILLIS) << 8) | sequence as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn unique_within_a_second() {
+        let ids = (0..300).map(|_| gen_id()).collect::<HashSet<u64>>();
+        assert_eq!(300, ids.len());
     }
 }
@@ -0,0 +1,61 @@
+use std::{future::Future, path::PathBuf, sync::Once};
+
+use blog_common::dto::post::{PostData, Visibility};
+use lazy_static::lazy_static;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::db::{self, post};
+
+static WORK_DIR: Once = Once::new();
+static DATASOURCE: Once = Once::new();
+
+lazy_static! {
+    static ref RUNTIME: Runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+}
+
+// server.secret、git-pages 等都放在当前目录下，所有测试共用一个临时的工作目录
+pub(crate) fn work_dir() -> PathBuf {
+    WORK_DIR.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("blog-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.as_path()).unwrap();
+        std::env::set_current_dir(dir.as_path()).unwrap();
+    });
+    std::env::current_dir().unwrap()
+}
+
+// 每个测试自己的临时目录，已经存在时先清空
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("blog-test-{}-{}", std::process::id(), name));
+    if dir.exists() {
+        std::fs::remove_dir_all(dir.as_path()).unwrap();
+    }
+    std::fs::create_dir_all(dir.as_path()).unwrap();
+    dir
+}
+
+// 用到数据库的测试共用一个运行时和 blog.dat，sqlx 的连接池不能在别的运行时里使用
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    work_dir();
+    DATASOURCE.call_once(|| RUNTIME.block_on(db::init_datasource()));
+    RUNTIME.block_on(future)
+}
+
+// 新建一篇没有标签的博客，需要密码的博客密码是 secret
+pub(crate) async fn new_post(title: &str, category_id: Option<i64>, visibility: Visibility) -> i64 {
+    let id = post::new_post().await.unwrap();
+    let password = Some(String::from("secret")).filter(|_| visibility == Visibility::Password);
+    let data = PostData {
+        id,
+        title: String::from(title),
+        title_image: String::new(),
+        content: format!("Content of {}", title),
+        tags: None,
+        category_id,
+        language: String::new(),
+        translation_of: None,
+        visibility,
+        password,
+    };
+    post::save(data).await.unwrap();
+    id
+}
@@ -9,3 +9,7 @@ pub const IMAGE_ROOT_PATH: &str = "/home/songday/website/blog/upload/image";
 // pub const BLOG_PAGE_SIZE: u8 = 20u8;
 // pub const I64SIZE: usize = std::mem::size_of::<i64>();
 pub(crate) const POST_DETAIL_RENDER_TEMPLATE: &'static str = "post_detail_render_template";
+pub(crate) const MARKDOWN_OPTIONS_SETTING: &'static str = "markdown_options";
+pub(crate) const SHORTCODE_TEMPLATES_SETTING: &'static str = "shortcode_templates";
+pub(crate) const STATIC_SITE_OPTIONS_SETTING: &'static str = "static_site_options";
+pub(crate) const DEPLOY_TARGETS_SETTING: &'static str = "deploy_targets";
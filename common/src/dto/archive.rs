@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ArchiveMonth {
+    pub month: u8,
+    pub amount: u32,
+}
+
+// 按博客创建时间（UTC）归档
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ArchiveYear {
+    pub year: i32,
+    pub amount: u32,
+    pub months: Vec<ArchiveMonth>,
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Category {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CategoryNode {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    // 本分类（包括子分类）下的博客数量
+    pub amount: u32,
+    pub children: Vec<CategoryNode>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CategoryData {
+    pub name: String,
+    pub parent_id: Option<i64>,
+}
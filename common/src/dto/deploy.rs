@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use super::git::ConflictStrategy;
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeployDestination {
+    // 支持 http(s)、ssh、file:// 和本地的裸仓库路径
+    Git {
+        remote_url: String,
+        branch: String,
+        // 提交使用的 user.name 和 user.email
+        user_name: String,
+        email: String,
+        #[serde(default)]
+        conflict_strategy: ConflictStrategy,
+        // http(s) 的密码/令牌，只在保存时提交，加密保存在服务器上，不会返回；为空时保留已保存的
+        #[serde(default, skip_serializing)]
+        credential: String,
+    },
+    // 服务器上的目录
+    Local {
+        path: String,
+    },
+    // 通过 ssh 执行 rsync，使用 Git Pages 的部署密钥和 known_hosts，例如 user@host:/var/www/blog
+    Rsync {
+        destination: String,
+        #[serde(default = "default_ssh_port")]
+        port: u16,
+    },
+    // 和 rsync 一样的地址，用于只能使用 sftp 的服务器；每次上传所有导出的文件
+    Sftp {
+        destination: String,
+        #[serde(default = "default_ssh_port")]
+        port: u16,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeployTarget {
+    pub name: String,
+    pub destination: DeployDestination,
+    // hugo、jekyll、zola、hexo，为空时是 hugo
+    #[serde(default)]
+    pub exporter: String,
+    #[serde(default)]
+    pub render_html: bool,
+    #[serde(default)]
+    pub subdirectory: String,
+    // 自动部署的间隔（分钟），0 表示只手动部署
+    #[serde(default)]
+    pub schedule_minutes: u32,
+    #[serde(default)]
+    pub last_deploy_second: i64,
+}
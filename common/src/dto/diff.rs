@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+// 按行比较的结果
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DiffLine {
+    pub op: DiffOp,
+    pub text: String,
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExportResult {
+    // 压缩包的下载地址
+    pub url: String,
+    // 找不到的上传文件：博客标题和引用地址
+    pub missing: Vec<String>,
+}
+
+// 和上次导出相比有变化的文件
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ExportChanges {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+}
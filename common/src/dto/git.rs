@@ -1,18 +1,179 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize)]
+use crate::dto::{diff::DiffLine, export::ExportChanges};
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct GitRepositoryInfo {
     pub name: String,
     pub email: String,
     pub remote_url: String,
     pub repository_name: String,
     pub branch_name: Option<String>,
-    pub last_export_second: i64,
+    #[serde(default)]
+    pub conflict_strategy: ConflictStrategy,
+}
+
+// 拉取时和本地提交冲突的处理方式
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    // 返回冲突的文件，不做修改
+    Abort,
+    Ours,
+    Theirs,
+    // 拉取后重置到远程分支，再重新导出
+    ResetToRemote,
+}
+
+impl Default for ConflictStrategy {
+    fn default() -> Self {
+        ConflictStrategy::Abort
+    }
 }
 
 #[derive(Deserialize, Serialize)]
+pub struct GitPullOptions {
+    pub conflict_strategy: ConflictStrategy,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct GitPushInfo {
     pub subdirectory: String,
     pub render_html: bool,
+    // hugo、jekyll、zola、hexo，为空时是 hugo
+    #[serde(default)]
+    pub exporter: String,
+    // 为空时使用保存的密码/令牌
+    #[serde(default)]
     pub repo_credential: String,
+    // 推送成功后加密保存 repo_credential
+    #[serde(default)]
+    pub save_credential: bool,
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct GitPushResult {
+    pub changes: ExportChanges,
+    // 找不到的上传文件：博客标题和引用地址
+    pub missing: Vec<String>,
+}
+
+// 部署密钥只返回公钥，私钥加密保存在服务器上
+#[derive(Default, Deserialize, Serialize)]
+pub struct DeployKey {
+    pub public_key: String,
+    // SHA256:...
+    pub fingerprint: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct DeployKeyUpload {
+    // OpenSSH 格式的私钥，不能有密码
+    pub private_key: String,
+}
+
+// OpenSSH known_hosts 格式，例如 ssh-keyscan 的输出
+#[derive(Default, Deserialize, Serialize)]
+pub struct KnownHosts {
+    pub content: String,
+}
+
+// 保存的仓库密码/令牌，只返回是否已保存，不返回内容
+#[derive(Default, Deserialize, Serialize)]
+pub struct GitCredentialStatus {
+    pub saved: bool,
+    pub updated_at: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct GitCredential {
+    pub credential: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishState {
+    Queued,
+    Pulling,
+    Exporting,
+    Committing,
+    Pushing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl PublishState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, PublishState::Done | PublishState::Failed | PublishState::Cancelled)
+    }
+}
+
+// 后台发布任务，不保存密码/令牌
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PublishJob {
+    pub id: String,
+    // 部署目标的名称，为空时是 Git Pages
+    #[serde(default)]
+    pub target: String,
+    pub state: PublishState,
+    pub exporter: String,
+    pub render_html: bool,
+    pub subdirectory: String,
+    pub created_at: u64,
+    pub finished_at: u64,
+    pub logs: Vec<String>,
+    pub result: Option<GitPushResult>,
+    pub error: Option<String>,
+}
+
+// 通过 Server-Sent Events 推送给页面，line 为空时只是状态变化
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PublishEvent {
+    pub job_id: String,
+    pub state: PublishState,
+    pub line: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GitCommit {
+    pub id: String,
+    pub summary: String,
+    pub author: String,
+    pub time: i64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct GitStatus {
+    pub branch: String,
+    pub head: Option<GitCommit>,
+    // 最近的提交，最新的在前面
+    pub commits: Vec<GitCommit>,
+    // 和上次拉取到的远程分支比较
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GitFileChange {
+    pub path: String,
+    pub kind: GitChangeKind,
+    // 二进制或者太大的文件没有 diff
+    pub binary: bool,
+    pub diff: Vec<DiffLine>,
+}
+
+// 下次发布会提交的修改
+#[derive(Default, Deserialize, Serialize)]
+pub struct GitPreview {
+    pub files: Vec<GitFileChange>,
+    pub missing: Vec<String>,
 }
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+// 导入服务器上的目录，dry_run 时只生成报告，不写入数据
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImportDirectory {
+    pub path: String,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImportedPost {
+    // 在压缩包或者目录里的路径
+    pub source: String,
+    pub title: String,
+    pub created_at: u64,
+    pub tags: Vec<String>,
+    pub draft: bool,
+    pub images: usize,
+    pub missing_images: Vec<String>,
+    // 已经有同名的博客，导入时跳过
+    pub duplicate: bool,
+    // 原来的地址，WordPress 导入时才有
+    #[serde(default)]
+    pub old_url: Option<String>,
+    // 导入后的博客 id，dry run 时为空
+    pub id: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImportSkipped {
+    pub source: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub posts: Vec<ImportedPost>,
+    pub skipped: Vec<ImportSkipped>,
+    // 旧地址到新博客的对照表
+    #[serde(default)]
+    pub mapping_file: Option<String>,
+}
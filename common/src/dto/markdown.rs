@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+// 对应 comrak 的扩展选项，保存在 settings 表里
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MarkdownOptions {
+    pub table: bool,
+    pub strikethrough: bool,
+    pub tasklist: bool,
+    pub autolink: bool,
+    pub footnotes: bool,
+    pub superscript: bool,
+    pub description_lists: bool,
+    pub smart_punctuation: bool,
+    pub hard_breaks: bool,
+    pub github_pre_lang: bool,
+    // 服务端代码高亮
+    pub syntax_highlight: bool,
+    pub highlight_theme: String,
+    // $公式$ 转换成 MathML，dot 代码块渲染成 SVG
+    pub math: bool,
+    pub diagram: bool,
+    // 展开 {{< name >}} 短代码
+    pub shortcodes: bool,
+    // 标题的锚点，文章目录和 [TOC] 需要它
+    pub header_ids: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions {
+            table: true,
+            strikethrough: true,
+            tasklist: true,
+            autolink: true,
+            footnotes: true,
+            superscript: false,
+            description_lists: false,
+            smart_punctuation: false,
+            hard_breaks: false,
+            github_pre_lang: false,
+            syntax_highlight: true,
+            highlight_theme: String::from("inspiredgithub"),
+            math: true,
+            diagram: true,
+            shortcodes: true,
+            header_ids: true,
+        }
+    }
+}
+
+// 用户自定义的短代码，template 为 Tera 模板，可以使用 args、params 和 inner
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ShortcodeTemplate {
+    pub name: String,
+    pub template: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MarkdownPreview {
+    pub content: String,
+}
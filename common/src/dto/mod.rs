@@ -4,9 +4,17 @@ use serde::{Deserialize, Serialize};
 
 use crate::result::ErrorResponse;
 
+pub mod archive;
+pub mod category;
+pub mod deploy;
+pub mod diff;
+pub mod export;
 pub mod git;
+pub mod import;
 pub mod management;
+pub mod markdown;
 pub mod post;
+pub mod site;
 pub mod tag;
 pub mod user;
 
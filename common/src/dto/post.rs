@@ -6,8 +6,49 @@
 
 use serde::{Deserialize, Serialize};
 
+use super::{category::Category, diff::DiffLine};
+
 // use crate::result::Error;
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    // 所有人可见
+    Public,
+    // 不出现在列表里，知道链接的人可以查看
+    Unlisted,
+    // 出现在列表里，需要输入密码才能查看正文
+    Password,
+    // 只有登录后才能查看
+    Private,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Public
+    }
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Password => "password",
+            Visibility::Private => "private",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "unlisted" => Visibility::Unlisted,
+            "password" => Visibility::Password,
+            "private" => Visibility::Private,
+            _ => Visibility::Public,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct PostData {
     pub id: i64,
@@ -15,6 +56,18 @@ pub struct PostData {
     pub title_image: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    pub category_id: Option<i64>,
+    // 语言代码，例如：zh-CN、en
+    #[serde(default)]
+    pub language: String,
+    // 这篇博客是哪篇博客的翻译
+    #[serde(default)]
+    pub translation_of: Option<i64>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // 为空时保留原来的密码
+    #[serde(default)]
+    pub password: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -24,11 +77,51 @@ pub struct PostDetail {
     pub title_image: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    pub category_id: Option<i64>,
+    // 从顶级分类到当前分类的路径
+    pub breadcrumb: Option<Vec<Category>>,
+    // 文章目录，只有查看详情时才有
+    pub toc: Option<Vec<TocItem>>,
+    pub related: Option<Vec<RelatedPost>>,
+    // 置顶的博客显示在列表第一页的最上面，推荐的博客显示在首页的轮播图里
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub featured: bool,
+    #[serde(default)]
+    pub language: String,
+    pub translations: Option<Vec<Translation>>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    // 需要输入密码才能查看，此时 content 为空
+    #[serde(default)]
+    pub locked: bool,
     pub created_at: u64,
     pub updated_at: Option<u64>,
     pub editable: bool,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TocItem {
+    pub level: u8,
+    pub text: String,
+    pub anchor: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RelatedPost {
+    pub id: i64,
+    pub title: String,
+    pub title_image: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Translation {
+    pub id: i64,
+    pub language: String,
+    pub title: String,
+}
+
 impl PostDetail {
     pub fn default() -> Self {
         PostDetail {
@@ -37,6 +130,16 @@ impl PostDetail {
             title_image: String::new(),
             content: String::new(),
             tags: None,
+            category_id: None,
+            breadcrumb: None,
+            toc: None,
+            related: None,
+            pinned: false,
+            featured: false,
+            language: String::new(),
+            translations: None,
+            visibility: Visibility::Public,
+            locked: false,
             created_at: 0,
             updated_at: None,
             editable: false,
@@ -72,6 +175,29 @@ impl PostDetail {
 //     }
 // }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostUnlock {
+    pub password: String,
+}
+
+// 编辑器定时自动保存的草稿，和已发布的内容分开存放
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PostAutosave {
+    pub id: i64,
+    pub title: String,
+    pub content: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AutosaveDraft {
+    pub title: String,
+    pub content: String,
+    pub saved_at: u64,
+    // 已保存的标题，以及已保存内容和草稿的差异
+    pub saved_title: String,
+    pub diff: Vec<DiffLine>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UploadImage {
     pub relative_path: String,
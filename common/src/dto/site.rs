@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{dto::export::ExportChanges, val};
+
+// 生成静态网站的选项，保存在 settings 表里
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct SiteOptions {
+    pub title: String,
+    pub description: String,
+    // 网站地址，例如 https://example.github.io/blog，feed 和 sitemap 需要完整地址
+    pub base_url: String,
+    pub page_size: u8,
+    // themes 目录下的主题名，为空时使用内置主题
+    pub theme: String,
+}
+
+impl Default for SiteOptions {
+    fn default() -> Self {
+        SiteOptions {
+            title: String::from("Blog"),
+            description: String::new(),
+            base_url: String::new(),
+            page_size: val::POSTS_PAGE_SIZE,
+            theme: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SiteBuildReport {
+    pub output: String,
+    pub posts: usize,
+    pub tags: usize,
+    // 生成的所有文件，包括列表页、feed 和复制的图片
+    pub files: usize,
+    pub changes: ExportChanges,
+    // 找不到的上传文件：博客标题和引用地址
+    pub missing: Vec<String>,
+}
@@ -62,6 +62,8 @@ pub enum Error {
     SavePostIdDataByTagFailed,
     #[error("Tag not found")]
     TagNotFound,
+    #[error("Can not find category you requested")]
+    CategoryNotFound,
 
     #[error("{0}")]
     BusinessException(String),
@@ -69,6 +69,9 @@ impl Component for App {
                         <Link<Route> classes={"navbar-item"} to={Route::Tags}>
                             {"标签/Tags"}
                         </Link<Route>>
+                        <Link<Route> classes={"navbar-item"} to={Route::Archive}>
+                            {"归档/Archive"}
+                        </Link<Route>>
 
                       <div class="navbar-item has-dropdown is-hoverable">
                         <a class="navbar-link">
@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+use std::vec::Vec;
+
+use blog_common::dto::archive::ArchiveYear;
+use blog_common::dto::Response;
+use wasm_bindgen::prelude::*;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::i18n;
+use crate::router::Route;
+
+#[wasm_bindgen(module = "/asset/show.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = userLanguage)]
+    fn user_language() -> String;
+}
+
+#[derive(PartialEq, Properties)]
+pub struct ArchiveTreeComponentProps {
+    // 0 表示没有选中
+    #[prop_or_default]
+    pub year: i32,
+    #[prop_or_default]
+    pub month: u8,
+}
+
+#[function_component(ArchiveTreeComponent)]
+pub fn archive_tree(ArchiveTreeComponentProps { year, month }: &ArchiveTreeComponentProps) -> Html {
+    let archive: UseStateHandle<Vec<ArchiveYear>> = use_state(|| Vec::with_capacity(0));
+    let expanded: UseStateHandle<HashSet<i32>> = {
+        let year = *year;
+        use_state(move || HashSet::from([year]))
+    };
+    {
+        let archive = archive.clone();
+        let expanded = expanded.clone();
+        let year = *year;
+        use_effect_with_deps(
+            move |_| {
+                let archive = archive.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Vec<ArchiveYear>> = reqwasm::http::Request::get("/post/archive")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    let data = response.data.unwrap_or_default();
+                    // 没有选中年份时默认展开最近的一年
+                    if year == 0 {
+                        if let Some(first) = data.first() {
+                            expanded.set(HashSet::from([first.year]));
+                        }
+                    }
+                    archive.set(data);
+                });
+                || ()
+            },
+            (),
+        );
+    }
+    if archive.is_empty() {
+        return html! {};
+    }
+    let messages = i18n::get(&user_language(), vec!["archive"]).unwrap();
+    let years = archive
+        .iter()
+        .map(|y| {
+            let is_expanded = expanded.contains(&y.year);
+            let toggle = {
+                let expanded = expanded.clone();
+                let current = y.year;
+                Callback::from(move |_: MouseEvent| {
+                    let mut set = (*expanded).clone();
+                    if !set.remove(&current) {
+                        set.insert(current);
+                    }
+                    expanded.set(set);
+                })
+            };
+            let icon = if is_expanded { "fas fa-angle-down" } else { "fas fa-angle-right" };
+            let months = if is_expanded {
+                y.months
+                    .iter()
+                    .map(|m| {
+                        let classes = if *year == y.year && *month == m.month { "is-active" } else { "" };
+                        html! {
+                            <li>
+                                <Link<Route> classes={classes!(classes)} to={Route::ListPostsByMonth { year: y.year, month: m.month }}>
+                                    { format!("{:02}", m.month) }{" "}<span class="tag is-light is-rounded">{ m.amount }</span>
+                                </Link<Route>>
+                            </li>
+                        }
+                    })
+                    .collect::<Html>()
+            } else {
+                html! {}
+            };
+            html! {
+                <li>
+                    <a onclick={toggle}>
+                        <span class="icon"><i class={icon}></i></span>
+                        { y.year }{" "}<span class="tag is-light is-rounded">{ y.amount }</span>
+                    </a>
+                    <ul>{ months }</ul>
+                </li>
+            }
+        })
+        .collect::<Html>();
+    html! {
+        <aside class="menu">
+            <p class="menu-label">{ messages.get("archive").unwrap() }</p>
+            <ul class="menu-list">
+                { years }
+            </ul>
+        </aside>
+    }
+}
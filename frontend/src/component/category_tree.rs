@@ -0,0 +1,81 @@
+use std::vec::Vec;
+
+use blog_common::dto::category::CategoryNode;
+use blog_common::dto::Response;
+use wasm_bindgen::prelude::*;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::i18n;
+use crate::router::Route;
+
+#[wasm_bindgen(module = "/asset/show.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = userLanguage)]
+    fn user_language() -> String;
+}
+
+fn view_nodes(nodes: &Vec<CategoryNode>, active: Option<i64>) -> Html {
+    nodes
+        .iter()
+        .map(|node| {
+            let classes = if active == Some(node.id) { "is-active" } else { "" };
+            let children = if node.children.is_empty() {
+                html! {}
+            } else {
+                html! { <ul>{ view_nodes(&node.children, active) }</ul> }
+            };
+            html! {
+                <li>
+                    <Link<Route> classes={classes!(classes)} to={Route::ListPostsByCategory { id: node.id }}>
+                        { &node.name }{" "}<span class="tag is-light is-rounded">{ node.amount }</span>
+                    </Link<Route>>
+                    { children }
+                </li>
+            }
+        })
+        .collect::<Html>()
+}
+
+#[derive(PartialEq, Properties)]
+pub struct CategoryTreeComponentProps {
+    #[prop_or_default]
+    pub active: Option<i64>,
+}
+
+#[function_component(CategoryTreeComponent)]
+pub fn category_tree(CategoryTreeComponentProps { active }: &CategoryTreeComponentProps) -> Html {
+    let categories: UseStateHandle<Vec<CategoryNode>> = use_state(|| Vec::with_capacity(0));
+    {
+        let categories = categories.clone();
+        use_effect_with_deps(
+            move |_| {
+                let categories = categories.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Vec<CategoryNode>> = reqwasm::http::Request::get("/category/tree")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    categories.set(response.data.unwrap_or_default());
+                });
+                || ()
+            },
+            (),
+        );
+    }
+    if categories.is_empty() {
+        return html! {};
+    }
+    let messages = i18n::get(&user_language(), vec!["categories"]).unwrap();
+    html! {
+        <aside class="menu">
+            <p class="menu-label">{ messages.get("categories").unwrap() }</p>
+            <ul class="menu-list">
+                { view_nodes(&*categories, *active) }
+            </ul>
+        </aside>
+    }
+}
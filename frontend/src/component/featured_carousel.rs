@@ -0,0 +1,86 @@
+use std::vec::Vec;
+
+use blog_common::dto::post::PostDetail;
+use blog_common::dto::Response;
+use gloo::timers::callback::Interval;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::router::Route;
+
+// 每张轮播图显示的毫秒数
+const SLIDE_INTERVAL: u32 = 5000;
+
+#[function_component(FeaturedCarouselComponent)]
+pub fn featured_carousel() -> Html {
+    let posts: UseStateHandle<Vec<PostDetail>> = use_state(|| Vec::with_capacity(0));
+    let current = use_state(|| 0usize);
+    {
+        let posts = posts.clone();
+        use_effect_with_deps(
+            move |_| {
+                let posts = posts.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Vec<PostDetail>> = reqwasm::http::Request::get("/post/featured")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    posts.set(response.data.unwrap_or_default());
+                });
+                || ()
+            },
+            (),
+        );
+    }
+    {
+        let current = current.clone();
+        let amount = posts.len();
+        use_effect_with_deps(
+            move |(index, amount)| {
+                let index = *index;
+                let amount = *amount;
+                let interval = Interval::new(SLIDE_INTERVAL, move || {
+                    if amount > 1 {
+                        current.set((index + 1) % amount);
+                    }
+                });
+                move || drop(interval)
+            },
+            (*current, amount),
+        );
+    }
+    if posts.is_empty() {
+        return html! {};
+    }
+    let index = *current % posts.len();
+    let post = &posts[index];
+    let dots = (0..posts.len())
+        .map(|i| {
+            let current = current.clone();
+            let onclick = Callback::from(move |_: MouseEvent| current.set(i));
+            let classes = if i == index { "button is-small is-rounded is-white" } else { "button is-small is-rounded is-white is-outlined" };
+            html! { <button class={classes} {onclick}>{ i + 1 }</button> }
+        })
+        .collect::<Html>();
+    html! {
+        <section class="hero is-medium is-dark has-background mb-5">
+            <img src={ post.title_image.clone() } class="hero-background is-transparent" alt=""/>
+            <div class="hero-body">
+                <div class="container">
+                    <Link<Route> classes={classes!("title", "is-2", "is-block")} to={Route::ShowPost { id: post.id as u64 }}>
+                        { &post.title }
+                    </Link<Route>>
+                    <p class="subtitle">{ &post.content }</p>
+                </div>
+            </div>
+            <div class="hero-foot">
+                <div class="container buttons is-centered">
+                    { dots }
+                </div>
+            </div>
+        </section>
+    }
+}
@@ -1,6 +1,12 @@
+pub mod archive_tree;
+pub mod category_tree;
+pub mod featured_carousel;
 pub mod posts_list;
 pub mod unauthorized;
 
+pub use archive_tree::ArchiveTreeComponent;
+pub use category_tree::CategoryTreeComponent;
+pub use featured_carousel::FeaturedCarouselComponent;
 pub use posts_list::PostsListComponent;
 pub use unauthorized::Unauthorized;
 
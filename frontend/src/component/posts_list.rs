@@ -34,6 +34,9 @@ fn view_posts(posts: Vec<&PostDetail>) -> Html {
                 </div>
                 <div class="card-content">
                     <Link<Route> classes={classes!("title", "is-block")} to={Route::ShowPost { id: post.id as u64 }}>
+                        if post.pinned {
+                            <span class="icon has-text-warning"><i class="fas fa-thumbtack"></i></span>
+                        }
                         { &post.title }
                     </Link<Route>>
                 </div>
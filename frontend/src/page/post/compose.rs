@@ -1,11 +1,17 @@
 
 use std::collections::HashMap;
 
-use blog_common::dto::post::{PostData, PostDetail};
+use blog_common::dto::category::CategoryNode;
+use blog_common::dto::markdown::MarkdownPreview;
+use blog_common::dto::diff::DiffOp;
+use blog_common::dto::post::{AutosaveDraft, PostAutosave, PostData, PostDetail, Visibility};
 use blog_common::dto::Response;
+use gloo::timers::callback::Interval;
 use gloo_file::callbacks::FileReader;
+use time::format_description;
+use time::OffsetDateTime;
 use wasm_bindgen::prelude::*;
-use web_sys::HtmlInputElement;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use weblog::*;
 use yew::events::InputEvent;
 use yew::prelude::*;
@@ -25,6 +31,8 @@ extern "C" {
 extern "C" {
     #[wasm_bindgen(js_name = getContent)]
     fn get_content() -> String;
+    #[wasm_bindgen(js_name = setContent)]
+    fn set_content(content: &str);
     #[wasm_bindgen(js_name = inputTag)]
     fn input_tag(event: web_sys::KeyboardEvent);
     #[wasm_bindgen(js_name = showOriginTags)]
@@ -37,6 +45,144 @@ extern "C" {
     fn upload_title_image(event: Event, post_id: u64, files: Vec<web_sys::File>, payload_callback: JsValue);
 }
 
+fn flatten_categories(nodes: &Vec<CategoryNode>, depth: usize, result: &mut Vec<(i64, String)>) {
+    for node in nodes.iter() {
+        let mut label = "— ".repeat(depth);
+        label.push_str(&node.name);
+        result.push((node.id, label));
+        flatten_categories(&node.children, depth + 1, result);
+    }
+}
+
+// 自动保存草稿的间隔毫秒数
+const AUTOSAVE_INTERVAL: u32 = 30_000;
+// 差异里每处改动前后保留的行数
+const DIFF_CONTEXT_LINES: usize = 2;
+
+fn format_time(secs: u64) -> String {
+    let datetime = OffsetDateTime::from_unix_timestamp(secs as i64).unwrap();
+    let format = format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
+    datetime.format(&format).unwrap_or_default()
+}
+
+// 只显示改动的行和前后几行，其余的用省略号代替
+fn show_diff(draft: &AutosaveDraft) -> Html {
+    let changed = draft
+        .diff
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.op != DiffOp::Equal)
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    let near_change = |i: usize| {
+        changed
+            .iter()
+            .any(|c| i + DIFF_CONTEXT_LINES >= *c && i <= *c + DIFF_CONTEXT_LINES)
+    };
+    let mut lines = Vec::with_capacity(draft.diff.len());
+    let mut skipped = false;
+    for (i, l) in draft.diff.iter().enumerate() {
+        if !near_change(i) {
+            if !skipped {
+                lines.push(html! { <div class="has-text-grey">{ "…" }</div> });
+                skipped = true;
+            }
+            continue;
+        }
+        skipped = false;
+        let (prefix, style) = match l.op {
+            DiffOp::Insert => ("+ ", "background-color:#e6ffec"),
+            DiffOp::Delete => ("- ", "background-color:#ffebe9"),
+            DiffOp::Equal => ("  ", ""),
+        };
+        lines.push(html! { <div {style}>{ prefix }{ &l.text }</div> });
+    }
+    let title_change = if draft.title != draft.saved_title {
+        html! {
+            <>
+                <div style="background-color:#ffebe9">{ "- " }{ &draft.saved_title }</div>
+                <div style="background-color:#e6ffec">{ "+ " }{ &draft.title }</div>
+                <hr class="my-2"/>
+            </>
+        }
+    } else {
+        html! {}
+    };
+    html! {
+        <pre class="is-size-7" style="max-height:320px;overflow:auto;white-space:pre-wrap">
+            { title_change }
+            { lines.into_iter().collect::<Html>() }
+        </pre>
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Properties)]
+pub struct AutosaveOfferProps {
+    post_id: u64,
+    on_restore: Callback<AutosaveDraft>,
+}
+
+// 打开博客时如果有比已保存内容更新的草稿，提示是否恢复
+#[function_component(AutosaveOffer)]
+fn autosave_offer(AutosaveOfferProps { post_id, on_restore }: &AutosaveOfferProps) -> Html {
+    let draft = use_state(|| None::<AutosaveDraft>);
+    {
+        let draft = draft.clone();
+        let url = format!("/post/autosave/{}", post_id);
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Option<AutosaveDraft>> = reqwasm::http::Request::get(&url)
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    draft.set(response.data.flatten());
+                });
+                || ()
+            },
+            *post_id,
+        );
+    }
+    let current = match &*draft {
+        Some(d) => d.clone(),
+        None => return html! {},
+    };
+    let restore = {
+        let draft = draft.clone();
+        let on_restore = on_restore.clone();
+        let current = current.clone();
+        Callback::from(move |_: MouseEvent| {
+            on_restore.emit(current.clone());
+            draft.set(None);
+        })
+    };
+    let discard = {
+        let draft = draft.clone();
+        let url = format!("/post/autosave/discard/{}", post_id);
+        Callback::from(move |_: MouseEvent| {
+            let url = url.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = reqwasm::http::Request::get(&url).send().await;
+            });
+            draft.set(None);
+        })
+    };
+    let messages = i18n::get(&user_language(), vec!["autosave_found", "restore", "discard"]).unwrap();
+    html! {
+        <div class="notification is-warning is-light">
+            <p class="block">{ messages.get("autosave_found").unwrap() }{ " " }{ format_time(current.saved_at) }</p>
+            { show_diff(&current) }
+            <div class="buttons mt-3">
+                <button class="button is-warning" onclick={restore}>{ messages.get("restore").unwrap() }</button>
+                <button class="button is-light" onclick={discard}>{ messages.get("discard").unwrap() }</button>
+            </div>
+        </div>
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub struct UpdatePostProps {
     onsubmit: Callback<FocusEvent>,
@@ -46,6 +192,11 @@ pub struct UpdatePostProps {
     post_id: u64,
     title_onchange: Callback<String>,
     title_image_onchange: Callback<String>,
+    category_onchange: Callback<Option<i64>>,
+    language_onchange: Callback<String>,
+    translation_onchange: Callback<Option<i64>>,
+    visibility_onchange: Callback<Visibility>,
+    password_onchange: Callback<String>,
 }
 
 #[function_component(UpdatePost)]
@@ -58,11 +209,17 @@ fn update_post(
         post_id,
         title_onchange,
         title_image_onchange,
+        category_onchange,
+        language_onchange,
+        translation_onchange,
+        visibility_onchange,
+        password_onchange,
     }: &UpdatePostProps,
 ) -> Html {
     let detail_url = format!("/post/show/{}?edit=true", post_id);
     console_log!("compose request post data");
     let post_detail = use_state(|| None::<PostDetail>);
+    let post_detail_handle = post_detail.clone();
     {
         let post_detail = post_detail.clone();
         use_effect_with_deps(
@@ -87,9 +244,39 @@ fn update_post(
             (),
         );
     }
+    let categories: UseStateHandle<Vec<(i64, String)>> = use_state(|| Vec::with_capacity(0));
+    {
+        let categories = categories.clone();
+        use_effect_with_deps(
+            move |_| {
+                let categories = categories.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Vec<CategoryNode>> = reqwasm::http::Request::get("/category/tree")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    let mut options: Vec<(i64, String)> = Vec::with_capacity(32);
+                    flatten_categories(&response.data.unwrap_or_default(), 0, &mut options);
+                    categories.set(options);
+                });
+                || ()
+            },
+            (),
+        );
+    }
     if post_detail.is_none() {
         return html! {};
     }
+    let on_restore = Callback::from(move |draft: AutosaveDraft| {
+        if let Some(mut detail) = (*post_detail_handle).clone() {
+            detail.title = draft.title;
+            post_detail_handle.set(Some(detail));
+        }
+        set_content(&draft.content);
+    });
     let post_detail = (*post_detail).clone().unwrap();
     if post_detail.id < 1 {
         return html! {
@@ -97,6 +284,12 @@ fn update_post(
         };
     }
     title_onchange.emit(post_detail.title.clone());
+    category_onchange.emit(post_detail.category_id);
+    language_onchange.emit(post_detail.language.clone());
+    // 关联到任意一篇翻译，都会加入同一个分组
+    let translation_of = post_detail.translations.as_ref().and_then(|t| t.first()).map(|t| t.id);
+    translation_onchange.emit(translation_of);
+    visibility_onchange.emit(post_detail.visibility);
     if post_detail.title_image.len() > 0 {
         title_image_onchange.emit(post_detail.title_image.clone());
     }
@@ -114,14 +307,88 @@ fn update_post(
         show_origin_tags(origin_tags);
     }
 
-    let message_ids = vec!["ti", "upload_image", "or", "download_image", "title", "content"];
+    let category_select = {
+        let category_onchange = category_onchange.clone();
+        Callback::from(move |e: Event| {
+            let select = e.target_unchecked_into::<HtmlSelectElement>();
+            category_onchange.emit(select.value().parse::<i64>().ok());
+        })
+    };
+    let category_options = categories
+        .iter()
+        .map(|(id, label)| {
+            html! { <option value={id.to_string()} selected={post_detail.category_id == Some(*id)}>{ label }</option> }
+        })
+        .collect::<Html>();
+
+    let language_input = {
+        let language_onchange = language_onchange.clone();
+        Callback::from(move |e: InputEvent| {
+            let input = e.target_unchecked_into::<HtmlInputElement>();
+            language_onchange.emit(input.value());
+        })
+    };
+    let translation_input = {
+        let translation_onchange = translation_onchange.clone();
+        Callback::from(move |e: InputEvent| {
+            let input = e.target_unchecked_into::<HtmlInputElement>();
+            translation_onchange.emit(input.value().trim().parse::<i64>().ok());
+        })
+    };
+    let visibility_select = {
+        let visibility_onchange = visibility_onchange.clone();
+        Callback::from(move |e: Event| {
+            let select = e.target_unchecked_into::<HtmlSelectElement>();
+            visibility_onchange.emit(Visibility::from_str(&select.value()));
+        })
+    };
+    let password_input = {
+        let password_onchange = password_onchange.clone();
+        Callback::from(move |e: InputEvent| {
+            let input = e.target_unchecked_into::<HtmlInputElement>();
+            password_onchange.emit(input.value());
+        })
+    };
+
+    let message_ids = vec![
+        "ti",
+        "upload_image",
+        "or",
+        "download_image",
+        "title",
+        "content",
+        "category",
+        "no_category",
+        "language",
+        "translation_of",
+        "visibility",
+        "visibility_public",
+        "visibility_unlisted",
+        "visibility_password",
+        "visibility_private",
+        "post_password",
+        "post_password_hint",
+    ];
     let messages = i18n::get(&user_language(), message_ids).unwrap();
+    let visibility_options = [
+        Visibility::Public,
+        Visibility::Unlisted,
+        Visibility::Password,
+        Visibility::Private,
+    ]
+    .iter()
+    .map(|v| {
+        let label = messages.get(format!("visibility_{}", v.as_str()).as_str()).unwrap();
+        html! { <option value={v.as_str()} selected={post_detail.visibility == *v}>{ label }</option> }
+    })
+    .collect::<Html>();
 
     gloo::utils::document().set_title(&post_detail.title);
     html! {
         <>
             { crate::component::blank_node() }
             <div class="container">
+                <AutosaveOffer post_id={*post_id} {on_restore} />
                 <div class="field">
                     <label class="label">{ messages.get("ti").unwrap() }</label>
                 </div>
@@ -161,6 +428,53 @@ fn update_post(
                         <input class="input" type="text" value={post_detail.title.clone()} oninput={oninput}/>
                     </div>
                 </div>
+                <div class="field">
+                    <label class="label">{ messages.get("category").unwrap() }</label>
+                    <div class="control">
+                        <div class="select">
+                            <select onchange={category_select}>
+                                <option value="" selected={post_detail.category_id.is_none()}>{ messages.get("no_category").unwrap() }</option>
+                                { category_options }
+                            </select>
+                        </div>
+                    </div>
+                </div>
+                <div class="field is-horizontal">
+                    <div class="field-body">
+                        <div class="field">
+                            <label class="label">{ messages.get("language").unwrap() }</label>
+                            <div class="control">
+                                <input class="input" type="text" placeholder="zh-CN / en" value={post_detail.language.clone()} oninput={language_input}/>
+                            </div>
+                        </div>
+                        <div class="field">
+                            <label class="label">{ messages.get("translation_of").unwrap() }</label>
+                            <div class="control">
+                                <input class="input" type="text" value={translation_of.map_or(String::new(), |id| id.to_string())} oninput={translation_input}/>
+                            </div>
+                        </div>
+                    </div>
+                </div>
+                <div class="field is-horizontal">
+                    <div class="field-body">
+                        <div class="field">
+                            <label class="label">{ messages.get("visibility").unwrap() }</label>
+                            <div class="control">
+                                <div class="select">
+                                    <select onchange={visibility_select}>
+                                        { visibility_options }
+                                    </select>
+                                </div>
+                            </div>
+                        </div>
+                        <div class="field">
+                            <label class="label">{ messages.get("post_password").unwrap() }</label>
+                            <div class="control">
+                                <input class="input" type="password" autocomplete="new-password" placeholder={ messages.get("post_password_hint").unwrap().to_string() } oninput={password_input}/>
+                            </div>
+                        </div>
+                    </div>
+                </div>
                 <div class="field">
                     <label class="label">{ messages.get("content").unwrap() }</label>
                     <div id="post-content" style="display:none">{&post_detail.content}</div>
@@ -180,14 +494,31 @@ pub struct PostCompose {
     post_id: u64,
     title: String,
     title_image: String,
+    category_id: Option<i64>,
+    language: String,
+    translation_of: Option<i64>,
+    visibility: Visibility,
+    password: String,
     readers: HashMap<String, FileReader>,
+    // 上一次自动保存的内容，没有变化时不再提交
+    last_autosave: Option<PostAutosave>,
+    _autosave_timer: Interval,
 }
 
 pub enum Msg {
     // RequestPostData(u64),
     Ignore,
     UpdateTitle(String),
+    UpdateCategory(Option<i64>),
+    UpdateLanguage(String),
+    UpdateTranslation(Option<i64>),
+    UpdateVisibility(Visibility),
+    UpdatePassword(String),
+    Autosave,
+    Autosaved(Option<u64>),
     UpdatePost,
+    Preview,
+    PreviewLoaded(String),
     LoadedBytes(String, Vec<u8>),
     Files(Event, Vec<web_sys::File>),
     RetrieveRandomTitleImage(MouseEvent),
@@ -201,11 +532,20 @@ impl Component for PostCompose {
     type Properties = Props;
 
     fn create(ctx: &Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        let autosave_timer = Interval::new(AUTOSAVE_INTERVAL, move || link.send_message(Msg::Autosave));
         Self {
             post_id: ctx.props().post_id,
             title: String::new(),
             title_image: String::new(),
+            category_id: None,
+            language: String::new(),
+            translation_of: None,
+            visibility: Visibility::Public,
+            password: String::new(),
             readers: HashMap::default(),
+            last_autosave: None,
+            _autosave_timer: autosave_timer,
         }
     }
 
@@ -216,6 +556,51 @@ impl Component for PostCompose {
             },
             Msg::Ignore => {},
             Msg::UpdateTitle(s) => self.title = s,
+            Msg::UpdateCategory(c) => self.category_id = c,
+            Msg::UpdateLanguage(l) => self.language = l,
+            Msg::UpdateTranslation(t) => self.translation_of = t,
+            Msg::UpdateVisibility(v) => self.visibility = v,
+            Msg::UpdatePassword(p) => self.password = p,
+            Msg::Autosave => {
+                let draft = PostAutosave {
+                    id: self.post_id as i64,
+                    title: self.title.clone(),
+                    content: get_content(),
+                };
+                if draft.content.is_empty() || self.last_autosave.as_ref() == Some(&draft) {
+                    return false;
+                }
+                let payload = serde_json::to_string(&draft).unwrap();
+                self.last_autosave = Some(draft);
+                let callback = ctx.link().callback(Msg::Autosaved);
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<Option<u64>> = reqwasm::http::Request::post("/post/autosave")
+                        .header("Content-Type", "application/json")
+                        .body(payload)
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    if response.status == 0 {
+                        callback.emit(response.data.flatten());
+                    }
+                });
+            },
+            Msg::Autosaved(saved_at) => {
+                // 和预览一样直接写入 DOM，避免重新渲染
+                if let Some(e) = gloo::utils::document().get_element_by_id("autosave-status") {
+                    let text = match saved_at {
+                        Some(secs) => {
+                            let messages = i18n::get(&user_language(), vec!["autosaved"]).unwrap();
+                            format!("{} {}", messages.get("autosaved").unwrap(), format_time(secs))
+                        },
+                        None => String::new(),
+                    };
+                    e.set_text_content(Some(&text));
+                }
+            },
             Msg::UpdatePost => {
                 let selected_tags = get_added_tags();
                 let tags = if selected_tags.is_empty() {
@@ -229,6 +614,11 @@ impl Component for PostCompose {
                     title_image: self.title_image.clone(),
                     content: get_content(),
                     tags,
+                    category_id: self.category_id,
+                    language: self.language.trim().to_string(),
+                    translation_of: self.translation_of,
+                    visibility: self.visibility,
+                    password: Some(self.password.clone()).filter(|p| !p.is_empty()),
                 };
                 console_log!(&post_data.content);
                 let navigator = ctx.link().history().unwrap();
@@ -276,6 +666,31 @@ impl Component for PostCompose {
             //     self.fetch_task = None;
             //     return true;
             // },
+            Msg::Preview => {
+                let payload = serde_json::to_string(&MarkdownPreview { content: get_content() }).unwrap();
+                let callback = ctx.link().callback(Msg::PreviewLoaded);
+                wasm_bindgen_futures::spawn_local(async move {
+                    let response: Response<String> = reqwasm::http::Request::post("/post/preview")
+                        .header("Content-Type", "application/json")
+                        .body(payload)
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    if response.status == 0 {
+                        callback.emit(response.data.unwrap());
+                    }
+                });
+            },
+            Msg::PreviewLoaded(html) => {
+                // 直接写入 DOM，避免重新渲染整个页面而把编辑中的标题覆盖掉
+                if let Some(e) = gloo::utils::document().get_element_by_id("post-preview") {
+                    e.set_inner_html(&html);
+                    e.set_class_name("content box my-6");
+                }
+            },
             Msg::LoadedBytes(file_name, data) => {
                 let info = format!("file_name: {}, data: {:?}", file_name, data);
                 console_log!(&info);
@@ -337,6 +752,11 @@ impl Component for PostCompose {
 
         let title_onchange = ctx.link().callback(move |title: String| Msg::UpdateTitle(title));
         let title_image_onchange = ctx.link().callback(move |s: String| Msg::PayloadCallback(s));
+        let category_onchange = ctx.link().callback(move |c: Option<i64>| Msg::UpdateCategory(c));
+        let language_onchange = ctx.link().callback(move |l: String| Msg::UpdateLanguage(l));
+        let translation_onchange = ctx.link().callback(move |t: Option<i64>| Msg::UpdateTranslation(t));
+        let visibility_onchange = ctx.link().callback(move |v: Visibility| Msg::UpdateVisibility(v));
+        let password_onchange = ctx.link().callback(move |p: String| Msg::UpdatePassword(p));
 
         let onsubmit = ctx.link().callback(|ev: FocusEvent| {
             ev.prevent_default();
@@ -363,7 +783,7 @@ impl Component for PostCompose {
             Msg::UpdateTitle(input.value())
         });
 
-        let message_ids = vec!["edit_post", "labels", "add_label", "update", "cancel"];
+        let message_ids = vec!["edit_post", "labels", "add_label", "update", "preview", "cancel"];
         let messages = i18n::get(&user_language(), message_ids).unwrap();
 
         html! {
@@ -374,7 +794,9 @@ impl Component for PostCompose {
                 <p>{" "}</p>
                 <UpdatePost onsubmit={onsubmit} onchange={onchange} {download_image} oninput={oninput}
                     post_id={post_id as u64} title_onchange={title_onchange.clone()}
-                    title_image_onchange={title_image_onchange.clone()} />
+                    title_image_onchange={title_image_onchange.clone()} category_onchange={category_onchange}
+                    language_onchange={language_onchange} translation_onchange={translation_onchange}
+                    visibility_onchange={visibility_onchange} password_onchange={password_onchange} />
                 <div class="container" id="tagsContainer" style="display:none">
                     <p>{" "}</p>
                     <div class="field">
@@ -390,10 +812,15 @@ impl Component for PostCompose {
                         <div class="control">
                             <button class="button is-link" onclick={ctx.link().callback(|_: MouseEvent| Msg::UpdatePost)}>{ messages.get("update").unwrap() }</button>
                         </div>
+                        <div class="control">
+                            <button class="button is-link is-outlined" onclick={ctx.link().callback(|_: MouseEvent| Msg::Preview)}>{ messages.get("preview").unwrap() }</button>
+                        </div>
                         <div class="control">
                             <button class="button is-link is-light" onclick={ctx.link().callback(|_: MouseEvent| Msg::GoBack)}>{ messages.get("cancel").unwrap() }</button>
                         </div>
+                        <p id="autosave-status" class="help is-align-self-center"></p>
                     </div>
+                    <div id="post-preview"></div>
                 </div>
             </>
         }
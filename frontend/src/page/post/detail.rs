@@ -1,10 +1,11 @@
-use blog_common::dto::post::PostDetail as PostDetailDto;
+use blog_common::dto::post::{PostDetail as PostDetailDto, PostUnlock};
 use blog_common::dto::Response;
+use gloo::storage::{SessionStorage, Storage};
 use gloo::utils::document;
 use time::format_description;
 use time::OffsetDateTime;
 use wasm_bindgen::prelude::*;
-use web_sys::{Element, Node};
+use web_sys::{Element, HtmlInputElement, Node};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
@@ -53,6 +54,247 @@ fn show_tags(post: &mut PostDetailDto) -> Html {
     }
 }
 
+fn show_breadcrumb(post: &PostDetailDto) -> Html {
+    if post.breadcrumb.is_none() {
+        return html! {};
+    }
+    let items = post
+        .breadcrumb
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            html! {
+                <li>
+                    <Link<Route> to={Route::ListPostsByCategory { id: c.id }}>{ &c.name }</Link<Route>>
+                </li>
+            }
+        })
+        .collect::<Html>();
+    html! {
+        <nav class="breadcrumb" aria-label="breadcrumbs">
+            <ul>
+                {items}
+            </ul>
+        </nav>
+    }
+}
+
+fn show_toc(post: &PostDetailDto) -> Html {
+    let toc = match &post.toc {
+        Some(toc) if !toc.is_empty() => toc,
+        _ => return html! {},
+    };
+    let min_level = toc.iter().map(|t| t.level).min().unwrap_or(1);
+    let items = toc
+        .iter()
+        .map(|t| {
+            let style = format!("padding-left:{}em", t.level - min_level);
+            let href = format!("#{}", t.anchor);
+            html! {
+                <li {style}><a {href}>{ &t.text }</a></li>
+            }
+        })
+        .collect::<Html>();
+    let messages = i18n::get(&user_language(), vec!["toc"]).unwrap();
+    html! {
+        <div class="column is-3">
+            <aside class="menu my-6" style="position:sticky;top:1.5rem;max-height:90vh;overflow-y:auto">
+                <p class="menu-label">{ messages.get("toc").unwrap() }</p>
+                <ul class="menu-list">
+                    {items}
+                </ul>
+            </aside>
+        </div>
+    }
+}
+
+fn show_related(post: &PostDetailDto) -> Html {
+    let related = match &post.related {
+        Some(related) if !related.is_empty() => related,
+        _ => return html! {},
+    };
+    let items = related
+        .iter()
+        .map(|p| {
+            html! {
+                <li>
+                    <Link<Route> to={Route::ShowPost { id: p.id as u64 }}>{ &p.title }</Link<Route>>
+                </li>
+            }
+        })
+        .collect::<Html>();
+    let messages = i18n::get(&user_language(), vec!["related_posts"]).unwrap();
+    html! {
+        <div class="box content">
+            <p class="has-text-weight-bold">{ messages.get("related_posts").unwrap() }</p>
+            <ul>
+                {items}
+            </ul>
+        </div>
+    }
+}
+
+fn show_translations(post: &PostDetailDto) -> Html {
+    let translations = match &post.translations {
+        Some(t) if !t.is_empty() => t,
+        _ => return html! {},
+    };
+    let current = if post.language.is_empty() {
+        html! {}
+    } else {
+        html! { <button class="button is-static">{ &post.language }</button> }
+    };
+    let items = translations
+        .iter()
+        .map(|t| {
+            html! {
+                <Link<Route> classes={classes!("button")} to={Route::ShowPost { id: t.id as u64 }}>
+                    <span title={t.title.clone()}>{ &t.language }</span>
+                </Link<Route>>
+            }
+        })
+        .collect::<Html>();
+    html! {
+        <div class="buttons has-addons are-small">
+            <span class="icon is-small mr-2"><i class="fas fa-language"></i></span>
+            { current }
+            { items }
+        </div>
+    }
+}
+
+// 单页应用没有服务端渲染，在 head 里动态加上 hreflang，方便搜索引擎识别翻译
+fn update_alternate_links(post: &PostDetailDto) {
+    let document = document();
+    while let Ok(Some(link)) = document.query_selector("link[hreflang]") {
+        link.remove();
+    }
+    let translations = match &post.translations {
+        Some(t) => t,
+        None => return,
+    };
+    let head = match document.query_selector("head") {
+        Ok(Some(h)) => h,
+        _ => return,
+    };
+    // hreflang 要求包含当前页面自己
+    let current = (post.id, &post.language);
+    let links = std::iter::once(current)
+        .filter(|_| !translations.is_empty())
+        .chain(translations.iter().map(|t| (t.id, &t.language)));
+    for (id, language) in links.filter(|(_, l)| !l.is_empty()) {
+        if let Ok(link) = document.create_element("link") {
+            let _ = link.set_attribute("rel", "alternate");
+            let _ = link.set_attribute("hreflang", language);
+            let _ = link.set_attribute("href", &format!("/posts/{}", id));
+            let _ = head.append_child(&link);
+        }
+    }
+}
+
+// action 为 pin 或 feature，切换置顶/推荐状态
+fn toggle_flag(post_detail: &UseStateHandle<PostDetailDto>, action: &'static str) -> Callback<MouseEvent> {
+    let post_detail = post_detail.clone();
+    Callback::from(move |_: MouseEvent| {
+        let post_detail = post_detail.clone();
+        let mut post = (*post_detail).clone();
+        let current = if action == "pin" { post.pinned } else { post.featured };
+        wasm_bindgen_futures::spawn_local(async move {
+            let url = format!("/post/{}/{}/{}", action, post.id, !current);
+            let response: Response<bool> = reqwasm::http::Request::post(&url)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            if let Some(flag) = response.data {
+                if action == "pin" {
+                    post.pinned = flag;
+                } else {
+                    post.featured = flag;
+                }
+                post_detail.set(post);
+            }
+        });
+    })
+}
+
+// 输入密码后拿到的凭证只保存在当前标签页
+fn grant_key(post_id: u64) -> String {
+    format!("post_grant_{}", post_id)
+}
+
+#[derive(Clone, Debug, PartialEq, Properties)]
+pub struct UnlockFormProps {
+    pub post_id: u64,
+    pub on_unlocked: Callback<()>,
+}
+
+#[function_component(UnlockForm)]
+fn unlock_form(UnlockFormProps { post_id, on_unlocked }: &UnlockFormProps) -> Html {
+    let post_id = *post_id;
+    let password = use_state(String::new);
+    let error = use_state(|| None::<String>);
+    let oninput = {
+        let password = password.clone();
+        Callback::from(move |e: InputEvent| {
+            let input = e.target_unchecked_into::<HtmlInputElement>();
+            password.set(input.value());
+        })
+    };
+    let onclick = {
+        let password = password.clone();
+        let error = error.clone();
+        let on_unlocked = on_unlocked.clone();
+        Callback::from(move |_: MouseEvent| {
+            let payload = serde_json::to_string(&PostUnlock {
+                password: (*password).clone(),
+            })
+            .unwrap();
+            let error = error.clone();
+            let on_unlocked = on_unlocked.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let response: Response<String> = reqwasm::http::Request::post(&format!("/post/unlock/{}", post_id))
+                    .header("Content-Type", "application/json")
+                    .body(payload)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                match response.data {
+                    Some(grant) => {
+                        let _ = SessionStorage::set(grant_key(post_id), grant);
+                        on_unlocked.emit(());
+                    },
+                    None => error.set(response.error.map(|e| e.detail)),
+                }
+            });
+        })
+    };
+    let messages = i18n::get(&user_language(), vec!["post_locked", "post_password", "unlock"]).unwrap();
+    html! {
+        <div class="box">
+            <p class="block">
+                <span class="icon"><i class="fas fa-lock"></i></span>
+                { messages.get("post_locked").unwrap() }
+            </p>
+            <div class="field has-addons">
+                <div class="control">
+                    <input class="input" type="password" placeholder={ messages.get("post_password").unwrap().to_string() } {oninput}/>
+                </div>
+                <div class="control">
+                    <button class="button is-info" {onclick}>{ messages.get("unlock").unwrap() }</button>
+                </div>
+            </div>
+            <p class="help is-danger">{ (*error).clone().unwrap_or_default() }</p>
+        </div>
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub struct ShowDetailProps {
     pub post_id: u64,
@@ -60,13 +302,18 @@ pub struct ShowDetailProps {
 
 #[function_component(ShowDetail)]
 fn app(ShowDetailProps { post_id }: &ShowDetailProps) -> Html {
-    let detail_url = format!("/post/show/{}", post_id);
     let post_detail = use_state(|| PostDetailDto::default());
+    // 解锁成功后加一，重新加载博客内容
+    let reload = use_state(|| 0u32);
     {
         let post_detail = post_detail.clone();
         use_effect_with_deps(
-            move |_| {
+            move |(post_id, _)| {
                 let post_detail = post_detail.clone();
+                let detail_url = match SessionStorage::get::<String>(grant_key(*post_id)) {
+                    Ok(grant) => format!("/post/show/{}?grant={}", post_id, grant),
+                    Err(_) => format!("/post/show/{}", post_id),
+                };
                 wasm_bindgen_futures::spawn_local(async move {
                     let response: Response<PostDetailDto> = reqwasm::http::Request::get(&detail_url)
                         .send()
@@ -79,21 +326,33 @@ fn app(ShowDetailProps { post_id }: &ShowDetailProps) -> Html {
                 });
                 || ()
             },
-            (),
+            (*post_id, *reload),
         );
     }
     let mut post = (*post_detail).clone();
+    let unlock_form = if post.locked {
+        let reload = reload.clone();
+        let on_unlocked = Callback::from(move |_| reload.set(*reload + 1));
+        html! { <UnlockForm post_id={*post_id} {on_unlocked} /> }
+    } else {
+        html! {}
+    };
     let title_image = post.title_image.to_string();
     let datetime = OffsetDateTime::from_unix_timestamp(post.created_at as i64).unwrap();
     let format = format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]").unwrap();
     let post_time = datetime.format(&format).expect("Failed to format the date");
     gloo::utils::document().set_title(&post.title);
+    update_alternate_links(&post);
+    let messages = i18n::get(&user_language(), vec!["pin", "unpin", "feature", "unfeature"]).unwrap();
+    let pin_text = messages.get(if post.pinned { "unpin" } else { "pin" }).unwrap();
+    let feature_text = messages.get(if post.featured { "unfeature" } else { "feature" }).unwrap();
     html! {
         <>
             <section class="hero is-large is-light has-background">
                 <img src={ title_image } class="hero-background is-transparent" alt=""/>
                 <div class="hero-body">
                     <div class="container">
+                        {show_breadcrumb(&post)}
                         <p class="title is-1">
                             // { &post.title }
                             { &post.title }
@@ -102,20 +361,38 @@ fn app(ShowDetailProps { post_id }: &ShowDetailProps) -> Html {
                             { &post_time }
                         </p>
                         {show_tags(&mut post)}
+                        {show_translations(&post)}
+                        <div class="buttons are-small">
+                            <button class="button is-light" onclick={toggle_flag(&post_detail, "pin")}>
+                                <span class="icon"><i class="fas fa-thumbtack"></i></span>
+                                <span>{ pin_text }</span>
+                            </button>
+                            <button class="button is-light" onclick={toggle_flag(&post_detail, "feature")}>
+                                <span class="icon"><i class="far fa-star"></i></span>
+                                <span>{ feature_text }</span>
+                            </button>
+                        </div>
                     </div>
                 </div>
             </section>
             <div class="section container">
-                <article class="media block box my-6">
-                    <div class="media-content">
-                        { show_content(&post.content) }
-                        // <div class="content">
-                            // <p class="is-family-secondary">
-                            //     { show_content(&post.content) }
-                            // </p>
-                        // </div>
+                <div class="columns">
+                    <div class="column">
+                        <article class="media block box my-6">
+                            <div class="media-content">
+                                { unlock_form }
+                                { show_content(&post.content) }
+                                // <div class="content">
+                                    // <p class="is-family-secondary">
+                                    //     { show_content(&post.content) }
+                                    // </p>
+                                // </div>
+                            </div>
+                        </article>
+                        {show_related(&post)}
                     </div>
-                </article>
+                    {show_toc(&post)}
+                </div>
             </div>
         </>
     }
@@ -1,6 +1,6 @@
 use yew::prelude::*;
 
-use crate::component::PostsListComponent;
+use crate::component::{CategoryTreeComponent, FeaturedCarouselComponent, PostsListComponent};
 
 pub struct PostsList {}
 
@@ -17,13 +17,21 @@ impl Component for PostsList {
 
         html! {
             <>
+                <FeaturedCarouselComponent />
                 <div class="columns">
                     <div class="column is-12">
                         <h1 class="title is-1">{ "博客列表/Posts list" }</h1>
                         <h2 class="subtitle">{ "All of your quality writing in one place" }</h2>
                     </div>
                 </div>
-                <PostsListComponent request_uri={"/post/list/".to_string()} />
+                <div class="columns">
+                    <div class="column is-3">
+                        <CategoryTreeComponent />
+                    </div>
+                    <div class="column">
+                        <PostsListComponent request_uri={"/post/list/".to_string()} />
+                    </div>
+                </div>
             </>
         }
     }
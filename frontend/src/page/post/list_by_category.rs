@@ -0,0 +1,69 @@
+use wasm_bindgen::prelude::*;
+use yew::prelude::*;
+
+use crate::component::{CategoryTreeComponent, PostsListComponent};
+use crate::i18n;
+
+#[wasm_bindgen(module = "/asset/show.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = userLanguage)]
+    fn user_language() -> String;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Properties)]
+pub struct Props {
+    pub id: i64,
+}
+
+pub struct PostsListByCategory {
+    id: i64,
+}
+
+impl Component for PostsListByCategory {
+    type Message = ();
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self { id: ctx.props().id }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let changed = self.id != ctx.props().id;
+        if changed {
+            weblog::console_log!("changed to load");
+            self.id = ctx.props().id;
+        }
+        changed
+    }
+
+    fn view(&self, _ctx: &Context<Self>) -> Html {
+        let Self { id } = self;
+        let mut request_uri = String::with_capacity(32);
+        request_uri.push_str("/post/category/");
+        request_uri.push_str(id.to_string().as_str());
+        request_uri.push_str("/");
+
+        let messages = i18n::get(&user_language(), vec!["categories"]).unwrap();
+        let title = messages.get("categories").unwrap();
+        gloo::utils::document().set_title(title);
+
+        html! {
+            <>
+                <div class="columns">
+                    <div class="column is-10">
+                        <h1 class="title is-1">{ title }</h1>
+                        <h2 class="subtitle">{ " " }</h2>
+                    </div>
+                </div>
+                <div class="columns">
+                    <div class="column is-3">
+                        <CategoryTreeComponent active={Some(*id)} />
+                    </div>
+                    <div class="column">
+                        <PostsListComponent {request_uri} />
+                    </div>
+                </div>
+            </>
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use wasm_bindgen::prelude::*;
+use yew::prelude::*;
+
+use crate::component::{ArchiveTreeComponent, PostsListComponent};
+use crate::i18n;
+
+#[wasm_bindgen(module = "/asset/show.js")]
+extern "C" {
+    #[wasm_bindgen(js_name = userLanguage)]
+    fn user_language() -> String;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Properties)]
+pub struct Props {
+    // 为 0 时只显示归档目录
+    pub year: i32,
+    pub month: u8,
+}
+
+pub struct PostsListByMonth {
+    year: i32,
+    month: u8,
+}
+
+impl Component for PostsListByMonth {
+    type Message = ();
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self {
+            year: ctx.props().year,
+            month: ctx.props().month,
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let changed = self.year != ctx.props().year || self.month != ctx.props().month;
+        if changed {
+            weblog::console_log!("changed to load");
+            self.year = ctx.props().year;
+            self.month = ctx.props().month;
+        }
+        changed
+    }
+
+    fn view(&self, _ctx: &Context<Self>) -> Html {
+        let Self { year, month } = self;
+        let messages = i18n::get(&user_language(), vec!["archive"]).unwrap();
+        let title = messages.get("archive").unwrap();
+        gloo::utils::document().set_title(title);
+
+        let subtitle = if *year == 0 {
+            String::from(" ")
+        } else if *month == 0 {
+            year.to_string()
+        } else {
+            format!("{}-{:02}", year, month)
+        };
+        let posts = if *year == 0 {
+            html! {}
+        } else {
+            let request_uri = format!("/post/archive/{}/{}/", year, month);
+            html! { <PostsListComponent {request_uri} /> }
+        };
+
+        html! {
+            <>
+                <div class="columns">
+                    <div class="column is-10">
+                        <h1 class="title is-1">{ title }</h1>
+                        <h2 class="subtitle">{ subtitle }</h2>
+                    </div>
+                </div>
+                <div class="columns">
+                    <div class="column is-3">
+                        <ArchiveTreeComponent year={*year} month={*month} />
+                    </div>
+                    <div class="column">
+                        { posts }
+                    </div>
+                </div>
+            </>
+        }
+    }
+}
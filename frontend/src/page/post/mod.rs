@@ -1,9 +1,13 @@
 mod compose;
 mod detail;
 mod list;
+mod list_by_category;
+mod list_by_month;
 mod list_by_tag;
 
 pub use compose::PostCompose;
 pub use detail::PostDetail;
 pub use list::PostsList;
+pub use list_by_category::PostsListByCategory;
+pub use list_by_month::PostsListByMonth;
 pub use list_by_tag::PostsListByTag;
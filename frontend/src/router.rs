@@ -1,7 +1,7 @@
 use yew::prelude::*;
 use yew_router::prelude::*;
 
-use crate::page::post::{PostCompose, PostDetail, PostsList, PostsListByTag};
+use crate::page::post::{PostCompose, PostDetail, PostsList, PostsListByCategory, PostsListByMonth, PostsListByTag};
 use crate::page::tag::TagsList;
 
 #[derive(Routable, PartialEq, Clone, Debug)]
@@ -12,6 +12,12 @@ pub enum Route {
     ComposePost { id: u64 },
     #[at("/posts/tag/:tag_name")]
     ListPostsByTag { tag_name: String },
+    #[at("/posts/category/:id")]
+    ListPostsByCategory { id: i64 },
+    #[at("/archive")]
+    Archive,
+    #[at("/archive/:year/:month")]
+    ListPostsByMonth { year: i32, month: u8 },
     #[at("/tags")]
     Tags,
     #[at("/word")]
@@ -107,6 +113,15 @@ pub fn switch(routes: &Route) -> Html {
         Route::ListPostsByTag { tag_name } => {
             html! { <PostsListByTag tag_name={String::from(tag_name)} /> }
         },
+        Route::ListPostsByCategory { id } => {
+            html! { <PostsListByCategory id={*id} /> }
+        },
+        Route::Archive => {
+            html! { <PostsListByMonth year={0} month={0} /> }
+        },
+        Route::ListPostsByMonth { year, month } => {
+            html! { <PostsListByMonth year={*year} month={*month} /> }
+        },
         Route::ListPosts => {
             html! { <PostsList /> }
         },